@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    input::{MemberType, OsmRelation},
+    relation::RelationMedium,
+    types::medium::{AeroCategory, AirAttributes, BoundingBox, Medium, MediumType, Position},
+};
+
+/// Length in meters from values like "2500" or "2500 m".
+pub(crate) fn parse_length(v: &str) -> Option<f64> {
+    v.trim().trim_end_matches('m').trim().parse().ok()
+}
+
+/// An aerodrome multipolygon with the attributes the tag mapping gave it, to be
+/// joined from its outer ways.
+pub fn aerodrome_relation(
+    relation: &impl OsmRelation,
+    attributes: AirAttributes,
+) -> RelationMedium {
+    let name = relation
+        .tags()
+        .find(|(k, _)| *k == "name")
+        .map(|(_, v)| String::from(v));
+    let ways = relation
+        .members()
+        .filter(|m| m.member_type == MemberType::Way)
+        .filter(|m| matches!(m.role, "outer" | ""))
+        .map(|m| m.member_id)
+        .collect();
+    RelationMedium {
+        osm_id: relation.id(),
+        name,
+        medium_type: MediumType::Airway(attributes),
+        ways,
+        tags: BTreeMap::new(),
+    }
+}
+
+/// Sets the aerodrome of every airside Medium that lies inside an aerodrome outline,
+/// and fills in measured lengths where none was tagged.
+///
+/// A Medium belongs to an aerodrome when its middle position is inside the outline;
+/// when outlines overlap the smallest one wins. Returns the number of airside Mediums
+/// left without an aerodrome.
+pub fn link_aerodromes(mediums: &mut [Medium]) -> usize {
+    let aerodromes: Vec<(i64, BoundingBox, f64, &[Position])> = mediums
+        .iter()
+        .filter(|m| is_category(m, AeroCategory::Aerodrome))
+        .filter(|m| m.medium_positions.len() >= 4)
+        .filter_map(|m| {
+            let outline = m.medium_positions.as_slice();
+            let bbox = bounding_box(outline);
+            let area = (bbox.max_lon - bbox.min_lon) * (bbox.max_lat - bbox.min_lat);
+            m.osm_id.map(|id| (id, bbox, area, outline))
+        })
+        .collect();
+    let links: Vec<Option<i64>> = mediums
+        .iter()
+        .map(|m| {
+            if !matches!(m.medium_type, MediumType::Airway(_))
+                || is_category(m, AeroCategory::Aerodrome)
+            {
+                return None;
+            }
+            let middle = m.medium_positions.get(m.medium_positions.len() / 2)?;
+            aerodromes
+                .iter()
+                .filter(|(_, bbox, _, outline)| bbox.contains(middle) && contains(outline, middle))
+                .min_by(|a, b| a.2.total_cmp(&b.2))
+                .map(|(id, ..)| *id)
+        })
+        .collect();
+
+    let mut unlinked = 0;
+    for (m, link) in mediums.iter_mut().zip(links) {
+        let measured = m.length();
+        if let MediumType::Airway(air) = &mut m.medium_type {
+            if air.category == AeroCategory::Aerodrome {
+                continue;
+            }
+            if air.length.is_none() && measured > 0.0 {
+                air.length = Some(measured);
+            }
+            air.aerodrome = link;
+            if link.is_none() {
+                unlinked += 1;
+            }
+        }
+    }
+    unlinked
+}
+
+fn is_category(medium: &Medium, category: AeroCategory) -> bool {
+    matches!(&medium.medium_type, MediumType::Airway(air) if air.category == category)
+}
+
+fn bounding_box(positions: &[Position]) -> BoundingBox {
+    positions.iter().fold(
+        BoundingBox::new(
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |b, p| {
+            BoundingBox::new(
+                b.min_lon.min(p.longitude),
+                b.min_lat.min(p.latitude),
+                b.max_lon.max(p.longitude),
+                b.max_lat.max(p.latitude),
+            )
+        },
+    )
+}
+
+/// Even-odd point in polygon test. The outline is treated as closed, so an
+/// aerodrome joined from several rings is only approximate near the joins.
+fn contains(outline: &[Position], pos: &Position) -> bool {
+    let mut inside = false;
+    let mut j = outline.len() - 1;
+    for i in 0..outline.len() {
+        let (a, b) = (&outline[i], &outline[j]);
+        if (a.latitude > pos.latitude) != (b.latitude > pos.latitude)
+            && pos.longitude
+                < a.longitude
+                    + (pos.latitude - a.latitude) * (b.longitude - a.longitude)
+                        / (b.latitude - a.latitude)
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
@@ -0,0 +1,543 @@
+use std::{fs, path::Path};
+
+use rayon::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    error::{KovachsError, Result},
+    types::medium::{BoundingBox, Medium, Position},
+};
+
+/// What happens to Mediums that cross the edge of an [`Area`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AreaMode {
+    /// Keep every Medium that touches the area as it is.
+    #[default]
+    Whole,
+    /// Cut Mediums at the boundary, keeping the pieces inside.
+    Clip,
+}
+
+/// A bounding box or polygon to restrict a dataset to.
+///
+/// Rings are combined with the even-odd rule, so holes only need to lie inside
+/// their outer ring.
+#[derive(Debug, Clone)]
+pub struct Area {
+    bbox: BoundingBox,
+    edges: Vec<[[f64; 2]; 2]>,
+    /// Indexes into `edges` for each band of latitude the area is cut into.
+    bands: Vec<Vec<usize>>,
+    band_height: f64,
+}
+
+impl Area {
+    pub fn from_bbox(bbox: BoundingBox) -> Area {
+        let (w, s, e, n) = (bbox.min_lon, bbox.min_lat, bbox.max_lon, bbox.max_lat);
+        Area::from_rings(vec![vec![[w, s], [e, s], [e, n], [w, n]]])
+    }
+
+    /// Builds an area from rings of `[longitude, latitude]` points, which may or
+    /// may not repeat their first point at the end.
+    pub fn from_rings(rings: Vec<Vec<[f64; 2]>>) -> Area {
+        let mut edges = Vec::new();
+        for ring in rings.iter() {
+            for (i, a) in ring.iter().enumerate() {
+                let b = ring[(i + 1) % ring.len()];
+                if *a != b {
+                    edges.push([*a, b]);
+                }
+            }
+        }
+        let bbox = edges.iter().flatten().fold(
+            BoundingBox::new(
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |b, p| {
+                BoundingBox::new(
+                    b.min_lon.min(p[0]),
+                    b.min_lat.min(p[1]),
+                    b.max_lon.max(p[0]),
+                    b.max_lat.max(p[1]),
+                )
+            },
+        );
+        let band_count = (edges.len() / 4).clamp(1, 1024);
+        let band_height =
+            ((bbox.max_lat - bbox.min_lat) / band_count as f64).max(f64::MIN_POSITIVE);
+        let mut area = Area {
+            bbox,
+            edges,
+            bands: vec![Vec::new(); band_count],
+            band_height,
+        };
+        for (i, [a, b]) in area.edges.iter().enumerate() {
+            for band in area.band(a[1].min(b[1]))..=area.band(a[1].max(b[1])) {
+                area.bands[band].push(i);
+            }
+        }
+        area
+    }
+
+    /// Reads an Osmosis `*.poly` file, or a GeoJSON Polygon or MultiPolygon, on its
+    /// own or in a Feature or FeatureCollection.
+    pub fn read(path: &Path) -> Result<Area> {
+        let text = fs::read_to_string(path)?;
+        let rings = if path.extension().is_some_and(|e| e == "poly") {
+            poly_rings(&text)?
+        } else {
+            let value = serde_json::from_str(&text)?;
+            let mut rings = Vec::new();
+            geojson_rings(&value, &mut rings)?;
+            rings
+        };
+        if rings.is_empty() {
+            return Err(invalid("no polygon found"));
+        }
+        Ok(Area::from_rings(rings))
+    }
+
+    pub fn bbox(&self) -> BoundingBox {
+        self.bbox
+    }
+
+    pub fn contains(&self, position: &Position) -> bool {
+        let (x, y) = (position.longitude, position.latitude);
+        if !self.bbox.contains(position) {
+            return false;
+        }
+        let mut inside = false;
+        for &i in self.bands[self.band(y)].iter() {
+            let [a, b] = self.edges[i];
+            if (a[1] > y) != (b[1] > y) && x < a[0] + (y - a[1]) / (b[1] - a[1]) * (b[0] - a[0]) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Drops the Mediums outside the area and, when clipping, cuts the rest at its
+    /// boundary.
+    ///
+    /// A clipped Medium becomes one Medium per piece inside, all with its
+    /// `osm_id`. The points where a piece meets the boundary get new negative node
+    /// refs below any ref already in `mediums`, such as those of an earlier clip or
+    /// of unsaved JOSM nodes, so refs and positions stay aligned. Mediums
+    /// whose refs and positions were not aligned to begin with are kept whole.
+    pub fn restrict(&self, mediums: &mut Vec<Medium>, mode: AreaMode) {
+        let mut boundary_ref = mediums
+            .iter()
+            .flat_map(|m| m.osm_node_refs.iter().copied())
+            .min()
+            .map_or(0, |r| r.min(0));
+        let pieces: Vec<Vec<Piece>> = std::mem::take(mediums)
+            .into_par_iter()
+            .map(|m| match mode {
+                AreaMode::Clip if m.osm_node_refs.len() == m.medium_positions.len() => self.clip(m),
+                _ if self.touches(&m.medium_positions) => vec![Piece::Whole(m)],
+                _ => Vec::new(),
+            })
+            .collect();
+        for piece in pieces.into_iter().flatten() {
+            mediums.push(match piece {
+                Piece::Whole(m) => m,
+                Piece::Clipped(mut m, nodes) => {
+                    for (r, p) in nodes {
+                        m.osm_node_refs.push(r.unwrap_or_else(|| {
+                            boundary_ref -= 1;
+                            boundary_ref
+                        }));
+                        m.medium_positions.push(p);
+                    }
+                    m
+                }
+            });
+        }
+    }
+
+    /// Whether a line has a position inside the area or crosses its boundary.
+    fn touches(&self, positions: &[Position]) -> bool {
+        if positions.iter().any(|p| self.contains(p)) {
+            return true;
+        }
+        positions
+            .windows(2)
+            .any(|w| !self.crossings(&w[0], &w[1]).is_empty())
+    }
+
+    /// The pieces of a Medium inside the area; `None` refs mark boundary points.
+    fn clip(&self, medium: Medium) -> Vec<Piece> {
+        let positions = &medium.medium_positions;
+        if !positions
+            .windows(2)
+            .any(|w| !self.crossings(&w[0], &w[1]).is_empty())
+        {
+            return match positions.first() {
+                Some(p) if self.contains(p) => vec![Piece::Whole(medium)],
+                _ => Vec::new(),
+            };
+        }
+        let mut runs: Vec<Vec<(Option<i64>, Position)>> = Vec::new();
+        let mut run = Vec::new();
+        for (i, w) in positions.windows(2).enumerate() {
+            let (a, b) = (&w[0], &w[1]);
+            let mut ts = vec![0.0];
+            ts.extend(self.crossings(a, b));
+            ts.push(1.0);
+            ts.sort_by(f64::total_cmp);
+            for t in ts.windows(2) {
+                let (t0, t1) = (t[0], t[1]);
+                if t1 <= t0 {
+                    continue;
+                }
+                if !self.contains(&lerp(a, b, (t0 + t1) / 2.0)) {
+                    if run.len() > 1 {
+                        runs.push(std::mem::take(&mut run));
+                    }
+                    run.clear();
+                    continue;
+                }
+                if run.is_empty() {
+                    run.push(if t0 == 0.0 {
+                        (Some(medium.osm_node_refs[i]), a.clone())
+                    } else {
+                        (None, lerp(a, b, t0))
+                    });
+                }
+                run.push(if t1 == 1.0 {
+                    (Some(medium.osm_node_refs[i + 1]), b.clone())
+                } else {
+                    (None, lerp(a, b, t1))
+                });
+            }
+        }
+        if run.len() > 1 {
+            runs.push(run);
+        }
+        // A closed way cut open shouldn't also be split where it starts.
+        let refs = &medium.osm_node_refs;
+        let joined = |nodes: Option<&(Option<i64>, Position)>| {
+            nodes.is_some_and(|n| n.0.is_some() && n.0 == refs.first().copied())
+        };
+        if runs.len() > 1
+            && refs.first() == refs.last()
+            && joined(runs[0].first())
+            && runs.last().is_some_and(|run| joined(run.last()))
+        {
+            let first = runs.remove(0);
+            if let Some(last) = runs.last_mut() {
+                last.extend(first.into_iter().skip(1));
+            }
+        }
+        runs.into_iter()
+            .map(|nodes| {
+                let mut piece = medium.clone();
+                piece.osm_node_refs = Vec::new();
+                piece.medium_positions = Vec::new();
+                Piece::Clipped(piece, nodes)
+            })
+            .collect()
+    }
+
+    /// Where along the segment from `a` to `b`, from 0 to 1, it crosses an edge.
+    fn crossings(&self, a: &Position, b: &Position) -> Vec<f64> {
+        let (p, q) = ([a.longitude, a.latitude], [b.longitude, b.latitude]);
+        let mut ts = Vec::new();
+        if p[0].max(q[0]) < self.bbox.min_lon
+            || p[0].min(q[0]) > self.bbox.max_lon
+            || p[1].max(q[1]) < self.bbox.min_lat
+            || p[1].min(q[1]) > self.bbox.max_lat
+        {
+            return ts;
+        }
+        let d = [q[0] - p[0], q[1] - p[1]];
+        let mut seen = Vec::new();
+        for band in self.band(p[1].min(q[1]))..=self.band(p[1].max(q[1])) {
+            for &i in self.bands[band].iter() {
+                if seen.contains(&i) {
+                    continue;
+                }
+                seen.push(i);
+                let [e0, e1] = self.edges[i];
+                let e = [e1[0] - e0[0], e1[1] - e0[1]];
+                let denominator = d[0] * e[1] - d[1] * e[0];
+                if denominator == 0.0 {
+                    continue;
+                }
+                let f = [e0[0] - p[0], e0[1] - p[1]];
+                let t = (f[0] * e[1] - f[1] * e[0]) / denominator;
+                let u = (f[0] * d[1] - f[1] * d[0]) / denominator;
+                if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+                    ts.push(t);
+                }
+            }
+        }
+        ts
+    }
+
+    fn band(&self, latitude: f64) -> usize {
+        let band = ((latitude - self.bbox.min_lat) / self.band_height).floor();
+        (band.max(0.0) as usize).min(self.bands.len() - 1)
+    }
+}
+
+enum Piece {
+    Whole(Medium),
+    Clipped(Medium, Vec<(Option<i64>, Position)>),
+}
+
+fn lerp(a: &Position, b: &Position, t: f64) -> Position {
+    Position {
+        longitude: a.longitude + t * (b.longitude - a.longitude),
+        latitude: a.latitude + t * (b.latitude - a.latitude),
+    }
+}
+
+/// The rings of an Osmosis polygon file: a name line, then sections of
+/// "longitude latitude" lines each closed by END, with a final END. Sections
+/// whose name starts with `!` are holes.
+fn poly_rings(text: &str) -> Result<Vec<Vec<[f64; 2]>>> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .skip(1);
+    let mut rings = Vec::new();
+    while let Some(section) = lines.next() {
+        if section == "END" {
+            return Ok(rings);
+        }
+        let mut ring = Vec::new();
+        loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid(format!("section {section} has no END")))?;
+            if line == "END" {
+                break;
+            }
+            let mut values = line.split_whitespace().map(str::parse::<f64>);
+            match (values.next(), values.next()) {
+                (Some(Ok(lon)), Some(Ok(lat))) => ring.push([lon, lat]),
+                _ => return Err(invalid(format!("bad coordinates {line:?}"))),
+            }
+        }
+        rings.push(ring);
+    }
+    Err(invalid("missing final END"))
+}
+
+fn geojson_rings(value: &Value, rings: &mut Vec<Vec<[f64; 2]>>) -> Result<()> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in value["features"].as_array().into_iter().flatten() {
+                geojson_rings(feature, rings)?;
+            }
+        }
+        Some("Feature") => geojson_rings(&value["geometry"], rings)?,
+        Some("Polygon") => polygon_rings(&value["coordinates"], rings)?,
+        Some("MultiPolygon") => {
+            for polygon in value["coordinates"].as_array().into_iter().flatten() {
+                polygon_rings(polygon, rings)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// The rings of GeoJSON Polygon coordinates, ignoring any altitude.
+fn polygon_rings(coordinates: &Value, rings: &mut Vec<Vec<[f64; 2]>>) -> Result<()> {
+    for ring in coordinates.as_array().into_iter().flatten() {
+        let points = ring.as_array().into_iter().flatten().map(|point| {
+            match (point[0].as_f64(), point[1].as_f64()) {
+                (Some(lon), Some(lat)) => Ok([lon, lat]),
+                _ => Err(invalid(format!("bad position {point}"))),
+            }
+        });
+        rings.push(points.collect::<Result<_>>()?);
+    }
+    Ok(())
+}
+
+fn invalid(message: impl Into<String>) -> KovachsError {
+    KovachsError::Area(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The unit square.
+    fn square() -> Area {
+        Area::from_bbox(BoundingBox::new(0.0, 0.0, 1.0, 1.0))
+    }
+
+    fn position(longitude: f64, latitude: f64) -> Position {
+        Position {
+            longitude,
+            latitude,
+        }
+    }
+
+    fn line(osm_id: i64, refs: &[i64], coordinates: &[(f64, f64)]) -> Medium {
+        let mut medium = Medium::new();
+        medium.osm_id = Some(osm_id);
+        medium.osm_node_refs = refs.to_vec();
+        medium.medium_positions = coordinates.iter().map(|&(x, y)| position(x, y)).collect();
+        medium
+    }
+
+    /// osm_id, refs and coordinates of a Medium.
+    type Summary = (Option<i64>, Vec<i64>, Vec<(f64, f64)>);
+
+    fn pieces(mediums: &[Medium]) -> Vec<Summary> {
+        mediums
+            .iter()
+            .map(|m| {
+                let coordinates = m
+                    .medium_positions
+                    .iter()
+                    .map(|p| (p.longitude, p.latitude))
+                    .collect();
+                (m.osm_id, m.osm_node_refs.clone(), coordinates)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn contains_uses_even_odd_rings() {
+        let area = Area::from_rings(vec![
+            vec![[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0], [0.0, 0.0]],
+            vec![[1.0, 1.0], [3.0, 1.0], [3.0, 3.0], [1.0, 3.0]],
+        ]);
+        assert!(area.contains(&position(0.5, 0.5)));
+        assert!(!area.contains(&position(2.0, 2.0)));
+        assert!(area.contains(&position(3.5, 2.0)));
+        assert!(!area.contains(&position(5.0, 2.0)));
+        assert!(!area.contains(&position(2.0, -0.5)));
+    }
+
+    #[test]
+    fn whole_keeps_mediums_touching_the_area() {
+        let mut mediums = vec![
+            line(1, &[1, 2], &[(0.5, 0.5), (2.0, 0.5)]),
+            // Crosses the square without a node inside it.
+            line(2, &[3, 4], &[(-1.0, 0.5), (2.0, 0.5)]),
+            line(3, &[5, 6], &[(2.0, 2.0), (3.0, 3.0)]),
+        ];
+        square().restrict(&mut mediums, AreaMode::Whole);
+        assert_eq!(
+            pieces(&mediums),
+            [
+                (Some(1), vec![1, 2], vec![(0.5, 0.5), (2.0, 0.5)]),
+                (Some(2), vec![3, 4], vec![(-1.0, 0.5), (2.0, 0.5)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn clip_cuts_at_the_boundary() {
+        let mut mediums = vec![
+            line(1, &[1, 2, 3], &[(-1.0, 0.5), (0.5, 0.5), (2.0, 0.5)]),
+            line(2, &[4, 5], &[(0.25, 0.25), (0.75, 0.75)]),
+            line(3, &[6, 7], &[(2.0, 2.0), (3.0, 3.0)]),
+        ];
+        square().restrict(&mut mediums, AreaMode::Clip);
+        assert_eq!(
+            pieces(&mediums),
+            [
+                (
+                    Some(1),
+                    vec![-1, 2, -2],
+                    vec![(0.0, 0.5), (0.5, 0.5), (1.0, 0.5)]
+                ),
+                (Some(2), vec![4, 5], vec![(0.25, 0.25), (0.75, 0.75)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn clip_splits_a_medium_that_leaves_and_comes_back() {
+        let mut mediums = vec![line(
+            1,
+            &[1, 2, 3, 4],
+            &[(0.5, 0.25), (1.5, 0.25), (1.5, 0.75), (0.5, 0.75)],
+        )];
+        square().restrict(&mut mediums, AreaMode::Clip);
+        assert_eq!(
+            pieces(&mediums),
+            [
+                (Some(1), vec![1, -1], vec![(0.5, 0.25), (1.0, 0.25)]),
+                (Some(1), vec![-2, 4], vec![(1.0, 0.75), (0.5, 0.75)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn clip_keeps_a_closed_way_in_one_piece() {
+        let mut mediums = vec![line(
+            1,
+            &[1, 2, 3, 4, 1],
+            &[
+                (0.5, 0.25),
+                (1.5, 0.25),
+                (1.5, 0.75),
+                (0.5, 0.75),
+                (0.5, 0.25),
+            ],
+        )];
+        square().restrict(&mut mediums, AreaMode::Clip);
+        assert_eq!(
+            pieces(&mediums),
+            [(
+                Some(1),
+                vec![-1, 4, 1, -2],
+                vec![(1.0, 0.75), (0.5, 0.75), (0.5, 0.25), (1.0, 0.25)]
+            )]
+        );
+    }
+
+    #[test]
+    fn boundary_refs_stay_below_existing_refs() {
+        let mut mediums = vec![
+            line(1, &[-5, 2], &[(0.5, 0.5), (2.0, 0.5)]),
+            line(2, &[3, 4], &[(0.5, 0.75), (2.0, 0.75)]),
+        ];
+        square().restrict(&mut mediums, AreaMode::Clip);
+        let refs: Vec<Vec<i64>> = mediums.iter().map(|m| m.osm_node_refs.clone()).collect();
+        assert_eq!(refs, [vec![-5, -6], vec![3, -7]]);
+
+        // A second clip continues below the first one's refs.
+        Area::from_bbox(BoundingBox::new(0.0, 0.0, 0.75, 1.0))
+            .restrict(&mut mediums, AreaMode::Clip);
+        let refs: Vec<Vec<i64>> = mediums.iter().map(|m| m.osm_node_refs.clone()).collect();
+        assert_eq!(refs, [vec![-5, -8], vec![3, -9]]);
+    }
+
+    #[test]
+    fn clip_keeps_misaligned_mediums_whole() {
+        let mut mediums = vec![line(1, &[1], &[(0.5, 0.5), (2.0, 0.5)])];
+        square().restrict(&mut mediums, AreaMode::Clip);
+        assert_eq!(
+            pieces(&mediums),
+            [(Some(1), vec![1], vec![(0.5, 0.5), (2.0, 0.5)])]
+        );
+    }
+
+    #[test]
+    fn reads_poly_files() {
+        let text = "area\n1\n 0 0\n 4 0\n 4 4\nEND\n!hole\n 1 1\n 2 1\n 1 2\nEND\nEND\n";
+        assert_eq!(
+            poly_rings(text).unwrap(),
+            [
+                vec![[0.0, 0.0], [4.0, 0.0], [4.0, 4.0]],
+                vec![[1.0, 1.0], [2.0, 1.0], [1.0, 2.0]],
+            ]
+        );
+        assert!(poly_rings("area\n1\n 0 0\nEND\n").is_err());
+        assert!(poly_rings("area\n1\n 0 x\nEND\nEND\n").is_err());
+    }
+}
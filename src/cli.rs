@@ -0,0 +1,442 @@
+use std::path::PathBuf;
+
+use clap::{builder::PossibleValuesParser, Args, Parser, Subcommand, ValueEnum};
+use sgp4::chrono::DateTime;
+
+use osm_kovachs::{
+    area::{Area, AreaMode},
+    columns::COLUMNS,
+    error::KovachsError,
+    mapping::TagMapping,
+    postgis::TagsType,
+    routing::Metric,
+    store::StoreKind,
+    tagfilter::TagFilter,
+    types::medium::{BoundingBox, MediumType, Position},
+};
+
+/// Turns OpenStreetMap extracts into Mediums: roads, railways, waterways, airways
+/// and orbits, with spatial indexing and routing on top.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Worker threads for parsing and indexing; defaults to one per core.
+    #[arg(long, short = 'j', global = true)]
+    pub threads: Option<usize>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Count the nodes, ways and relations in an OSM file.
+    Count {
+        /// Input *.osm.pbf, *.osm, *.osm.gz or *.osm.bz2 file.
+        input: PathBuf,
+        /// Only count elements whose tags match this expression.
+        #[arg(long)]
+        filter: Option<TagFilter>,
+    },
+    /// Parse an OSM file into Mediums and write them out.
+    Extract {
+        /// Input *.osm.pbf, *.osm, *.osm.gz or *.osm.bz2 file.
+        input: PathBuf,
+        /// Output file, or directory for the postgis format.
+        #[arg(long, short)]
+        output: PathBuf,
+        #[arg(long, short, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        #[command(flatten)]
+        output_options: OutputOptions,
+        #[command(flatten)]
+        filters: Filters,
+        /// Where node locations are kept until the ways are joined to them;
+        /// defaults to one picked from the input size.
+        #[arg(long, value_enum)]
+        node_store: Option<NodeStore>,
+        /// Do not write the spatial index next to the output.
+        #[arg(long)]
+        no_index: bool,
+    },
+    /// Apply OsmChange files to a Medium dataset and write the result.
+    Update {
+        /// A Medium dataset (*.json, *.geojson, *.parquet or *.fgb) written by extract.
+        input: PathBuf,
+        /// *.osc, *.osc.gz or *.osc.bz2 files, oldest first.
+        #[arg(required = true)]
+        changes: Vec<PathBuf>,
+        /// Output file, or directory for the postgis format.
+        #[arg(long, short)]
+        output: PathBuf,
+        #[arg(long, short, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        #[command(flatten)]
+        output_options: OutputOptions,
+        /// The filters the dataset was extracted with; new Mediums must pass them.
+        #[command(flatten)]
+        filters: Filters,
+        /// Do not write the spatial index next to the output.
+        #[arg(long)]
+        no_index: bool,
+    },
+    /// Propagate the element sets in a TLE file into SpaceTrajectory Mediums and
+    /// write them out.
+    Orbit {
+        /// A file of two-line element sets, with or without a name line before each.
+        input: PathBuf,
+        /// Output file, or directory for the postgis format.
+        #[arg(long, short)]
+        output: PathBuf,
+        #[arg(long, short, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        #[command(flatten)]
+        output_options: OutputOptions,
+        /// First sample, as an RFC 3339 time or Unix seconds; defaults to now.
+        #[arg(long, value_parser = parse_time)]
+        start: Option<f64>,
+        /// Minutes to follow each orbit for.
+        #[arg(long, default_value_t = 90.0, value_parser = parse_positive)]
+        minutes: f64,
+        /// Seconds between two samples.
+        #[arg(long, default_value_t = 60.0, value_parser = parse_positive)]
+        step: f64,
+        /// Do not write the spatial index next to the output.
+        #[arg(long)]
+        no_index: bool,
+    },
+    /// Summarise a Medium dataset, or an OSM file after extracting it in memory.
+    Stats {
+        /// A Medium dataset (*.json, *.geojson, *.parquet or *.fgb) or OSM file.
+        input: PathBuf,
+        #[command(flatten)]
+        filters: Filters,
+    },
+    /// Find the shortest route between two positions over the highway Mediums.
+    Route {
+        /// A Medium dataset (*.json, *.geojson, *.parquet or *.fgb) written by extract.
+        input: PathBuf,
+        /// Start as "longitude,latitude".
+        #[arg(long, value_parser = parse_position, allow_hyphen_values = true)]
+        from: Position,
+        /// Destination as "longitude,latitude".
+        #[arg(long, value_parser = parse_position, allow_hyphen_values = true)]
+        to: Position,
+        #[arg(long, value_enum, default_value_t = RouteAlgorithm::AStar)]
+        algorithm: RouteAlgorithm,
+        #[arg(long, value_enum, default_value_t = RouteMetric::Distance)]
+        metric: RouteMetric,
+        /// Write the route as JSON here instead of printing a summary.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Build contraction hierarchies for a Medium dataset and save them next to
+    /// it, for `route --algorithm ch` and `distances`.
+    Contract {
+        /// A Medium dataset (*.json, *.geojson, *.parquet or *.fgb) written by extract.
+        input: PathBuf,
+        /// Metrics to build a hierarchy for.
+        #[arg(long, value_enum, value_delimiter = ',', default_values_t = [RouteMetric::Distance, RouteMetric::Time])]
+        metric: Vec<RouteMetric>,
+    },
+    /// Cost from one position to many, using the contraction hierarchy.
+    Distances {
+        /// A Medium dataset (*.json, *.geojson, *.parquet or *.fgb) written by extract.
+        input: PathBuf,
+        /// Start as "longitude,latitude".
+        #[arg(long, value_parser = parse_position, allow_hyphen_values = true)]
+        from: Position,
+        /// Destinations as "longitude,latitude"; repeat for each one.
+        #[arg(long, value_parser = parse_position, allow_hyphen_values = true, required = true)]
+        to: Vec<Position>,
+        #[arg(long, value_enum, default_value_t = RouteMetric::Distance)]
+        metric: RouteMetric,
+        /// Write the costs as a JSON array, null where there is no route, instead of
+        /// printing them.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Convert a Medium dataset to another format.
+    Export {
+        /// A Medium dataset (*.json, *.geojson, *.parquet or *.fgb) written by extract.
+        input: PathBuf,
+        /// Output file, or directory for the postgis format.
+        #[arg(long, short)]
+        output: PathBuf,
+        #[arg(long, short, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        #[command(flatten)]
+        output_options: OutputOptions,
+        #[command(flatten)]
+        filters: Filters,
+    },
+    /// Cut a Medium dataset into Mapbox Vector Tiles.
+    Tile {
+        /// A Medium dataset (*.json, *.geojson, *.parquet or *.fgb) or OSM file.
+        input: PathBuf,
+        /// Directory for a z/x/y.mvt tree, or an *.mbtiles file.
+        #[arg(long, short)]
+        output: PathBuf,
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=22))]
+        min_zoom: u8,
+        /// Everything left is drawn at this zoom, whatever its category.
+        #[arg(long, default_value_t = 14, value_parser = clap::value_parser!(u8).range(0..=22))]
+        max_zoom: u8,
+        #[command(flatten)]
+        filters: Filters,
+    },
+    /// Check a Medium dataset, and its index when present, for inconsistencies.
+    Validate {
+        /// A Medium dataset (*.json, *.geojson, *.parquet or *.fgb) written by extract.
+        input: PathBuf,
+    },
+}
+
+/// Output formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A JSON array of Mediums.
+    Json,
+    /// A GeoJSON FeatureCollection, one feature per Medium.
+    #[value(name = "geojson")]
+    GeoJson,
+    /// GeoParquet with a WKB geometry column.
+    #[value(name = "geoparquet")]
+    GeoParquet,
+    /// FlatGeobuf with a packed Hilbert R-tree for bounding box queries.
+    #[value(name = "fgb")]
+    FlatGeobuf,
+    /// A GeoPackage with mediums and intersection nodes, for QGIS.
+    #[value(name = "gpkg")]
+    GeoPackage,
+    /// A directory with PostGIS DDL, COPY data and a psql load script. The spatial
+    /// index goes inside it, as `mediums.index.json`.
+    #[value(name = "postgis")]
+    Postgis,
+}
+
+/// Settings for particular output formats.
+#[derive(Debug, Clone, Args)]
+pub struct OutputOptions {
+    /// Rows per GeoParquet row group.
+    #[arg(long, default_value_t = 128 * 1024)]
+    pub row_group_size: usize,
+    /// Attribute columns of FlatGeobuf output; defaults to all of them.
+    /// `medium_type` brings `attributes` along.
+    #[arg(long, value_delimiter = ',', value_parser = PossibleValuesParser::new(COLUMNS))]
+    pub columns: Vec<String>,
+    /// Prefix for the PostGIS table names.
+    #[arg(long, default_value = "")]
+    pub table_prefix: String,
+    /// SRID of the PostGIS geometry column; other than 4326 transforms on load.
+    #[arg(long, default_value_t = 4326)]
+    pub srid: u32,
+    /// Column type for the OSM tags kept on PostGIS Mediums.
+    #[arg(long, value_enum, default_value_t = Tags::Jsonb)]
+    pub tags: Tags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Tags {
+    Hstore,
+    Jsonb,
+}
+
+impl From<Tags> for TagsType {
+    fn from(tags: Tags) -> TagsType {
+        match tags {
+            Tags::Hstore => TagsType::Hstore,
+            Tags::Jsonb => TagsType::Jsonb,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NodeStore {
+    /// In memory; fine for country extracts.
+    Sparse,
+    /// Sorted runs on disk next to the output, for continents.
+    Sorted,
+    /// A memory-mapped array indexed by node id, for the planet.
+    Dense,
+}
+
+impl From<NodeStore> for StoreKind {
+    fn from(store: NodeStore) -> StoreKind {
+        match store {
+            NodeStore::Sparse => StoreKind::Sparse,
+            NodeStore::Sorted => StoreKind::SortedFile,
+            NodeStore::Dense => StoreKind::Dense,
+        }
+    }
+}
+
+/// Which Mediums to keep.
+#[derive(Debug, Clone, Default, Args)]
+pub struct Filters {
+    /// Only read OSM ways, relations and stations whose tags match this
+    /// expression, e.g. "highway in (primary, secondary) and not access=private".
+    #[arg(long)]
+    pub filter: Option<TagFilter>,
+    /// TOML file mapping OSM tags to medium types, categories and attributes;
+    /// defaults to the built-in mapping.
+    #[arg(long)]
+    pub mapping: Option<PathBuf>,
+    /// Keep these OSM tags on every Medium, on top of the mapping's `tags` list.
+    #[arg(long, value_delimiter = ',')]
+    pub keep_tags: Vec<String>,
+    /// Only keep Mediums of these types.
+    #[arg(long = "type", value_enum, value_delimiter = ',')]
+    pub types: Vec<MediumKind>,
+    /// Only keep Mediums within "min_lon,min_lat,max_lon,max_lat".
+    #[arg(long, value_parser = parse_bbox, allow_hyphen_values = true, conflicts_with = "polygon")]
+    pub bbox: Option<BoundingBox>,
+    /// Only keep Mediums within the polygon in an Osmosis *.poly or GeoJSON file.
+    #[arg(long)]
+    pub polygon: Option<PathBuf>,
+    /// Cut Mediums at the edge of --bbox or --polygon instead of keeping whole
+    /// every Medium that touches it.
+    #[arg(long)]
+    pub clip: bool,
+}
+
+impl Filters {
+    pub fn keep(&self, medium_type: &MediumType) -> bool {
+        self.types.is_empty() || self.types.iter().any(|k| k.name() == medium_type.name())
+    }
+
+    /// The mapping from --mapping, if given, keeping the tags from --keep-tags.
+    pub fn mapping(&self) -> Result<Option<TagMapping>, KovachsError> {
+        let mapping = self.mapping.as_deref().map(TagMapping::read).transpose()?;
+        if self.keep_tags.is_empty() {
+            return Ok(mapping);
+        }
+        Ok(Some(
+            mapping
+                .unwrap_or_default()
+                .keep_tags(self.keep_tags.clone()),
+        ))
+    }
+
+    /// The area from --bbox or --polygon, if either was given.
+    pub fn area(&self) -> Result<Option<(Area, AreaMode)>, KovachsError> {
+        let mode = if self.clip {
+            AreaMode::Clip
+        } else {
+            AreaMode::Whole
+        };
+        let area = match (&self.bbox, &self.polygon) {
+            (Some(bbox), _) => Area::from_bbox(*bbox),
+            (None, Some(path)) => Area::read(path)?,
+            (None, None) => return Ok(None),
+        };
+        Ok(Some((area, mode)))
+    }
+}
+
+/// The variants of [`MediumType`], without their attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MediumKind {
+    Default,
+    Highway,
+    Railway,
+    Waterway,
+    Airway,
+    SpaceTrajectory,
+}
+
+impl MediumKind {
+    fn name(&self) -> &'static str {
+        match self {
+            MediumKind::Default => "default",
+            MediumKind::Highway => "highway",
+            MediumKind::Railway => "railway",
+            MediumKind::Waterway => "waterway",
+            MediumKind::Airway => "airway",
+            MediumKind::SpaceTrajectory => "space_trajectory",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RouteAlgorithm {
+    #[value(name = "astar")]
+    AStar,
+    Bidirectional,
+    /// Contraction hierarchy, loaded from next to the dataset (see `contract`) or
+    /// built on the fly.
+    Ch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RouteMetric {
+    Distance,
+    Time,
+}
+
+impl From<RouteMetric> for Metric {
+    fn from(metric: RouteMetric) -> Metric {
+        match metric {
+            RouteMetric::Distance => Metric::Distance,
+            RouteMetric::Time => Metric::Time,
+        }
+    }
+}
+
+/// Exit codes besides 0 for success; clap exits with 2 on bad usage.
+pub mod exit_code {
+    /// Reading, parsing or writing failed.
+    pub const FAILURE: u8 = 1;
+    /// `validate` found problems in the dataset.
+    pub const INVALID: u8 = 3;
+    /// `route` found no route between the positions.
+    pub const NO_ROUTE: u8 = 4;
+}
+
+fn parse_bbox(s: &str) -> Result<BoundingBox, String> {
+    let values: Vec<f64> = s
+        .split(',')
+        .map(|v| v.trim().parse::<f64>().map_err(|e| format!("{v:?}: {e}")))
+        .collect::<Result<_, _>>()?;
+    let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+        return Err(format!(
+            "expected \"min_lon,min_lat,max_lon,max_lat\", got {s:?}"
+        ));
+    };
+    if min_lon > max_lon || min_lat > max_lat {
+        return Err(format!("{s:?} has its minimum above its maximum"));
+    }
+    Ok(BoundingBox::new(min_lon, min_lat, max_lon, max_lat))
+}
+
+/// Seconds since the Unix epoch, from an RFC 3339 time or a number of seconds.
+fn parse_time(s: &str) -> Result<f64, String> {
+    if let Ok(seconds) = s.parse::<f64>() {
+        return Ok(seconds);
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.timestamp_millis() as f64 / 1000.0)
+        .map_err(|e| format!("expected an RFC 3339 time or Unix seconds: {e}"))
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v > 0.0 => Ok(v),
+        Ok(_) => Err(String::from("must be above zero")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_position(s: &str) -> Result<Position, String> {
+    let (lon, lat) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected \"longitude,latitude\", got {s:?}"))?;
+    let longitude: f64 = lon.trim().parse().map_err(|e| format!("longitude: {e}"))?;
+    let latitude: f64 = lat.trim().parse().map_err(|e| format!("latitude: {e}"))?;
+    if !(-180.0..=180.0).contains(&longitude) || !(-90.0..=90.0).contains(&latitude) {
+        return Err(format!("{s:?} is outside the valid coordinate range"));
+    }
+    Ok(Position {
+        longitude,
+        latitude,
+    })
+}
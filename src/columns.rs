@@ -0,0 +1,172 @@
+use serde_json::Value;
+
+use crate::{
+    error::{KovachsError, Result},
+    types::medium::{Medium, MediumType, OsmType},
+};
+
+/// Names of the attribute columns every tabular export has, in column order.
+pub const COLUMNS: [&str; 10] = [
+    "osm_id",
+    "osm_type",
+    "name",
+    "medium_type",
+    "category",
+    "is_one_way",
+    "length",
+    "node_refs",
+    "tags",
+    "attributes",
+];
+
+/// A Medium flattened into the columns shared by the tabular exports; the
+/// geometry is written separately.
+///
+/// `category` is the street, rail, water or aeroway category by its variant name.
+/// `tags` holds the kept OSM tags as a JSON object, and `attributes` the remaining
+/// attributes of the type as JSON, so the Medium can be rebuilt exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediumRow {
+    pub osm_id: Option<i64>,
+    pub osm_type: Option<OsmType>,
+    pub name: Option<String>,
+    pub medium_type: &'static str,
+    pub category: Option<String>,
+    pub is_one_way: bool,
+    /// Length in meters.
+    pub length: f64,
+    pub node_refs: Vec<i64>,
+    pub tags: Option<String>,
+    pub attributes: Option<String>,
+}
+
+impl MediumRow {
+    pub fn from_medium(medium: &Medium) -> Result<MediumRow> {
+        let attributes = match &medium.medium_type {
+            MediumType::Default | MediumType::Highway(_) => None,
+            MediumType::Railway(a) => Some(serde_json::to_value(a)?),
+            MediumType::Waterway(a) => Some(serde_json::to_value(a)?),
+            MediumType::Airway(a) => Some(serde_json::to_value(a)?),
+            MediumType::SpaceTrajectory(a) => Some(serde_json::to_value(a)?),
+        };
+        let category = match &medium.medium_type {
+            MediumType::Highway(categories) => categories.first().map(|c| format!("{:?}", c)),
+            _ => attributes
+                .as_ref()
+                .and_then(|a| a.get("category"))
+                .and_then(Value::as_str)
+                .map(String::from),
+        };
+        Ok(MediumRow {
+            osm_id: medium.osm_id,
+            osm_type: medium.osm_type,
+            name: medium.medium_osm_name.clone(),
+            medium_type: medium.medium_type.name(),
+            category,
+            is_one_way: medium.is_one_way,
+            length: medium.length(),
+            node_refs: medium.osm_node_refs.clone(),
+            tags: (!medium.osm_tags.is_empty())
+                .then(|| serde_json::to_string(&medium.osm_tags))
+                .transpose()?,
+            attributes: attributes.map(|a| a.to_string()),
+        })
+    }
+
+    /// Rebuilds the Medium, without positions.
+    pub fn into_medium(self) -> Result<Medium> {
+        let attributes = || -> Result<Value> {
+            match self.attributes.as_deref() {
+                Some(a) => Ok(serde_json::from_str(a)?),
+                None => Err(invalid("missing attributes")),
+            }
+        };
+        let medium_type = match self.medium_type {
+            "default" => MediumType::Default,
+            "highway" => MediumType::Highway(match self.category.as_deref() {
+                Some(c) => vec![serde_json::from_value(Value::from(c))?],
+                None => Vec::new(),
+            }),
+            "railway" => MediumType::Railway(serde_json::from_value(attributes()?)?),
+            "waterway" => MediumType::Waterway(serde_json::from_value(attributes()?)?),
+            "airway" => MediumType::Airway(serde_json::from_value(attributes()?)?),
+            "space_trajectory" => {
+                MediumType::SpaceTrajectory(serde_json::from_value(attributes()?)?)
+            }
+            other => return Err(invalid(format!("unknown medium_type {other:?}"))),
+        };
+        let mut medium = Medium::new();
+        medium.osm_id = self.osm_id;
+        medium.osm_type = self.osm_type;
+        medium.medium_osm_name = self.name;
+        medium.medium_type = medium_type;
+        medium.is_one_way = self.is_one_way;
+        medium.osm_node_refs = self.node_refs;
+        if let Some(tags) = self.tags.as_deref() {
+            medium.osm_tags = serde_json::from_str(tags)?;
+        }
+        Ok(medium)
+    }
+}
+
+/// The `osm_type` column value read back from a file.
+pub fn osm_type(name: &str) -> Result<OsmType> {
+    OsmType::from_name(name).ok_or_else(|| invalid(format!("unknown osm_type {name:?}")))
+}
+
+/// The `&'static` name for a `medium_type` column value read back from a file.
+pub fn medium_type_name(name: &str) -> Result<&'static str> {
+    [
+        "default",
+        "highway",
+        "railway",
+        "waterway",
+        "airway",
+        "space_trajectory",
+    ]
+    .into_iter()
+    .find(|n| *n == name)
+    .ok_or_else(|| invalid(format!("unknown medium_type {name:?}")))
+}
+
+fn invalid(message: impl Into<String>) -> KovachsError {
+    KovachsError::InvalidData(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::medium::{RailAttributes, RailCategory, StreetCategory};
+
+    #[test]
+    fn round_trips_tags_and_attributes() {
+        let mut highway = Medium::new();
+        highway.osm_id = Some(11);
+        highway.osm_type = Some(OsmType::Way);
+        highway.medium_type = MediumType::Highway(vec![StreetCategory::Primary]);
+        highway.osm_node_refs = vec![3, 5];
+        highway.osm_tags = [("highway", "primary"), ("surface", "asphalt")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut railway = Medium::new();
+        railway.medium_type = MediumType::Railway(RailAttributes {
+            category: RailCategory::Rail,
+            gauge: Some(1435),
+            electrified: None,
+            usage: None,
+            service: None,
+            stations: Vec::new(),
+        });
+
+        for medium in [highway, railway] {
+            let row = MediumRow::from_medium(&medium).unwrap();
+            assert_eq!(row.tags.is_some(), !medium.osm_tags.is_empty());
+            let back = row.into_medium().unwrap();
+            assert_eq!(
+                serde_json::to_value(&back).unwrap(),
+                serde_json::to_value(&medium).unwrap()
+            );
+        }
+    }
+}
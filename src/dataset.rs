@@ -0,0 +1,50 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    error::Result,
+    fgb::read_flatgeobuf,
+    geojson::{GeoJsonReader, GeoJsonWriter},
+    geoparquet::read_geoparquet,
+    types::medium::Medium,
+};
+
+/// Reads a JSON array of Mediums, as written by [`write_json`].
+pub fn read_json(path: &Path) -> Result<Vec<Medium>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn write_json(path: &Path, mediums: &[Medium]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, mediums)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_geojson(path: &Path, mediums: &[Medium]) -> Result<()> {
+    let mut writer = GeoJsonWriter::new(BufWriter::new(File::create(path)?))?;
+    for m in mediums {
+        writer.write(m)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn read_geojson(path: &Path) -> Result<Vec<Medium>> {
+    GeoJsonReader::new(BufReader::new(File::open(path)?)).collect()
+}
+
+/// Reads a dataset in whichever format its extension names: `.geojson`,
+/// `.parquet`, `.fgb` or JSON.
+pub fn read(path: &Path) -> Result<Vec<Medium>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("geojson") => read_geojson(path),
+        Some("parquet") => read_geoparquet(path),
+        Some("fgb") => read_flatgeobuf(path, None),
+        _ => read_json(path),
+    }
+}
@@ -0,0 +1,191 @@
+use std::{error::Error, fmt, io};
+
+use arrow_schema::ArrowError;
+use flatgeobuf::geozero::error::GeozeroError;
+use parquet::errors::ParquetError;
+
+use crate::{resolve::MissingRefs, tagfilter::FilterError};
+
+/// Why turning OSM data into Mediums, or reading and writing them, failed.
+#[derive(Debug)]
+pub enum KovachsError {
+    Io(io::Error),
+    /// A PBF file could not be read or decoded.
+    Pbf(osmpbf::Error),
+    /// Mediums with nodes that have no location in the input, when extracting
+    /// with [`MediumExtractor::strict`](crate::extract::MediumExtractor::strict).
+    MissingNodeRefs(Vec<MissingRefs>),
+    /// A Medium dataset could not be read or written as JSON.
+    Serialization(serde_json::Error),
+    /// A GeoParquet file could not be read or written.
+    Parquet(ParquetError),
+    Arrow(ArrowError),
+    /// A FlatGeobuf file could not be read or written.
+    FlatGeobuf(flatgeobuf::Error),
+    Geozero(GeozeroError),
+    /// A GeoPackage could not be written.
+    Sqlite(rusqlite::Error),
+    /// A contraction hierarchy could not be saved or loaded.
+    Hierarchy(bincode::Error),
+    /// A TLE file could not be parsed.
+    Tle(sgp4::TleError),
+    /// An element set cannot be propagated.
+    Elements(sgp4::ElementsError),
+    /// A dataset file holds something its format does not allow, such as
+    /// truncated WKB or an unknown medium type.
+    InvalidData(String),
+    /// A tag mapping file is not valid TOML or has unknown fields.
+    Toml(toml::de::Error),
+    /// A rule of a tag mapping is invalid.
+    Mapping(String),
+    Filter(FilterError),
+    /// An area file has no polygon or malformed coordinates.
+    Area(String),
+}
+
+pub type Result<T> = std::result::Result<T, KovachsError>;
+
+impl fmt::Display for KovachsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KovachsError::Io(e) => write!(f, "{e}"),
+            KovachsError::Pbf(e) => write!(f, "{e}"),
+            KovachsError::MissingNodeRefs(missing) => {
+                write!(f, "{} mediums have missing node refs", missing.len())?;
+                match missing.first() {
+                    Some(m) => write!(
+                        f,
+                        "; Medium {:?} is missing nodes {:?}",
+                        m.osm_id, m.node_refs
+                    ),
+                    None => Ok(()),
+                }
+            }
+            KovachsError::Serialization(e) => write!(f, "{e}"),
+            KovachsError::Parquet(e) => write!(f, "{e}"),
+            KovachsError::Arrow(e) => write!(f, "{e}"),
+            KovachsError::FlatGeobuf(e) => write!(f, "{e}"),
+            KovachsError::Geozero(e) => write!(f, "{e}"),
+            KovachsError::Sqlite(e) => write!(f, "{e}"),
+            KovachsError::Hierarchy(e) => write!(f, "{e}"),
+            KovachsError::Tle(e) => write!(f, "{e}"),
+            KovachsError::Elements(e) => write!(f, "{e}"),
+            KovachsError::Toml(e) => write!(f, "{e}"),
+            KovachsError::Filter(e) => write!(f, "{e}"),
+            KovachsError::InvalidData(message)
+            | KovachsError::Mapping(message)
+            | KovachsError::Area(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for KovachsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KovachsError::Io(e) => Some(e),
+            KovachsError::Pbf(e) => Some(e),
+            KovachsError::Serialization(e) => Some(e),
+            KovachsError::Parquet(e) => Some(e),
+            KovachsError::Arrow(e) => Some(e),
+            KovachsError::FlatGeobuf(e) => Some(e),
+            KovachsError::Geozero(e) => Some(e),
+            KovachsError::Sqlite(e) => Some(e),
+            KovachsError::Hierarchy(e) => Some(e),
+            KovachsError::Tle(e) => Some(e),
+            KovachsError::Elements(e) => Some(e),
+            KovachsError::Toml(e) => Some(e),
+            KovachsError::Filter(e) => Some(e),
+            KovachsError::MissingNodeRefs(_)
+            | KovachsError::InvalidData(_)
+            | KovachsError::Mapping(_)
+            | KovachsError::Area(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for KovachsError {
+    fn from(e: io::Error) -> KovachsError {
+        KovachsError::Io(e)
+    }
+}
+
+impl From<osmpbf::Error> for KovachsError {
+    fn from(e: osmpbf::Error) -> KovachsError {
+        KovachsError::Pbf(e)
+    }
+}
+
+impl From<serde_json::Error> for KovachsError {
+    fn from(e: serde_json::Error) -> KovachsError {
+        KovachsError::Serialization(e)
+    }
+}
+
+impl From<ParquetError> for KovachsError {
+    fn from(e: ParquetError) -> KovachsError {
+        KovachsError::Parquet(e)
+    }
+}
+
+impl From<ArrowError> for KovachsError {
+    fn from(e: ArrowError) -> KovachsError {
+        KovachsError::Arrow(e)
+    }
+}
+
+impl From<flatgeobuf::Error> for KovachsError {
+    fn from(e: flatgeobuf::Error) -> KovachsError {
+        KovachsError::FlatGeobuf(e)
+    }
+}
+
+impl From<GeozeroError> for KovachsError {
+    fn from(e: GeozeroError) -> KovachsError {
+        KovachsError::Geozero(e)
+    }
+}
+
+impl From<rusqlite::Error> for KovachsError {
+    fn from(e: rusqlite::Error) -> KovachsError {
+        KovachsError::Sqlite(e)
+    }
+}
+
+impl From<bincode::Error> for KovachsError {
+    fn from(e: bincode::Error) -> KovachsError {
+        KovachsError::Hierarchy(e)
+    }
+}
+
+impl From<sgp4::TleError> for KovachsError {
+    fn from(e: sgp4::TleError) -> KovachsError {
+        KovachsError::Tle(e)
+    }
+}
+
+impl From<sgp4::ElementsError> for KovachsError {
+    fn from(e: sgp4::ElementsError) -> KovachsError {
+        KovachsError::Elements(e)
+    }
+}
+
+impl From<toml::de::Error> for KovachsError {
+    fn from(e: toml::de::Error) -> KovachsError {
+        KovachsError::Toml(e)
+    }
+}
+
+impl From<FilterError> for KovachsError {
+    fn from(e: FilterError) -> KovachsError {
+        KovachsError::Filter(e)
+    }
+}
+
+impl From<KovachsError> for io::Error {
+    fn from(e: KovachsError) -> io::Error {
+        match e {
+            KovachsError::Io(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
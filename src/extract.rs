@@ -0,0 +1,628 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use osmpbf::{BlobDecode, BlobReader, Element, ElementReader};
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use crate::{
+    airway::{aerodrome_relation, link_aerodromes},
+    area::{Area, AreaMode},
+    error::{KovachsError, Result},
+    input::{InputFormat, OsmRelation, OsmWay, XmlElement, XmlReader},
+    mapping::TagMapping,
+    railway::{link_stations, rail_station},
+    relation::{assemble_relations, RelationMedium},
+    resolve,
+    store::{create_store, NodeLocation, NodeLocationStore, StoreKind},
+    tagfilter::TagFilter,
+    types::medium::{
+        AeroCategory, Medium, MediumType, OsmType, Position, RailStation, WaterCategory,
+    },
+    waterway::ferry_route,
+};
+
+enum Input {
+    Path(PathBuf),
+    Reader(Box<dyn Read + Send>),
+}
+
+type MediumFilter = Box<dyn Fn(&Medium) -> bool + Send + Sync>;
+
+/// Elements read from XML files are handed to the workers in batches this large.
+const XML_BATCH_SIZE: usize = 64 * 1024;
+
+/// Reads an OSM PBF or XML file into Mediums with their positions resolved.
+///
+/// PBF files written with locations on ways are read in a single pass. Otherwise
+/// node locations are kept in a [`NodeLocationStore`] while the ways are read and
+/// joined to them afterwards.
+///
+/// ```no_run
+/// use osm_kovachs::{extract::MediumExtractor, types::medium::MediumType};
+///
+/// let roads = MediumExtractor::from_path("kenya-latest.osm.pbf")
+///     .filter(|m| matches!(m.medium_type, MediumType::Highway(_)))
+///     .extract()?;
+/// # Ok::<(), osm_kovachs::error::KovachsError>(())
+/// ```
+pub struct MediumExtractor {
+    input: Input,
+    format: Option<InputFormat>,
+    work_dir: Option<PathBuf>,
+    store_kind: Option<StoreKind>,
+    tag_filter: Option<TagFilter>,
+    mapping: Option<TagMapping>,
+    filter: Option<MediumFilter>,
+    area: Option<(Area, AreaMode)>,
+    strict: bool,
+    verbose: bool,
+}
+
+impl MediumExtractor {
+    pub fn from_path(path: impl Into<PathBuf>) -> MediumExtractor {
+        MediumExtractor::new(Input::Path(path.into()))
+    }
+
+    /// Reads from any byte stream, e.g. stdin or a download.
+    pub fn from_reader(reader: impl Read + Send + 'static) -> MediumExtractor {
+        MediumExtractor::new(Input::Reader(Box::new(reader)))
+    }
+
+    fn new(input: Input) -> MediumExtractor {
+        MediumExtractor {
+            input,
+            format: None,
+            work_dir: None,
+            store_kind: None,
+            tag_filter: None,
+            mapping: None,
+            filter: None,
+            area: None,
+            strict: false,
+            verbose: false,
+        }
+    }
+
+    /// Format of the input. Defaults to the one the file name suggests, or PBF
+    /// when reading from a stream or an unknown file name.
+    pub fn format(mut self, format: InputFormat) -> MediumExtractor {
+        self.format = Some(format);
+        self
+    }
+
+    /// Where file backed stores keep their temporary files; defaults to the system
+    /// temp directory.
+    pub fn work_dir(mut self, dir: impl Into<PathBuf>) -> MediumExtractor {
+        self.work_dir = Some(dir.into());
+        self
+    }
+
+    /// Store for node locations. Defaults to one picked from the file size, or a
+    /// sparse store when reading from a stream.
+    pub fn store_kind(mut self, kind: StoreKind) -> MediumExtractor {
+        self.store_kind = Some(kind);
+        self
+    }
+
+    /// Only read the ways, relations and stations whose tags match `filter`. Node
+    /// locations are kept whatever their tags.
+    pub fn tag_filter(mut self, filter: TagFilter) -> MediumExtractor {
+        self.tag_filter = Some(filter);
+        self
+    }
+
+    /// Which ways become which Mediums; defaults to [`TagMapping::default`].
+    pub fn mapping(mut self, mapping: TagMapping) -> MediumExtractor {
+        self.mapping = Some(mapping);
+        self
+    }
+
+    /// Only keep the Mediums for which `filter` returns true. The filter runs after
+    /// positions are resolved and stations and aerodromes are linked.
+    pub fn filter(
+        mut self,
+        filter: impl Fn(&Medium) -> bool + Send + Sync + 'static,
+    ) -> MediumExtractor {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Only keep the Mediums inside `area`, whole or clipped at its boundary. Runs
+    /// after the filter.
+    pub fn area(mut self, area: Area, mode: AreaMode) -> MediumExtractor {
+        self.area = Some((area, mode));
+        self
+    }
+
+    /// Fail with [`KovachsError::MissingNodeRefs`] when node refs have no location
+    /// in the input, instead of leaving those nodes out of the Mediums.
+    pub fn strict(mut self, strict: bool) -> MediumExtractor {
+        self.strict = strict;
+        self
+    }
+
+    /// Print progress and timings to stdout.
+    pub fn verbose(mut self, verbose: bool) -> MediumExtractor {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn extract(self) -> Result<Vec<Medium>> {
+        let MediumExtractor {
+            input,
+            format,
+            work_dir,
+            store_kind,
+            tag_filter,
+            mapping,
+            filter,
+            area,
+            strict,
+            verbose,
+        } = self;
+        let mapping = mapping.unwrap_or_default();
+        let report = |message: String| {
+            if verbose {
+                println!("{message}");
+            }
+        };
+        let start_time = SystemTime::now();
+        let (reader, format, default_kind) = match input {
+            Input::Path(path) => {
+                let format = format
+                    .or_else(|| InputFormat::from_path(&path))
+                    .unwrap_or(InputFormat::Pbf);
+                let kind = StoreKind::for_input(&path, format)?;
+                let reader: Box<dyn Read + Send> = Box::new(File::open(&path)?);
+                (reader, format, kind)
+            }
+            Input::Reader(reader) => (
+                reader,
+                format.unwrap_or(InputFormat::Pbf),
+                StoreKind::Sparse,
+            ),
+        };
+        let new_store = || -> Result<Box<dyn NodeLocationStore>> {
+            let kind = store_kind.unwrap_or(default_kind);
+            report(format!(
+                "Joining node locations to the ways, keeping them in a {:?} store",
+                kind
+            ));
+            create_store(kind, &work_dir.clone().unwrap_or_else(std::env::temp_dir))
+        };
+
+        let (pass, mut store) = if format.is_xml() {
+            let mut store = new_store()?;
+            let elements = XmlReader::decompressed(reader, format);
+            (
+                xml_first_pass(elements, store.as_mut(), tag_filter.as_ref(), &mapping)?,
+                Some(store),
+            )
+        } else {
+            let mut blobs = BlobReader::new(BufReader::new(reader));
+            // The header comes first; anything else is put back in front of the rest.
+            let first = blobs.next().transpose()?;
+            let locations_on_ways = match first.as_ref().map(|b| b.decode()).transpose()? {
+                Some(BlobDecode::OsmHeader(header)) => header
+                    .optional_features()
+                    .iter()
+                    .any(|f| f == "LocationsOnWays"),
+                _ => false,
+            };
+            let mut store = if locations_on_ways {
+                report(String::from(
+                    "Header lists LocationsOnWays: reading positions from the ways in a single pass",
+                ));
+                None
+            } else {
+                Some(new_store()?)
+            };
+            let pass = first_pass(
+                first.into_iter().map(Ok).chain(blobs),
+                store
+                    .as_mut()
+                    .map(|s| s.as_mut() as &mut dyn NodeLocationStore),
+                locations_on_ways,
+                tag_filter.as_ref(),
+                &mapping,
+            )?;
+            (pass, store)
+        };
+        let duration = start_time.elapsed().unwrap_or_default();
+        report(format!(
+            "Read {} ways, {} stations and {} relations in: {:#?}",
+            pass.mediums.len(),
+            pass.stations.len(),
+            pass.relations,
+            duration
+        ));
+
+        let mut mediums = pass.mediums;
+        let relation_count = pass.relation_mediums.len();
+        let dropped = assemble_relations(&mut mediums, pass.relation_mediums);
+        report(format!(
+            "Assembled {} of {} relations",
+            relation_count - dropped,
+            relation_count
+        ));
+        if let Some(store) = store.as_mut() {
+            store.finish()?;
+            report(format!("The nodes total: {:?}", store.len()));
+            let missing = resolve::fill_positions(&mut mediums, store.as_ref());
+            if strict && !missing.is_empty() {
+                return Err(KovachsError::MissingNodeRefs(missing));
+            }
+            report(format!("{} mediums have missing node refs", missing.len()));
+            for m in missing.iter().take(10) {
+                report(format!(
+                    "Medium {:?} is missing nodes {:?}",
+                    m.osm_id, m.node_refs
+                ));
+            }
+        }
+        let unlinked = link_aerodromes(&mut mediums);
+        report(format!(
+            "{} airside mediums lie outside any aerodrome",
+            unlinked
+        ));
+        let station_count = pass.stations.len();
+        let unlinked = link_stations(&mut mediums, pass.stations);
+        report(format!(
+            "Linked {} of {} stations to railways",
+            station_count - unlinked,
+            station_count
+        ));
+        if let Some(filter) = filter {
+            mediums.retain(|m| filter(m));
+        }
+        if let Some((area, mode)) = area {
+            area.restrict(&mut mediums, mode);
+        }
+
+        let duration = start_time.elapsed().unwrap_or_default();
+        report(format!(
+            "Created {} Mediums in: {:#?}",
+            mediums.len(),
+            duration
+        ));
+        Ok(mediums)
+    }
+}
+
+/// Number of each kind of element in a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ElementCounts {
+    pub nodes: u64,
+    pub ways: u64,
+    pub relations: u64,
+}
+
+/// Counts the elements of an OSM file, only those whose tags match `filter` when
+/// one is given.
+pub fn count_elements(path: impl AsRef<Path>, filter: Option<&TagFilter>) -> Result<ElementCounts> {
+    let path = path.as_ref();
+    if InputFormat::from_path(path).is_some_and(InputFormat::is_xml) {
+        let mut counts = ElementCounts::default();
+        for element in XmlReader::from_path(path)? {
+            match element? {
+                XmlElement::Node(n) => counts.nodes += counted(filter, xml_tags(&n.tags)),
+                XmlElement::Way(w) => counts.ways += counted(filter, w.tags()),
+                XmlElement::Relation(r) => counts.relations += counted(filter, r.tags()),
+            }
+        }
+        return Ok(counts);
+    }
+    let reader = ElementReader::from_path(path)?;
+    let counts = reader.par_map_reduce(
+        |element| match element {
+            Element::Node(n) => ElementCounts {
+                nodes: counted(filter, n.tags()),
+                ..Default::default()
+            },
+            Element::DenseNode(n) => ElementCounts {
+                nodes: counted(filter, n.tags()),
+                ..Default::default()
+            },
+            Element::Way(w) => ElementCounts {
+                ways: counted(filter, w.tags()),
+                ..Default::default()
+            },
+            Element::Relation(r) => ElementCounts {
+                relations: counted(filter, r.tags()),
+                ..Default::default()
+            },
+        },
+        ElementCounts::default,
+        |a, b| ElementCounts {
+            nodes: a.nodes + b.nodes,
+            ways: a.ways + b.ways,
+            relations: a.relations + b.relations,
+        },
+    )?;
+    Ok(counts)
+}
+
+fn counted<'a>(
+    filter: Option<&TagFilter>,
+    tags: impl Iterator<Item = (&'a str, &'a str)> + Clone,
+) -> u64 {
+    keep(filter, tags) as u64
+}
+
+fn keep<'a>(
+    filter: Option<&TagFilter>,
+    tags: impl Iterator<Item = (&'a str, &'a str)> + Clone,
+) -> bool {
+    filter.is_none_or(|f| f.matches(tags))
+}
+
+fn xml_tags(tags: &[(String, String)]) -> impl Iterator<Item = (&str, &str)> + Clone {
+    tags.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+}
+
+/// What the first pass collects from the file besides node locations.
+#[derive(Default)]
+struct FirstPass {
+    mediums: Vec<Medium>,
+    stations: Vec<RailStation>,
+    /// Ferry routes and aerodrome multipolygons, joined once all ways are read.
+    relation_mediums: Vec<RelationMedium>,
+    relations: u64,
+}
+
+impl FirstPass {
+    fn merge(mut self, other: FirstPass) -> FirstPass {
+        self.mediums.extend(other.mediums);
+        self.stations.extend(other.stations);
+        self.relation_mediums.extend(other.relation_mediums);
+        self.relations += other.relations;
+        self
+    }
+
+    /// Keeps the node's location for the store, and the node itself when it is a
+    /// station that passes the filter.
+    fn add_node<'a>(
+        &mut self,
+        locations: &mut Vec<NodeLocation>,
+        (id, lon, lat): NodeLocation,
+        tags: impl Iterator<Item = (&'a str, &'a str)> + Clone,
+        filter: Option<&TagFilter>,
+    ) {
+        locations.push((id, lon, lat));
+        let station = rail_station(id, Position::from_decimicro(lon, lat), tags.clone());
+        if station.is_some() && keep(filter, tags) {
+            self.stations.extend(station);
+        }
+    }
+
+    fn add_relation(
+        &mut self,
+        relation: &impl OsmRelation,
+        filter: Option<&TagFilter>,
+        mapping: &TagMapping,
+    ) {
+        if !keep(filter, relation.tags()) {
+            return;
+        }
+        self.relation_mediums
+            .extend(relation_medium(relation, mapping));
+        self.relations += 1;
+    }
+}
+
+fn insert_locations(
+    store: &Mutex<Option<&mut dyn NodeLocationStore>>,
+    locations: &[NodeLocation],
+) -> Result<()> {
+    if locations.is_empty() {
+        return Ok(());
+    }
+    let mut store = store.lock().unwrap_or_else(|e| e.into_inner());
+    match store.as_mut() {
+        Some(store) => store.insert_batch(locations),
+        None => Ok(()),
+    }
+}
+
+/// Decodes the blobs in parallel: every way that passes the filter becomes a
+/// Medium, node locations go into `store` when there is one, and stations and
+/// relations are kept for later.
+fn first_pass(
+    blobs: impl Iterator<Item = osmpbf::Result<osmpbf::Blob>> + Send,
+    store: Option<&mut dyn NodeLocationStore>,
+    locations_on_ways: bool,
+    filter: Option<&TagFilter>,
+    mapping: &TagMapping,
+) -> Result<FirstPass> {
+    let store = Mutex::new(store);
+    blobs
+        .par_bridge()
+        .map(|blob| -> Result<FirstPass> {
+            let BlobDecode::OsmData(block) = blob?.decode()? else {
+                return Ok(FirstPass::default());
+            };
+            let mut pass = FirstPass::default();
+            let mut locations = Vec::new();
+            for group in block.groups() {
+                for n in group.dense_nodes() {
+                    let location = (n.id(), n.decimicro_lon(), n.decimicro_lat());
+                    pass.add_node(&mut locations, location, n.tags(), filter);
+                }
+                for n in group.nodes() {
+                    let location = (n.id(), n.decimicro_lon(), n.decimicro_lat());
+                    pass.add_node(&mut locations, location, n.tags(), filter);
+                }
+                for way in group.ways().filter(|w| keep(filter, w.tags())) {
+                    let mut way_medium = medium_from_way(&way, mapping);
+                    if locations_on_ways {
+                        way_medium.medium_positions = way
+                            .node_locations()
+                            .map(Position::from_way_node_location)
+                            .collect();
+                    }
+                    pass.mediums.push(way_medium);
+                }
+                for r in group.relations() {
+                    pass.add_relation(&r, filter, mapping);
+                }
+            }
+            insert_locations(&store, &locations)?;
+            Ok(pass)
+        })
+        .try_reduce(FirstPass::default, |a, b| Ok(a.merge(b)))
+}
+
+/// The first pass over an XML file. Parsing is sequential; the elements are
+/// turned into Mediums in parallel batches.
+fn xml_first_pass(
+    mut elements: impl Iterator<Item = io::Result<XmlElement>> + Send,
+    store: &mut dyn NodeLocationStore,
+    filter: Option<&TagFilter>,
+    mapping: &TagMapping,
+) -> Result<FirstPass> {
+    let store = Mutex::new(Some(store));
+    let batches = std::iter::from_fn(|| {
+        let batch: io::Result<Vec<XmlElement>> = elements.by_ref().take(XML_BATCH_SIZE).collect();
+        match batch {
+            Ok(batch) if batch.is_empty() => None,
+            batch => Some(batch),
+        }
+    });
+    batches
+        .par_bridge()
+        .map(|batch| -> Result<FirstPass> {
+            let mut pass = FirstPass::default();
+            let mut locations = Vec::new();
+            for element in batch? {
+                match element {
+                    XmlElement::Node(n) => {
+                        let location = (n.id, n.decimicro_lon, n.decimicro_lat);
+                        pass.add_node(&mut locations, location, xml_tags(&n.tags), filter);
+                    }
+                    XmlElement::Way(w) => {
+                        if keep(filter, w.tags()) {
+                            pass.mediums.push(medium_from_way(&w, mapping));
+                        }
+                    }
+                    XmlElement::Relation(r) => pass.add_relation(&r, filter, mapping),
+                }
+            }
+            insert_locations(&store, &locations)?;
+            Ok(pass)
+        })
+        .try_reduce(FirstPass::default, |a, b| Ok(a.merge(b)))
+}
+
+/// The ferry route or aerodrome a relation stands for, with the tags `mapping`
+/// keeps. `mapping` classifies the relation like a way; only the Ferry waterway
+/// and Aerodrome airway categories are joined from member ways.
+pub(crate) fn relation_medium(
+    relation: &impl OsmRelation,
+    mapping: &TagMapping,
+) -> Option<RelationMedium> {
+    let tags: Vec<(&str, &str)> = relation.tags().collect();
+    let mut medium = match mapping.classify(&tags)?.medium_type {
+        MediumType::Waterway(a) if a.category == WaterCategory::Ferry => ferry_route(relation, a),
+        MediumType::Airway(a) if a.category == AeroCategory::Aerodrome => {
+            aerodrome_relation(relation, a)
+        }
+        _ => return None,
+    };
+    medium.tags = mapping.kept_tags(relation.tags());
+    Some(medium)
+}
+
+/// Turns a way into a Medium holding its node refs; positions are filled in later.
+/// Ways `mapping` does not take become highways without a category.
+pub(crate) fn medium_from_way(way: &impl OsmWay, mapping: &TagMapping) -> Medium {
+    // For each way we create a medium
+    // and populate it with nodes
+    let mut way_medium = Medium::new();
+    let mut way_one_way = None;
+    let med_positions = Vec::new();
+    let mut node_refs: Vec<i64> = Vec::new();
+    way.refs().for_each(|r| {
+        node_refs.push(r);
+    });
+    way_medium.osm_node_refs = node_refs;
+    way_medium.medium_positions = med_positions;
+    let tags: Vec<(&str, &str)> = way.tags().collect();
+    tags.iter().for_each(|&(k, v)| {
+        if k == "oneway" {
+            match v {
+                "yes" => way_one_way = Some(true),
+                "no" => way_one_way = Some(false),
+                _ => (),
+            }
+        } else if k == "name" {
+            way_medium.medium_osm_name = Some(String::from(v))
+        }
+    });
+    way_medium.osm_tags = mapping.kept_tags(tags.iter().copied());
+    let classified = mapping.classify(&tags);
+    way_medium.osm_id = Some(way.id());
+    way_medium.osm_type = Some(OsmType::Way);
+    way_medium.is_one_way = way_one_way
+        .or(classified.as_ref().and_then(|c| c.oneway))
+        .unwrap_or(false);
+    way_medium.medium_type = match classified {
+        Some(c) => c.medium_type,
+        None => MediumType::Highway(Vec::new()),
+    };
+    way_medium
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{MemberType, XmlRelation};
+
+    fn relation(tags: &[(&str, &str)]) -> XmlRelation {
+        XmlRelation {
+            id: 7,
+            members: vec![
+                (MemberType::Way, 1, "outer".into()),
+                (MemberType::Way, 2, "inner".into()),
+                (MemberType::Node, 3, String::new()),
+            ],
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn classifies_relations_with_the_mapping() {
+        let default = TagMapping::default();
+        let ferry = relation(&[("type", "route"), ("route", "ferry"), ("duration", "01:30")]);
+        let medium = relation_medium(&ferry, &default).unwrap();
+        assert_eq!(medium.ways, [1, 2]);
+        assert!(matches!(medium.medium_type, MediumType::Waterway(w) if w.duration == Some(5400)));
+        let aerodrome = relation(&[("type", "multipolygon"), ("aeroway", "aerodrome")]);
+        assert_eq!(relation_medium(&aerodrome, &default).unwrap().ways, [1]);
+
+        let boat = relation(&[("type", "route"), ("route", "boat")]);
+        assert!(relation_medium(&boat, &default).is_none());
+        let mapping = TagMapping::parse(
+            r#"
+            [[rule]]
+            medium_type = "waterway"
+            key = "route"
+            categories = { boat = "Ferry" }
+            defaults = { boat = "yes" }
+            "#,
+        )
+        .unwrap();
+        let medium = relation_medium(&boat, &mapping).unwrap();
+        assert!(
+            matches!(medium.medium_type, MediumType::Waterway(w) if w.boat.as_deref() == Some("yes"))
+        );
+        assert!(relation_medium(&ferry, &mapping).is_none());
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use flatgeobuf::{
+    geozero::{
+        self, error::GeozeroError, ColumnValue, FeatureProperties, GeomProcessor, GeozeroGeometry,
+        PropertyProcessor,
+    },
+    ColumnType, FallibleStreamingIterator, FgbCrs, FgbReader, FgbWriter, FgbWriterOptions,
+    GeometryType,
+};
+
+use crate::{
+    columns::{medium_type_name, osm_type, MediumRow, COLUMNS},
+    error::{KovachsError, Result},
+    types::medium::{BoundingBox, Medium, Position},
+};
+
+/// Writes Mediums as FlatGeobuf with a packed Hilbert R-tree, so readers can
+/// fetch the features in a bounding box without reading the whole file.
+///
+/// Each Medium becomes a Point or LineString feature in longitude/latitude
+/// carrying the chosen [`COLUMNS`]; `node_refs`, `tags` and `attributes` are
+/// JSON columns. Features are buffered in a temporary file until [`finish`] sorts
+/// them along the Hilbert curve.
+///
+/// [`finish`]: FlatGeobufWriter::finish
+pub struct FlatGeobufWriter {
+    writer: FgbWriter<'static>,
+    columns: Vec<&'static str>,
+    /// Mediums without positions, which have no place in the index.
+    skipped: usize,
+}
+
+impl FlatGeobufWriter {
+    /// Fails if one of `columns` is not in [`COLUMNS`]; an empty slice writes
+    /// them all. `attributes` is added to `medium_type`, which cannot be read
+    /// back without it.
+    pub fn new(columns: &[&str]) -> Result<FlatGeobufWriter> {
+        let mut columns: Vec<&'static str> = if columns.is_empty() {
+            COLUMNS.to_vec()
+        } else {
+            columns
+                .iter()
+                .map(|c| {
+                    COLUMNS
+                        .into_iter()
+                        .find(|n| n == c)
+                        .ok_or_else(|| invalid(format!("unknown column {c:?}")))
+                })
+                .collect::<Result<_>>()?
+        };
+        if columns.contains(&"medium_type") && !columns.contains(&"attributes") {
+            columns.push("attributes");
+        }
+        let options = FgbWriterOptions {
+            write_index: true,
+            // Points and LineStrings are mixed, so every feature keeps its own type.
+            detect_type: false,
+            promote_to_multi: false,
+            crs: FgbCrs {
+                code: 4326,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut writer = FgbWriter::create_with_options("mediums", GeometryType::Unknown, options)?;
+        for name in columns.iter().copied() {
+            let column_type = match name {
+                "osm_id" => ColumnType::Long,
+                "is_one_way" => ColumnType::Bool,
+                "length" => ColumnType::Double,
+                "node_refs" | "tags" | "attributes" => ColumnType::Json,
+                _ => ColumnType::String,
+            };
+            writer.add_column(name, column_type, |_, column| {
+                column.nullable = !matches!(name, "medium_type" | "is_one_way" | "length");
+            });
+        }
+        Ok(FlatGeobufWriter {
+            writer,
+            columns,
+            skipped: 0,
+        })
+    }
+
+    /// Adds a Medium; ones without positions are skipped and counted.
+    pub fn write(&mut self, medium: &Medium) -> Result<()> {
+        if medium.medium_positions.is_empty() {
+            self.skipped += 1;
+            return Ok(());
+        }
+        let row = MediumRow::from_medium(medium)?;
+        let node_refs = serde_json::to_string(&row.node_refs)?;
+        let columns = &self.columns;
+        let mut result = Ok(());
+        self.writer
+            .add_feature_geom(Line(&medium.medium_positions), |feature| {
+                for (i, name) in columns.iter().enumerate() {
+                    let value = match *name {
+                        "osm_id" => row.osm_id.map(ColumnValue::Long),
+                        "osm_type" => row.osm_type.map(|t| ColumnValue::String(t.name())),
+                        "name" => row.name.as_deref().map(ColumnValue::String),
+                        "medium_type" => Some(ColumnValue::String(row.medium_type)),
+                        "category" => row.category.as_deref().map(ColumnValue::String),
+                        "is_one_way" => Some(ColumnValue::Bool(row.is_one_way)),
+                        "length" => Some(ColumnValue::Double(row.length)),
+                        "node_refs" => Some(ColumnValue::Json(&node_refs)),
+                        "tags" => row.tags.as_deref().map(ColumnValue::Json),
+                        "attributes" => row.attributes.as_deref().map(ColumnValue::Json),
+                        _ => None,
+                    };
+                    if let Some(value) = value {
+                        if let Err(e) = feature.property(i, name, &value) {
+                            result = Err(e);
+                            return;
+                        }
+                    }
+                }
+            })?;
+        Ok(result?)
+    }
+
+    /// Sorts the features, writes the index and the features out, and returns
+    /// how many Mediums were skipped for having no positions.
+    pub fn finish(self, writer: impl Write) -> Result<usize> {
+        self.writer.write(writer)?;
+        Ok(self.skipped)
+    }
+}
+
+/// Writes `mediums` with the given columns, all of them when `columns` is empty.
+/// Returns how many Mediums were skipped for having no positions.
+pub fn write_flatgeobuf(path: &Path, mediums: &[Medium], columns: &[&str]) -> Result<usize> {
+    let mut writer = FlatGeobufWriter::new(columns)?;
+    for m in mediums {
+        writer.write(m)?;
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    let skipped = writer.finish(&mut out)?;
+    out.flush()?;
+    Ok(skipped)
+}
+
+/// Reads Mediums back from a file written by [`FlatGeobufWriter`], only those
+/// whose bounding box intersects `bbox` when one is given.
+///
+/// Columns left out when writing come back empty; without `medium_type` every
+/// Medium is a Default one.
+pub fn read_flatgeobuf(path: &Path, bbox: Option<&BoundingBox>) -> Result<Vec<Medium>> {
+    let reader = FgbReader::open(BufReader::new(File::open(path)?))?;
+    let mut features = match bbox {
+        Some(b) => reader.select_bbox(b.min_lon, b.min_lat, b.max_lon, b.max_lat),
+        None => reader.select_all(),
+    }?;
+    let mut mediums = Vec::new();
+    while let Some(feature) = features.next()? {
+        let mut row = RowReader(MediumRow {
+            osm_id: None,
+            osm_type: None,
+            name: None,
+            medium_type: "default",
+            category: None,
+            is_one_way: false,
+            length: 0.0,
+            node_refs: Vec::new(),
+            tags: None,
+            attributes: None,
+        });
+        feature.process_properties(&mut row)?;
+        let mut medium = row.0.into_medium()?;
+        let mut positions = Positions(Vec::new());
+        feature.process_geom(&mut positions)?;
+        medium.medium_positions = positions.0;
+        mediums.push(medium);
+    }
+    Ok(mediums)
+}
+
+fn invalid(message: impl Into<String>) -> KovachsError {
+    KovachsError::InvalidData(message.into())
+}
+
+/// A Medium's positions as a Point, or a LineString when there are several.
+struct Line<'a>(&'a [Position]);
+
+impl GeozeroGeometry for Line<'_> {
+    fn process_geom<P: GeomProcessor>(&self, processor: &mut P) -> geozero::error::Result<()> {
+        match self.0 {
+            [p] => {
+                processor.point_begin(0)?;
+                processor.xy(p.longitude, p.latitude, 0)?;
+                processor.point_end(0)
+            }
+            positions => {
+                processor.linestring_begin(true, positions.len(), 0)?;
+                for (i, p) in positions.iter().enumerate() {
+                    processor.xy(p.longitude, p.latitude, i)?;
+                }
+                processor.linestring_end(true, 0)
+            }
+        }
+    }
+}
+
+/// Collects the coordinates of whatever geometry a feature has.
+struct Positions(Vec<Position>);
+
+impl GeomProcessor for Positions {
+    fn xy(&mut self, x: f64, y: f64, _idx: usize) -> geozero::error::Result<()> {
+        self.0.push(Position {
+            longitude: x,
+            latitude: y,
+        });
+        Ok(())
+    }
+}
+
+struct RowReader(MediumRow);
+
+impl PropertyProcessor for RowReader {
+    fn property(
+        &mut self,
+        _idx: usize,
+        name: &str,
+        value: &ColumnValue,
+    ) -> geozero::error::Result<bool> {
+        let row = &mut self.0;
+        match (name, value) {
+            ("osm_id", ColumnValue::Long(v)) => row.osm_id = Some(*v),
+            ("osm_type", ColumnValue::String(v)) => {
+                row.osm_type = Some(osm_type(v).map_err(|e| GeozeroError::Property(e.to_string()))?)
+            }
+            ("name", ColumnValue::String(v)) => row.name = Some(v.to_string()),
+            ("medium_type", ColumnValue::String(v)) => {
+                row.medium_type =
+                    medium_type_name(v).map_err(|e| GeozeroError::Property(e.to_string()))?
+            }
+            ("category", ColumnValue::String(v)) => row.category = Some(v.to_string()),
+            ("is_one_way", ColumnValue::Bool(v)) => row.is_one_way = *v,
+            ("length", ColumnValue::Double(v)) => row.length = *v,
+            ("node_refs", ColumnValue::Json(v)) => {
+                row.node_refs =
+                    serde_json::from_str(v).map_err(|e| GeozeroError::Property(e.to_string()))?
+            }
+            ("tags", ColumnValue::Json(v)) => row.tags = Some(v.to_string()),
+            ("attributes", ColumnValue::Json(v)) => row.attributes = Some(v.to_string()),
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::medium::{
+        MediumType, RailAttributes, RailCategory, WaterAttributes, WaterCategory,
+    };
+
+    fn medium(osm_id: i64, medium_type: MediumType) -> Medium {
+        let mut medium = Medium::new();
+        medium.osm_id = Some(osm_id);
+        medium.medium_osm_name = Some(format!("medium {osm_id}"));
+        medium.medium_type = medium_type;
+        medium.osm_node_refs = vec![1, 2];
+        medium.medium_positions = vec![
+            Position {
+                longitude: 36.8 + osm_id as f64 * 0.01,
+                latitude: -1.3,
+            },
+            Position {
+                longitude: 36.8 + osm_id as f64 * 0.01,
+                latitude: -1.2,
+            },
+        ];
+        medium
+    }
+
+    #[test]
+    fn reads_back_a_subset_of_columns() {
+        let mediums = vec![
+            medium(
+                1,
+                MediumType::Railway(RailAttributes {
+                    category: RailCategory::Rail,
+                    gauge: Some(1000),
+                    electrified: None,
+                    usage: None,
+                    service: None,
+                    stations: Vec::new(),
+                }),
+            ),
+            medium(
+                2,
+                MediumType::Waterway(WaterAttributes {
+                    category: WaterCategory::Ferry,
+                    boat: None,
+                    ship: None,
+                    width: None,
+                    duration: Some(900),
+                }),
+            ),
+        ];
+        let path =
+            std::env::temp_dir().join(format!("osm-kovachs-{}-subset.fgb", std::process::id()));
+        write_flatgeobuf(&path, &mediums, &["osm_id", "medium_type"]).unwrap();
+        let read = read_flatgeobuf(&path, None);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut read = read.unwrap();
+        read.sort_by_key(|m| m.osm_id);
+        assert_eq!(read.len(), 2);
+        for (back, medium) in read.iter().zip(mediums.iter()) {
+            assert_eq!(back.osm_id, medium.osm_id);
+            assert_eq!(
+                serde_json::to_value(&back.medium_type).unwrap(),
+                serde_json::to_value(&medium.medium_type).unwrap()
+            );
+            // Left out when writing.
+            assert_eq!(back.medium_osm_name, None);
+            assert!(back.osm_node_refs.is_empty());
+            assert_eq!(back.medium_positions.len(), 2);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::io::{BufRead, Write};
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    error::{KovachsError, Result},
+    types::medium::{Medium, MediumType, OsmType, Position},
+};
+
+/// First line of every collection written by [`GeoJsonWriter`]; each feature then
+/// follows on a line of its own.
+const HEADER: &str = r#"{"type":"FeatureCollection","features":["#;
+
+/// Properties every feature has; anything else holds the Medium's type attributes.
+const RESERVED: [&str; 9] = [
+    "osm_id",
+    "osm_type",
+    "name",
+    "medium_type",
+    "category",
+    "categories",
+    "oneway",
+    "node_refs",
+    "tags",
+];
+
+/// Writes Mediums as a GeoJSON FeatureCollection one at a time, so the whole
+/// collection never has to be in memory.
+///
+/// Each Medium becomes a LineString feature (a Point when it has a single position)
+/// with `osm_id`, `osm_type`, `name`, `medium_type`, `category`, `oneway` and
+/// `node_refs` properties, plus the remaining attributes of its type. Highways
+/// also list every category in `categories`, and Mediums with kept OSM tags
+/// carry them in a `tags` object.
+pub struct GeoJsonWriter<W: Write> {
+    writer: W,
+    features: usize,
+}
+
+impl<W: Write> GeoJsonWriter<W> {
+    pub fn new(mut writer: W) -> Result<GeoJsonWriter<W>> {
+        writer.write_all(HEADER.as_bytes())?;
+        Ok(GeoJsonWriter {
+            writer,
+            features: 0,
+        })
+    }
+
+    pub fn write(&mut self, medium: &Medium) -> Result<()> {
+        let separator = if self.features == 0 { "\n" } else { ",\n" };
+        self.writer.write_all(separator.as_bytes())?;
+        serde_json::to_writer(&mut self.writer, &to_feature(medium)?)?;
+        self.features += 1;
+        Ok(())
+    }
+
+    /// Closes the collection and returns the underlying writer, flushed.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.write_all(b"\n]}\n")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads Mediums back from GeoJSON.
+///
+/// Collections written by [`GeoJsonWriter`] are read a feature at a time; any
+/// other GeoJSON is parsed as a whole first. Features without a `medium_type`
+/// become Default Mediums.
+pub struct GeoJsonReader<R: BufRead> {
+    reader: R,
+    /// Features of a document that was not written line by line.
+    parsed: Option<std::vec::IntoIter<Value>>,
+    started: bool,
+    line: String,
+}
+
+impl<R: BufRead> GeoJsonReader<R> {
+    pub fn new(reader: R) -> GeoJsonReader<R> {
+        GeoJsonReader {
+            reader,
+            parsed: None,
+            started: false,
+            line: String::new(),
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        self.started = true;
+        self.reader.read_line(&mut self.line)?;
+        if self.line.trim_end() == HEADER {
+            return Ok(());
+        }
+        let mut document = std::mem::take(&mut self.line);
+        self.reader.read_to_string(&mut document)?;
+        let features = match serde_json::from_str::<Value>(&document)? {
+            Value::Object(mut collection) => match collection.remove("features") {
+                Some(Value::Array(features)) => features,
+                // A lone feature.
+                _ => vec![Value::Object(collection)],
+            },
+            _ => return Err(invalid("expected a GeoJSON object")),
+        };
+        self.parsed = Some(features.into_iter());
+        Ok(())
+    }
+
+    fn next_line_feature(&mut self) -> Result<Option<Value>> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            let line = self.line.trim().trim_end_matches(',');
+            if line.starts_with('{') {
+                return Ok(Some(serde_json::from_str(line)?));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for GeoJsonReader<R> {
+    type Item = Result<Medium>;
+
+    fn next(&mut self) -> Option<Result<Medium>> {
+        if !self.started {
+            if let Err(e) = self.start() {
+                return Some(Err(e));
+            }
+        }
+        let feature = match self.parsed.as_mut() {
+            Some(features) => features.next(),
+            None => match self.next_line_feature() {
+                Ok(feature) => feature,
+                Err(e) => return Some(Err(e)),
+            },
+        }?;
+        Some(from_feature(feature))
+    }
+}
+
+fn invalid(message: impl Into<String>) -> KovachsError {
+    KovachsError::InvalidData(message.into())
+}
+
+fn to_feature(medium: &Medium) -> Result<Value> {
+    let coordinates: Vec<[f64; 2]> = medium
+        .medium_positions
+        .iter()
+        .map(|p| [p.longitude, p.latitude])
+        .collect();
+    let geometry = match coordinates.len() {
+        0 => Value::Null,
+        1 => json!({"type": "Point", "coordinates": coordinates[0]}),
+        _ => json!({"type": "LineString", "coordinates": coordinates}),
+    };
+
+    let mut properties = Map::new();
+    properties.insert("osm_id".into(), json!(medium.osm_id));
+    properties.insert("osm_type".into(), json!(medium.osm_type));
+    properties.insert("name".into(), json!(medium.medium_osm_name));
+    properties.insert("medium_type".into(), json!(medium.medium_type.name()));
+    let attributes = match &medium.medium_type {
+        MediumType::Default => Value::Null,
+        MediumType::Highway(categories) => {
+            json!({"category": categories.first(), "categories": categories})
+        }
+        MediumType::Railway(a) => serde_json::to_value(a)?,
+        MediumType::Waterway(a) => serde_json::to_value(a)?,
+        MediumType::Airway(a) => serde_json::to_value(a)?,
+        MediumType::SpaceTrajectory(a) => serde_json::to_value(a)?,
+    };
+    let mut attributes = match attributes {
+        Value::Object(attributes) => attributes,
+        _ => Map::new(),
+    };
+    properties.insert(
+        "category".into(),
+        attributes.remove("category").unwrap_or(Value::Null),
+    );
+    properties.insert("oneway".into(), json!(medium.is_one_way));
+    properties.insert("node_refs".into(), json!(medium.osm_node_refs));
+    if !medium.osm_tags.is_empty() {
+        properties.insert("tags".into(), json!(medium.osm_tags));
+    }
+    properties.extend(attributes);
+
+    Ok(json!({
+        "type": "Feature",
+        "geometry": geometry,
+        "properties": properties,
+    }))
+}
+
+fn from_feature(feature: Value) -> Result<Medium> {
+    let Value::Object(mut feature) = feature else {
+        return Err(invalid("expected a GeoJSON feature"));
+    };
+    let mut properties = match feature.remove("properties") {
+        Some(Value::Object(properties)) => properties,
+        _ => Map::new(),
+    };
+    let mut medium = Medium::new();
+    medium.medium_positions = match feature.remove("geometry") {
+        Some(Value::Object(geometry)) => positions(&geometry)?,
+        _ => Vec::new(),
+    };
+    medium.osm_id = properties.get("osm_id").and_then(Value::as_i64);
+    medium.osm_type = properties
+        .get("osm_type")
+        .and_then(Value::as_str)
+        .and_then(OsmType::from_name);
+    medium.medium_osm_name = properties
+        .get("name")
+        .and_then(Value::as_str)
+        .map(String::from);
+    medium.is_one_way = properties
+        .get("oneway")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    if let Some(refs) = properties.remove("node_refs") {
+        medium.osm_node_refs = serde_json::from_value(refs).unwrap_or_default();
+    }
+    if let Some(tags) = properties.remove("tags") {
+        medium.osm_tags = serde_json::from_value(tags)?;
+    }
+
+    let kind = properties
+        .get("medium_type")
+        .and_then(Value::as_str)
+        .unwrap_or("default")
+        .to_string();
+    let category = properties.remove("category").unwrap_or(Value::Null);
+    let categories = properties.remove("categories");
+    properties.retain(|k, _| !RESERVED.contains(&k.as_str()));
+    properties.insert("category".into(), category.clone());
+    let attributes = Value::Object(properties);
+    medium.medium_type = match kind.as_str() {
+        "default" => MediumType::Default,
+        // GeoJSON from elsewhere may only have the first category.
+        "highway" => MediumType::Highway(match (categories, category) {
+            (Some(categories), _) => serde_json::from_value(categories)?,
+            (None, Value::Null) => Vec::new(),
+            (None, category) => vec![serde_json::from_value(category)?],
+        }),
+        "railway" => MediumType::Railway(serde_json::from_value(attributes)?),
+        "waterway" => MediumType::Waterway(serde_json::from_value(attributes)?),
+        "airway" => MediumType::Airway(serde_json::from_value(attributes)?),
+        "space_trajectory" => MediumType::SpaceTrajectory(serde_json::from_value(attributes)?),
+        other => return Err(invalid(format!("unknown medium_type {other:?}"))),
+    };
+    Ok(medium)
+}
+
+/// Positions of a Point, LineString or MultiLineString, or of a Polygon's outer ring.
+fn positions(geometry: &Map<String, Value>) -> Result<Vec<Position>> {
+    let coordinates = geometry.get("coordinates").unwrap_or(&Value::Null);
+    let line = match geometry.get("type").and_then(Value::as_str) {
+        Some("Point") => vec![coordinates],
+        Some("LineString") => coordinates.as_array().into_iter().flatten().collect(),
+        Some("MultiLineString") => coordinates
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_array)
+            .flatten()
+            .collect(),
+        Some("Polygon") => coordinates
+            .get(0)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .collect(),
+        other => return Err(invalid(format!("unsupported geometry {other:?}"))),
+    };
+    line.into_iter()
+        .map(|c| {
+            match (
+                c.get(0).and_then(Value::as_f64),
+                c.get(1).and_then(Value::as_f64),
+            ) {
+                (Some(longitude), Some(latitude)) => Ok(Position {
+                    longitude,
+                    latitude,
+                }),
+                _ => Err(invalid("expected [longitude, latitude] coordinates")),
+            }
+        })
+        .collect()
+}
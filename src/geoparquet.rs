@@ -0,0 +1,275 @@
+use std::{fs::File, io::Write, path::Path, sync::Arc};
+
+use arrow_array::{
+    builder::{
+        BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, ListBuilder, StringBuilder,
+    },
+    Array, ArrayRef, BinaryArray, BooleanArray, Float64Array, Int64Array, ListArray, RecordBatch,
+    StringArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+    basic::Compression,
+    file::{metadata::KeyValue, properties::WriterProperties},
+};
+use serde_json::json;
+
+use crate::{
+    columns::{self, medium_type_name, MediumRow},
+    error::{KovachsError, Result},
+    types::medium::{BoundingBox, Medium, OsmType},
+    wkb,
+};
+
+/// Rows collected before they are handed to the Parquet writer as one batch.
+const BATCH_ROWS: usize = 8192;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParquetOptions {
+    /// Rows per row group. Smaller groups let readers skip more of a file when
+    /// filtering, larger ones compress better.
+    pub row_group_size: usize,
+    pub compression: Compression,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            row_group_size: 128 * 1024,
+            compression: Compression::SNAPPY,
+        }
+    }
+}
+
+/// Writes Mediums as GeoParquet: the [`MediumRow`] columns plus a WKB `geometry`
+/// column in longitude/latitude, with the `geo` metadata readers look for.
+pub struct GeoParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    builders: Builders,
+    bbox: Option<BoundingBox>,
+    geometry_types: [bool; 2],
+}
+
+impl<W: Write + Send> GeoParquetWriter<W> {
+    pub fn new(writer: W, options: ParquetOptions) -> Result<GeoParquetWriter<W>> {
+        let schema = schema();
+        let properties = WriterProperties::builder()
+            .set_max_row_group_row_count(Some(options.row_group_size.max(1)))
+            .set_compression(options.compression)
+            .build();
+        let writer = ArrowWriter::try_new(writer, schema.clone(), Some(properties))?;
+        Ok(GeoParquetWriter {
+            writer,
+            schema,
+            builders: Builders::default(),
+            bbox: None,
+            geometry_types: [false; 2],
+        })
+    }
+
+    pub fn write(&mut self, medium: &Medium) -> Result<()> {
+        let row = MediumRow::from_medium(medium)?;
+        let b = &mut self.builders;
+        b.osm_id.append_option(row.osm_id);
+        b.osm_type.append_option(row.osm_type.map(OsmType::name));
+        b.name.append_option(row.name);
+        b.medium_type.append_value(row.medium_type);
+        b.category.append_option(row.category);
+        b.is_one_way.append_value(row.is_one_way);
+        b.length.append_value(row.length);
+        b.node_refs.values().append_slice(&row.node_refs);
+        b.node_refs.append(true);
+        b.tags.append_option(row.tags);
+        b.attributes.append_option(row.attributes);
+        b.geometry
+            .append_option(wkb::encode(&medium.medium_positions));
+        b.rows += 1;
+
+        match medium.medium_positions.len() {
+            0 => {}
+            1 => self.geometry_types[0] = true,
+            _ => self.geometry_types[1] = true,
+        }
+        for p in medium.medium_positions.iter() {
+            self.bbox = Some(match self.bbox {
+                None => BoundingBox::new(p.longitude, p.latitude, p.longitude, p.latitude),
+                Some(b) => BoundingBox::new(
+                    b.min_lon.min(p.longitude),
+                    b.min_lat.min(p.latitude),
+                    b.max_lon.max(p.longitude),
+                    b.max_lat.max(p.latitude),
+                ),
+            });
+        }
+        if self.builders.rows >= BATCH_ROWS {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush_batch(&mut self) -> Result<()> {
+        if self.builders.rows == 0 {
+            return Ok(());
+        }
+        let batch = self.builders.finish(self.schema.clone())?;
+        Ok(self.writer.write(&batch)?)
+    }
+
+    /// Writes the remaining rows and the GeoParquet metadata, and closes the file.
+    pub fn finish(mut self) -> Result<()> {
+        self.flush_batch()?;
+        let geometry_types: Vec<&str> = ["Point", "LineString"]
+            .into_iter()
+            .zip(self.geometry_types)
+            .filter(|(_, used)| *used)
+            .map(|(t, _)| t)
+            .collect();
+        let mut geometry = json!({
+            "encoding": "WKB",
+            "geometry_types": geometry_types,
+        });
+        if let Some(b) = self.bbox {
+            geometry["bbox"] = json!([b.min_lon, b.min_lat, b.max_lon, b.max_lat]);
+        }
+        let geo = json!({
+            "version": "1.1.0",
+            "primary_column": "geometry",
+            "columns": {"geometry": geometry},
+        });
+        self.writer
+            .append_key_value_metadata(KeyValue::new("geo".to_string(), geo.to_string()));
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+pub fn write_geoparquet(path: &Path, mediums: &[Medium], options: ParquetOptions) -> Result<()> {
+    let mut writer = GeoParquetWriter::new(File::create(path)?, options)?;
+    for m in mediums {
+        writer.write(m)?;
+    }
+    writer.finish()
+}
+
+/// Reads Mediums back from a file written by [`GeoParquetWriter`].
+pub fn read_geoparquet(path: &Path) -> Result<Vec<Medium>> {
+    let reader =
+        ParquetRecordBatchReaderBuilder::try_new(File::open(path)?).and_then(|b| b.build())?;
+    let mut mediums = Vec::new();
+    for batch in reader {
+        let batch = batch?;
+        let osm_id: &Int64Array = column(&batch, "osm_id")?;
+        // Files written before these columns existed leave them out.
+        let osm_type: Option<&StringArray> = column(&batch, "osm_type").ok();
+        let name: &StringArray = column(&batch, "name")?;
+        let medium_type: &StringArray = column(&batch, "medium_type")?;
+        let category: &StringArray = column(&batch, "category")?;
+        let is_one_way: &BooleanArray = column(&batch, "is_one_way")?;
+        let length: &Float64Array = column(&batch, "length")?;
+        let node_refs: &ListArray = column(&batch, "node_refs")?;
+        let tags: Option<&StringArray> = column(&batch, "tags").ok();
+        let attributes: &StringArray = column(&batch, "attributes")?;
+        let geometry: &BinaryArray = column(&batch, "geometry")?;
+        for i in 0..batch.num_rows() {
+            let refs = node_refs.value(i);
+            let refs = refs
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .ok_or_else(|| invalid("node_refs is not a list of int64"))?;
+            let row = MediumRow {
+                osm_id: osm_id.is_valid(i).then(|| osm_id.value(i)),
+                osm_type: match osm_type {
+                    Some(t) if t.is_valid(i) => Some(columns::osm_type(t.value(i))?),
+                    _ => None,
+                },
+                name: name.is_valid(i).then(|| name.value(i).to_string()),
+                medium_type: medium_type_name(medium_type.value(i))?,
+                category: category.is_valid(i).then(|| category.value(i).to_string()),
+                is_one_way: is_one_way.value(i),
+                length: length.value(i),
+                node_refs: refs.values().to_vec(),
+                tags: tags
+                    .filter(|t| t.is_valid(i))
+                    .map(|t| t.value(i).to_string()),
+                attributes: attributes
+                    .is_valid(i)
+                    .then(|| attributes.value(i).to_string()),
+            };
+            let mut medium = row.into_medium()?;
+            if geometry.is_valid(i) {
+                medium.medium_positions = wkb::decode(geometry.value(i))?;
+            }
+            mediums.push(medium);
+        }
+    }
+    Ok(mediums)
+}
+
+fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("osm_id", DataType::Int64, true),
+        Field::new("osm_type", DataType::Utf8, true),
+        Field::new("name", DataType::Utf8, true),
+        Field::new("medium_type", DataType::Utf8, false),
+        Field::new("category", DataType::Utf8, true),
+        Field::new("is_one_way", DataType::Boolean, false),
+        Field::new("length", DataType::Float64, false),
+        Field::new(
+            "node_refs",
+            DataType::List(Arc::new(Field::new("item", DataType::Int64, true))),
+            false,
+        ),
+        Field::new("tags", DataType::Utf8, true),
+        Field::new("attributes", DataType::Utf8, true),
+        Field::new("geometry", DataType::Binary, true),
+    ]))
+}
+
+#[derive(Default)]
+struct Builders {
+    rows: usize,
+    osm_id: Int64Builder,
+    osm_type: StringBuilder,
+    name: StringBuilder,
+    medium_type: StringBuilder,
+    category: StringBuilder,
+    is_one_way: BooleanBuilder,
+    length: Float64Builder,
+    node_refs: ListBuilder<Int64Builder>,
+    tags: StringBuilder,
+    attributes: StringBuilder,
+    geometry: BinaryBuilder,
+}
+
+impl Builders {
+    fn finish(&mut self, schema: SchemaRef) -> Result<RecordBatch> {
+        self.rows = 0;
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.osm_id.finish()),
+            Arc::new(self.osm_type.finish()),
+            Arc::new(self.name.finish()),
+            Arc::new(self.medium_type.finish()),
+            Arc::new(self.category.finish()),
+            Arc::new(self.is_one_way.finish()),
+            Arc::new(self.length.finish()),
+            Arc::new(self.node_refs.finish()),
+            Arc::new(self.tags.finish()),
+            Arc::new(self.attributes.finish()),
+            Arc::new(self.geometry.finish()),
+        ];
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+fn column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a T> {
+    batch
+        .column_by_name(name)
+        .and_then(|c| c.as_any().downcast_ref::<T>())
+        .ok_or_else(|| invalid(format!("missing or mistyped column {name:?}")))
+}
+
+fn invalid(message: impl Into<String>) -> KovachsError {
+    KovachsError::InvalidData(message.into())
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::ErrorKind,
+    path::Path,
+};
+
+use rusqlite::{params, Connection};
+
+use crate::{
+    columns::MediumRow,
+    error::Result,
+    types::medium::{Medium, OsmType, Position},
+    wkb,
+};
+
+/// WGS 84, the only SRS Mediums are written in.
+const SRS_ID: i32 = 4326;
+
+/// `PRAGMA application_id` of a GeoPackage: "GPKG".
+const APPLICATION_ID: i32 = 0x4750_4B47;
+
+/// `PRAGMA user_version` of GeoPackage 1.4.0.
+const USER_VERSION: i32 = 10400;
+
+const SCHEMA: &str = r#"
+CREATE TABLE gpkg_spatial_ref_sys (
+    srs_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL PRIMARY KEY,
+    organization TEXT NOT NULL,
+    organization_coordsys_id INTEGER NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT
+);
+INSERT INTO gpkg_spatial_ref_sys VALUES
+    ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', 'undefined cartesian coordinate reference system'),
+    ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', 'undefined geographic coordinate reference system'),
+    ('WGS 84 geodetic', 4326, 'EPSG', 4326,
+     'GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AXIS["Latitude",NORTH],AXIS["Longitude",EAST],AUTHORITY["EPSG","4326"]]',
+     'longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid');
+CREATE TABLE gpkg_contents (
+    table_name TEXT NOT NULL PRIMARY KEY,
+    data_type TEXT NOT NULL,
+    identifier TEXT UNIQUE,
+    description TEXT DEFAULT '',
+    last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+    min_x DOUBLE,
+    min_y DOUBLE,
+    max_x DOUBLE,
+    max_y DOUBLE,
+    srs_id INTEGER,
+    CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+);
+CREATE TABLE gpkg_geometry_columns (
+    table_name TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    geometry_type_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL,
+    z TINYINT NOT NULL,
+    m TINYINT NOT NULL,
+    CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+    CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+    CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+);
+CREATE TABLE gpkg_extensions (
+    table_name TEXT,
+    column_name TEXT,
+    extension_name TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    CONSTRAINT ge_tce UNIQUE (table_name, column_name, extension_name)
+);
+CREATE TABLE mediums (
+    fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    geom LINESTRING NOT NULL,
+    osm_id INTEGER,
+    osm_type TEXT,
+    name TEXT,
+    medium_type TEXT NOT NULL,
+    category TEXT,
+    is_one_way BOOLEAN NOT NULL,
+    length DOUBLE NOT NULL,
+    node_refs TEXT NOT NULL,
+    tags TEXT,
+    attributes TEXT
+);
+CREATE TABLE nodes (
+    fid INTEGER PRIMARY KEY NOT NULL,
+    geom POINT NOT NULL,
+    mediums INTEGER NOT NULL
+);
+CREATE TABLE medium_nodes (
+    medium_fid INTEGER NOT NULL REFERENCES mediums(fid) ON DELETE CASCADE,
+    node_fid INTEGER NOT NULL REFERENCES nodes(fid) ON DELETE CASCADE,
+    sequence INTEGER NOT NULL,
+    PRIMARY KEY (medium_fid, sequence)
+);
+CREATE INDEX medium_nodes_node_fid ON medium_nodes(node_fid);
+INSERT INTO gpkg_contents (table_name, data_type, identifier, description, srs_id) VALUES
+    ('mediums', 'features', 'mediums', 'Roads, railways, waterways, airways and orbits', 4326),
+    ('nodes', 'features', 'nodes', 'Intersections and ends of the mediums; fid is the OSM node id', 4326),
+    ('medium_nodes', 'attributes', 'medium_nodes', 'Which nodes each medium passes, in order', NULL);
+INSERT INTO gpkg_geometry_columns VALUES
+    ('mediums', 'geom', 'LINESTRING', 4326, 0, 0),
+    ('nodes', 'geom', 'POINT', 4326, 0, 0);
+"#;
+
+/// What [`write_geopackage`] wrote.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GeoPackageSummary {
+    pub mediums: usize,
+    pub nodes: usize,
+    /// Mediums with fewer than two positions, which make no LineString.
+    pub skipped: usize,
+}
+
+/// Writes Mediums into a new GeoPackage at `path`, replacing any file there.
+///
+/// The `mediums` table holds a LineString per Medium with the [`MediumRow`]
+/// columns. `nodes` holds the OSM nodes where Mediums end or meet each other,
+/// keyed by node id, and `medium_nodes` links every Medium to the nodes along
+/// it. Both feature tables get the R-tree index QGIS and GDAL use for bounding
+/// box queries.
+pub fn write_geopackage(path: &Path, mediums: &[Medium]) -> Result<GeoPackageSummary> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut conn = Connection::open(path)?;
+    conn.pragma_update(None, "application_id", APPLICATION_ID)
+        .and_then(|_| conn.pragma_update(None, "user_version", USER_VERSION))
+        .and_then(|_| conn.pragma_update(None, "foreign_keys", true))?;
+
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    let mut summary = GeoPackageSummary::default();
+    let nodes = network_nodes(mediums);
+    {
+        let mut insert_node =
+            tx.prepare("INSERT INTO nodes (fid, geom, mediums) VALUES (?1, ?2, ?3)")?;
+        for (id, (position, uses)) in nodes.iter() {
+            let geom = geometry(std::slice::from_ref(position));
+            insert_node.execute(params![id, geom, uses])?;
+        }
+        summary.nodes = nodes.len();
+
+        let mut insert_medium = tx.prepare(
+            "INSERT INTO mediums (geom, osm_id, osm_type, name, medium_type, category, \
+                 is_one_way, length, node_refs, tags, attributes) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )?;
+        let mut insert_link = tx.prepare(
+            "INSERT INTO medium_nodes (medium_fid, node_fid, sequence) VALUES (?1, ?2, ?3)",
+        )?;
+        for m in mediums {
+            if m.medium_positions.len() < 2 {
+                summary.skipped += 1;
+                continue;
+            }
+            let row = MediumRow::from_medium(m)?;
+            insert_medium.execute(params![
+                geometry(&m.medium_positions),
+                row.osm_id,
+                row.osm_type.map(OsmType::name),
+                row.name,
+                row.medium_type,
+                row.category,
+                row.is_one_way,
+                row.length,
+                serde_json::to_string(&row.node_refs)?,
+                row.tags,
+                row.attributes,
+            ])?;
+            let fid = tx.last_insert_rowid();
+            let linked = m.osm_node_refs.iter().filter(|r| nodes.contains_key(r));
+            for (sequence, node) in linked.enumerate() {
+                insert_link.execute(params![fid, node, sequence as i64])?;
+            }
+            summary.mediums += 1;
+        }
+    }
+    for table in ["mediums", "nodes"] {
+        spatial_index(&tx, table)?;
+    }
+    tx.commit()?;
+    Ok(summary)
+}
+
+/// Nodes where a Medium starts or ends, or which more than one Medium (or one
+/// Medium twice) passes, with their position and how many times they are used.
+///
+/// Only Mediums whose positions are in step with their node refs count.
+fn network_nodes(mediums: &[Medium]) -> BTreeMap<i64, (Position, u32)> {
+    let in_step = || {
+        mediums.iter().filter(|m| {
+            m.medium_positions.len() >= 2 && m.osm_node_refs.len() == m.medium_positions.len()
+        })
+    };
+    let mut uses: HashMap<i64, u32> = HashMap::new();
+    for m in in_step() {
+        for r in m.osm_node_refs.iter() {
+            *uses.entry(*r).or_insert(0) += 1;
+        }
+    }
+    let mut nodes = BTreeMap::new();
+    for m in in_step() {
+        let last = m.osm_node_refs.len() - 1;
+        for (i, (r, p)) in m
+            .osm_node_refs
+            .iter()
+            .zip(m.medium_positions.iter())
+            .enumerate()
+        {
+            if i == 0 || i == last || uses[r] > 1 {
+                nodes.entry(*r).or_insert_with(|| (p.clone(), uses[r]));
+            }
+        }
+    }
+    nodes
+}
+
+/// A GeoPackage geometry blob: the `GP` header with the SRS id and, for
+/// LineStrings, the envelope, followed by little-endian WKB.
+fn geometry(positions: &[Position]) -> Option<Vec<u8>> {
+    let wkb = wkb::encode(positions)?;
+    let mut blob = Vec::with_capacity(8 + 32 + wkb.len());
+    blob.extend_from_slice(b"GP");
+    blob.push(0);
+    // Little-endian, with an [minx, maxx, miny, maxy] envelope unless it is a point.
+    let with_envelope = positions.len() > 1;
+    blob.push(if with_envelope {
+        0b0000_0011
+    } else {
+        0b0000_0001
+    });
+    blob.extend_from_slice(&SRS_ID.to_le_bytes());
+    if with_envelope {
+        let (mut min_x, mut max_x) = (f64::INFINITY, f64::NEG_INFINITY);
+        let (mut min_y, mut max_y) = (f64::INFINITY, f64::NEG_INFINITY);
+        for p in positions {
+            min_x = min_x.min(p.longitude);
+            max_x = max_x.max(p.longitude);
+            min_y = min_y.min(p.latitude);
+            max_y = max_y.max(p.latitude);
+        }
+        for v in [min_x, max_x, min_y, max_y] {
+            blob.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    blob.extend_from_slice(&wkb);
+    Some(blob)
+}
+
+/// Creates and fills the `gpkg_rtree_index` extension for `table`'s `geom` column,
+/// records the table's extent, and adds the triggers that keep the index up to
+/// date when the table is edited later.
+fn spatial_index(conn: &Connection, table: &str) -> rusqlite::Result<()> {
+    let rtree = format!("rtree_{table}_geom");
+    conn.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE {rtree} USING rtree(id, minx, maxx, miny, maxy);"
+    ))?;
+    // The R-tree stores single precision, so the extent is taken from the blobs.
+    let mut extent: Option<[f64; 4]> = None;
+    {
+        let mut select = conn.prepare(&format!("SELECT fid, geom FROM {table}"))?;
+        let mut insert = conn.prepare(&format!(
+            "INSERT INTO {rtree} (id, minx, maxx, miny, maxy) VALUES (?1, ?2, ?3, ?4, ?5)"
+        ))?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let fid: i64 = row.get(0)?;
+            let blob: Vec<u8> = row.get(1)?;
+            if let Some(e @ [min_x, max_x, min_y, max_y]) = envelope(&blob) {
+                insert.execute(params![fid, min_x, max_x, min_y, max_y])?;
+                extent = Some(match extent {
+                    None => e,
+                    Some(x) => [
+                        x[0].min(min_x),
+                        x[1].max(max_x),
+                        x[2].min(min_y),
+                        x[3].max(max_y),
+                    ],
+                });
+            }
+        }
+    }
+    if let Some([min_x, max_x, min_y, max_y]) = extent {
+        conn.execute(
+            "UPDATE gpkg_contents SET min_x = ?2, max_x = ?3, min_y = ?4, max_y = ?5 \
+             WHERE table_name = ?1",
+            params![table, min_x, max_x, min_y, max_y],
+        )?;
+    }
+    conn.execute(
+        "INSERT INTO gpkg_extensions VALUES (?1, 'geom', 'gpkg_rtree_index', \
+         'http://www.geopackage.org/spec120/#extension_rtree', 'write-only')",
+        [table],
+    )?;
+    conn.execute_batch(&rtree_triggers(table))
+}
+
+/// The envelope of a geometry blob written by [`geometry`], read back from its
+/// header or, for points, from the coordinates.
+fn envelope(blob: &[u8]) -> Option<[f64; 4]> {
+    let f64_at = |at: usize| Some(f64::from_le_bytes(blob.get(at..at + 8)?.try_into().ok()?));
+    if blob.get(3)? & 0b0000_1110 != 0 {
+        return Some([f64_at(8)?, f64_at(16)?, f64_at(24)?, f64_at(32)?]);
+    }
+    // 8 header bytes, then the WKB Point's byte order and type.
+    let (x, y) = (f64_at(8 + 5)?, f64_at(8 + 13)?);
+    Some([x, x, y, y])
+}
+
+/// The triggers the GeoPackage spec defines for the R-tree extension. They call
+/// the ST_* functions that GeoPackage readers provide, so they are only created
+/// once the index has been filled.
+fn rtree_triggers(t: &str) -> String {
+    format!(
+        r#"
+CREATE TRIGGER rtree_{t}_geom_insert AFTER INSERT ON {t}
+WHEN (new.geom NOT NULL AND NOT ST_IsEmpty(NEW.geom))
+BEGIN
+  INSERT OR REPLACE INTO rtree_{t}_geom VALUES (
+    NEW.fid, ST_MinX(NEW.geom), ST_MaxX(NEW.geom), ST_MinY(NEW.geom), ST_MaxY(NEW.geom)
+  );
+END;
+CREATE TRIGGER rtree_{t}_geom_update1 AFTER UPDATE OF geom ON {t}
+WHEN OLD.fid = NEW.fid AND (NEW.geom NOTNULL AND NOT ST_IsEmpty(NEW.geom))
+BEGIN
+  INSERT OR REPLACE INTO rtree_{t}_geom VALUES (
+    NEW.fid, ST_MinX(NEW.geom), ST_MaxX(NEW.geom), ST_MinY(NEW.geom), ST_MaxY(NEW.geom)
+  );
+END;
+CREATE TRIGGER rtree_{t}_geom_update2 AFTER UPDATE OF geom ON {t}
+WHEN OLD.fid = NEW.fid AND (NEW.geom ISNULL OR ST_IsEmpty(NEW.geom))
+BEGIN
+  DELETE FROM rtree_{t}_geom WHERE id = OLD.fid;
+END;
+CREATE TRIGGER rtree_{t}_geom_update3 AFTER UPDATE ON {t}
+WHEN OLD.fid != NEW.fid AND (NEW.geom NOTNULL AND NOT ST_IsEmpty(NEW.geom))
+BEGIN
+  DELETE FROM rtree_{t}_geom WHERE id = OLD.fid;
+  INSERT OR REPLACE INTO rtree_{t}_geom VALUES (
+    NEW.fid, ST_MinX(NEW.geom), ST_MaxX(NEW.geom), ST_MinY(NEW.geom), ST_MaxY(NEW.geom)
+  );
+END;
+CREATE TRIGGER rtree_{t}_geom_update4 AFTER UPDATE ON {t}
+WHEN OLD.fid != NEW.fid AND (NEW.geom ISNULL OR ST_IsEmpty(NEW.geom))
+BEGIN
+  DELETE FROM rtree_{t}_geom WHERE id IN (OLD.fid, NEW.fid);
+END;
+CREATE TRIGGER rtree_{t}_geom_delete AFTER DELETE ON {t}
+WHEN old.geom NOT NULL
+BEGIN
+  DELETE FROM rtree_{t}_geom WHERE id = OLD.fid;
+END;
+"#
+    )
+}
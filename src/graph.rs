@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::medium::{Medium, MediumType, Position, StreetCategory};
+
+/// An intersection or dead end: an OSM node where a Medium starts, ends or
+/// meets another Medium.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vertex {
+    pub osm_node_id: i64,
+    pub position: Position,
+}
+
+/// A directed piece of a Medium between two vertices.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    /// Index of the source Medium in the Vec the graph was built from.
+    pub medium: usize,
+    pub medium_osm_id: Option<i64>,
+    pub category: StreetCategory,
+    /// Length in meters.
+    pub length: f64,
+    /// First and last index into the source Medium's `medium_positions`.
+    /// `first > last` when the edge runs against the way's direction.
+    pub first: usize,
+    pub last: usize,
+}
+
+impl Edge {
+    /// The edge's geometry, in travel order.
+    pub fn positions<'a>(&self, mediums: &'a [Medium]) -> Vec<&'a Position> {
+        let positions = &mediums[self.medium].medium_positions;
+        if self.first <= self.last {
+            positions[self.first..=self.last].iter().collect()
+        } else {
+            positions[self.last..=self.first].iter().rev().collect()
+        }
+    }
+}
+
+/// Directed road network derived from highway Mediums.
+///
+/// Each Medium is split at every node it shares with another Medium (or with
+/// itself), so vertices are intersections and dead ends. Two-way Mediums give an
+/// edge in each direction, one-way Mediums only the forward one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoadGraph {
+    pub vertices: Vec<Vertex>,
+    pub edges: Vec<Edge>,
+    /// Edge ids leaving vertex `v` are `out_edges[out_index[v]..out_index[v + 1]]`.
+    out_index: Vec<usize>,
+    out_edges: Vec<usize>,
+    /// Edge ids entering vertex `v`, laid out like the outgoing ones.
+    in_index: Vec<usize>,
+    in_edges: Vec<usize>,
+    vertex_by_node: HashMap<i64, usize>,
+    /// Mediums left out because some of their node refs had no position.
+    pub skipped_mediums: usize,
+}
+
+/// Category used for an edge: the first one the Medium was tagged with. `None`
+/// for anything but a highway with a category, which includes the ways no
+/// mapping rule took.
+pub fn street_category(medium: &Medium) -> Option<StreetCategory> {
+    match &medium.medium_type {
+        MediumType::Highway(categories) => categories.first().copied(),
+        _ => None,
+    }
+}
+
+impl RoadGraph {
+    pub fn from_mediums(mediums: &[Medium]) -> RoadGraph {
+        let mut graph = RoadGraph::default();
+        let roads: Vec<(usize, &Medium, StreetCategory)> = mediums
+            .iter()
+            .enumerate()
+            .filter_map(|(i, m)| street_category(m).map(|c| (i, m, c)))
+            .filter(|(_, m, _)| {
+                // Positions are only in step with the refs when none went missing.
+                let usable =
+                    m.osm_node_refs.len() >= 2 && m.osm_node_refs.len() == m.medium_positions.len();
+                if !usable {
+                    graph.skipped_mediums += 1;
+                }
+                usable
+            })
+            .collect();
+
+        let mut uses: HashMap<i64, u32> = HashMap::new();
+        for (_, m, _) in roads.iter() {
+            for r in m.osm_node_refs.iter() {
+                *uses.entry(*r).or_insert(0) += 1;
+            }
+        }
+
+        for (i, m, category) in roads {
+            let refs = &m.osm_node_refs;
+            let last_ref = refs.len() - 1;
+            let mut start = 0;
+            for end in 1..=last_ref {
+                if end != last_ref && uses[&refs[end]] < 2 {
+                    continue;
+                }
+                let from = graph.vertex(refs[start], &m.medium_positions[start]);
+                let to = graph.vertex(refs[end], &m.medium_positions[end]);
+                let length = m.medium_positions[start..=end]
+                    .windows(2)
+                    .map(|w| w[0].haversine_distance(&w[1]))
+                    .sum();
+                let edge = Edge {
+                    from,
+                    to,
+                    medium: i,
+                    medium_osm_id: m.osm_id,
+                    category,
+                    length,
+                    first: start,
+                    last: end,
+                };
+                if !m.is_one_way {
+                    graph.edges.push(Edge {
+                        from: to,
+                        to: from,
+                        first: end,
+                        last: start,
+                        ..edge.clone()
+                    });
+                }
+                graph.edges.push(edge);
+                start = end;
+            }
+        }
+        graph.build_adjacency();
+        graph
+    }
+
+    fn vertex(&mut self, osm_node_id: i64, position: &Position) -> usize {
+        let vertices = &mut self.vertices;
+        *self.vertex_by_node.entry(osm_node_id).or_insert_with(|| {
+            vertices.push(Vertex {
+                osm_node_id,
+                position: position.clone(),
+            });
+            vertices.len() - 1
+        })
+    }
+
+    /// Counting sort of the edge ids by source and by target vertex.
+    fn build_adjacency(&mut self) {
+        let n = self.vertices.len();
+        let (out_index, out_edges) = group_edges(n, self.edges.iter().map(|e| e.from));
+        let (in_index, in_edges) = group_edges(n, self.edges.iter().map(|e| e.to));
+        self.out_index = out_index;
+        self.out_edges = out_edges;
+        self.in_index = in_index;
+        self.in_edges = in_edges;
+    }
+
+    pub fn vertex_for_node(&self, osm_node_id: i64) -> Option<usize> {
+        self.vertex_by_node.get(&osm_node_id).copied()
+    }
+
+    /// Ids of the edges leaving `v`.
+    pub fn outgoing(&self, v: usize) -> &[usize] {
+        &self.out_edges[self.out_index[v]..self.out_index[v + 1]]
+    }
+
+    /// Ids of the edges entering `v`.
+    pub fn incoming(&self, v: usize) -> &[usize] {
+        &self.in_edges[self.in_index[v]..self.in_index[v + 1]]
+    }
+}
+
+fn group_edges(
+    vertices: usize,
+    keys: impl Iterator<Item = usize> + Clone,
+) -> (Vec<usize>, Vec<usize>) {
+    let mut index = vec![0; vertices + 1];
+    for k in keys.clone() {
+        index[k + 1] += 1;
+    }
+    for v in 0..vertices {
+        index[v + 1] += index[v];
+    }
+    let mut next = index.clone();
+    let mut edges = vec![0; index[vertices]];
+    for (e, k) in keys.enumerate() {
+        edges[next[k]] = e;
+        next[k] += 1;
+    }
+    (index, edges)
+}
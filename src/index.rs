@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    types::medium::{BoundingBox, Medium, Position, EARTH_RADIUS_M},
+};
+
+/// Meters per degree of latitude.
+const METERS_PER_DEGREE: f64 = EARTH_RADIUS_M * std::f64::consts::PI / 180.0;
+
+/// One straight piece of a Medium's geometry, as stored in the R-tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedSegment {
+    /// Index of the Medium in the Vec the index was built from.
+    pub medium: usize,
+    /// Index of the segment's first position in `medium_positions`.
+    pub segment: usize,
+    from: [f64; 2],
+    to: [f64; 2],
+}
+
+impl IndexedSegment {
+    fn ends(&self) -> (Position, Position) {
+        (
+            Position {
+                longitude: self.from[0],
+                latitude: self.from[1],
+            },
+            Position {
+                longitude: self.to[0],
+                latitude: self.to[1],
+            },
+        )
+    }
+}
+
+impl RTreeObject for IndexedSegment {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_corners(self.from, self.to)
+    }
+}
+
+impl PointDistance for IndexedSegment {
+    /// Squared distance in degrees, only used to order the tree search.
+    fn distance_2(&self, point: &[f64; 2]) -> f64 {
+        let (dx, dy) = (self.to[0] - self.from[0], self.to[1] - self.from[1]);
+        let len_2 = dx * dx + dy * dy;
+        let t = if len_2 == 0.0 {
+            0.0
+        } else {
+            (((point[0] - self.from[0]) * dx + (point[1] - self.from[1]) * dy) / len_2)
+                .clamp(0.0, 1.0)
+        };
+        let (x, y) = (
+            self.from[0] + t * dx - point[0],
+            self.from[1] + t * dy - point[1],
+        );
+        x * x + y * y
+    }
+}
+
+/// Where a query point comes closest to a Medium.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediumHit {
+    /// Index of the Medium in the Vec the index was built from.
+    pub medium: usize,
+    /// Index of the closest segment's first position in `medium_positions`.
+    pub segment: usize,
+    /// Closest point on the Medium.
+    pub closest: Position,
+    /// How far along the segment `closest` lies, from 0 to 1.
+    pub fraction: f64,
+    /// Distance from the query point to `closest` in meters.
+    pub distance: f64,
+}
+
+/// R-tree over the segments of a set of Mediums.
+///
+/// Hits refer to Mediums by their position in the Vec the index was built from,
+/// so a saved index is only valid next to the output it was built with.
+#[derive(Serialize, Deserialize)]
+pub struct MediumIndex {
+    medium_count: usize,
+    tree: RTree<IndexedSegment>,
+}
+
+impl MediumIndex {
+    pub fn build(mediums: &[Medium]) -> MediumIndex {
+        let segments = mediums
+            .iter()
+            .enumerate()
+            .flat_map(|(i, m)| {
+                let positions = &m.medium_positions;
+                // A single position still needs to be found, as a zero-length segment.
+                let pairs: Vec<(usize, &Position, &Position)> = if positions.len() == 1 {
+                    vec![(0, &positions[0], &positions[0])]
+                } else {
+                    positions
+                        .windows(2)
+                        .enumerate()
+                        .map(|(s, w)| (s, &w[0], &w[1]))
+                        .collect()
+                };
+                pairs
+                    .into_iter()
+                    .map(move |(segment, a, b)| IndexedSegment {
+                        medium: i,
+                        segment,
+                        from: [a.longitude, a.latitude],
+                        to: [b.longitude, b.latitude],
+                    })
+            })
+            .collect();
+        MediumIndex {
+            medium_count: mediums.len(),
+            tree: RTree::bulk_load(segments),
+        }
+    }
+
+    /// Number of Mediums the index was built from.
+    pub fn medium_count(&self) -> usize {
+        self.medium_count
+    }
+
+    /// Indices of the Mediums with at least one segment intersecting `bbox`, sorted.
+    pub fn in_bbox(&self, bbox: &BoundingBox) -> Vec<usize> {
+        let envelope =
+            AABB::from_corners([bbox.min_lon, bbox.min_lat], [bbox.max_lon, bbox.max_lat]);
+        let mut mediums: Vec<usize> = self
+            .tree
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|s| s.medium)
+            .collect();
+        mediums.sort_unstable();
+        mediums.dedup();
+        mediums
+    }
+
+    /// The `k` Mediums closest to `pos`, nearest first.
+    pub fn nearest(&self, pos: &Position, k: usize) -> Vec<MediumHit> {
+        if k == 0 {
+            return vec![];
+        }
+        let mut best: HashMap<usize, MediumHit> = HashMap::new();
+        let mut kth_distance = f64::INFINITY;
+        for (segment, distance_2) in self
+            .tree
+            .nearest_neighbor_iter_with_distance_2(&[pos.longitude, pos.latitude])
+        {
+            // The tree searches in plain degrees; once even the shortest possible
+            // metric distance of the next segment is past the k-th hit we are done.
+            let degrees = distance_2.sqrt();
+            let max_lat = (pos.latitude.abs() + degrees).min(90.0);
+            let lower_bound = degrees * METERS_PER_DEGREE * max_lat.to_radians().cos();
+            if best.len() >= k && lower_bound > kth_distance {
+                break;
+            }
+            let hit = hit_for(pos, segment);
+            match best.get(&hit.medium) {
+                Some(b) if b.distance <= hit.distance => {}
+                _ => {
+                    best.insert(hit.medium, hit);
+                }
+            }
+            if best.len() >= k {
+                let mut distances: Vec<f64> = best.values().map(|h| h.distance).collect();
+                distances.sort_unstable_by(f64::total_cmp);
+                kth_distance = distances[k - 1];
+            }
+        }
+        let mut hits: Vec<MediumHit> = best.into_values().collect();
+        hits.sort_unstable_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits.truncate(k);
+        hits
+    }
+
+    /// Every Medium within `meters` of `pos`, nearest first.
+    pub fn within_distance(&self, pos: &Position, meters: f64) -> Vec<MediumHit> {
+        let d_lat = meters / METERS_PER_DEGREE;
+        let max_lat = (pos.latitude.abs() + d_lat).min(89.9);
+        let d_lon = (d_lat / max_lat.to_radians().cos()).min(180.0);
+        let envelope = AABB::from_corners(
+            [pos.longitude - d_lon, pos.latitude - d_lat],
+            [pos.longitude + d_lon, pos.latitude + d_lat],
+        );
+        let mut best: HashMap<usize, MediumHit> = HashMap::new();
+        for segment in self.tree.locate_in_envelope_intersecting(&envelope) {
+            let hit = hit_for(pos, segment);
+            if hit.distance > meters {
+                continue;
+            }
+            match best.get(&hit.medium) {
+                Some(b) if b.distance <= hit.distance => {}
+                _ => {
+                    best.insert(hit.medium, hit);
+                }
+            }
+        }
+        let mut hits: Vec<MediumHit> = best.into_values().collect();
+        hits.sort_unstable_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<MediumIndex> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+/// Where the index for a JSON output is kept: `kenya.json` -> `kenya.index.json`.
+pub fn index_path_for(out_file: &Path) -> PathBuf {
+    out_file.with_extension("index.json")
+}
+
+fn hit_for(pos: &Position, segment: &IndexedSegment) -> MediumHit {
+    let (a, b) = segment.ends();
+    let (closest, distance, fraction) = pos.project_onto_segment(&a, &b);
+    MediumHit {
+        medium: segment.medium,
+        segment: segment.segment,
+        closest,
+        fraction,
+        distance,
+    }
+}
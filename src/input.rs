@@ -0,0 +1,106 @@
+mod xml;
+
+use std::path::Path;
+
+use osmpbf::{RelMemberType, Relation, Way};
+
+pub use xml::{ChangeAction, ChangeReader, XmlElement, XmlNode, XmlReader, XmlRelation, XmlWay};
+
+/// The file formats OSM data can be read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Pbf,
+    Xml,
+    XmlGzip,
+    XmlBzip2,
+}
+
+impl InputFormat {
+    /// The format of an OSM file going by its name: `*.osm.pbf`, `*.osm`,
+    /// `*.osm.gz` or `*.osm.bz2`. `None` for anything else.
+    pub fn from_path(path: &Path) -> Option<InputFormat> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".pbf") {
+            Some(InputFormat::Pbf)
+        } else if name.ends_with(".osm") {
+            Some(InputFormat::Xml)
+        } else if name.ends_with(".osm.gz") {
+            Some(InputFormat::XmlGzip)
+        } else if name.ends_with(".osm.bz2") {
+            Some(InputFormat::XmlBzip2)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_xml(self) -> bool {
+        self != InputFormat::Pbf
+    }
+}
+
+/// Kind of a relation member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberType {
+    Node,
+    Way,
+    Relation,
+}
+
+/// A relation member, whichever format it was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Member<'a> {
+    pub member_type: MemberType,
+    pub member_id: i64,
+    pub role: &'a str,
+}
+
+/// A way, whichever format it was read from.
+pub trait OsmWay {
+    fn id(&self) -> i64;
+    fn refs(&self) -> impl Iterator<Item = i64> + '_;
+    fn tags(&self) -> impl Iterator<Item = (&str, &str)> + Clone + '_;
+}
+
+/// A relation, whichever format it was read from.
+pub trait OsmRelation {
+    fn id(&self) -> i64;
+    fn tags(&self) -> impl Iterator<Item = (&str, &str)> + Clone + '_;
+    fn members(&self) -> impl Iterator<Item = Member<'_>> + '_;
+}
+
+impl OsmWay for Way<'_> {
+    fn id(&self) -> i64 {
+        Way::id(self)
+    }
+
+    fn refs(&self) -> impl Iterator<Item = i64> + '_ {
+        Way::refs(self)
+    }
+
+    fn tags(&self) -> impl Iterator<Item = (&str, &str)> + Clone + '_ {
+        Way::tags(self)
+    }
+}
+
+impl OsmRelation for Relation<'_> {
+    fn id(&self) -> i64 {
+        Relation::id(self)
+    }
+
+    fn tags(&self) -> impl Iterator<Item = (&str, &str)> + Clone + '_ {
+        Relation::tags(self)
+    }
+
+    fn members(&self) -> impl Iterator<Item = Member<'_>> + '_ {
+        Relation::members(self).map(|m| Member {
+            member_type: match m.member_type {
+                RelMemberType::Node => MemberType::Node,
+                RelMemberType::Way => MemberType::Way,
+                RelMemberType::Relation => MemberType::Relation,
+            },
+            member_id: m.member_id,
+            // Roles that are not valid UTF-8 are treated as empty.
+            role: m.role().unwrap_or_default(),
+        })
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, ErrorKind, Read},
+    path::Path,
+};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader, XmlVersion,
+};
+
+use super::{InputFormat, Member, MemberType, OsmRelation, OsmWay};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlNode {
+    pub id: i64,
+    pub decimicro_lon: i32,
+    pub decimicro_lat: i32,
+    pub tags: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlWay {
+    pub id: i64,
+    pub refs: Vec<i64>,
+    pub tags: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlRelation {
+    pub id: i64,
+    pub members: Vec<(MemberType, i64, String)>,
+    pub tags: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlElement {
+    Node(XmlNode),
+    Way(XmlWay),
+    Relation(XmlRelation),
+}
+
+impl OsmWay for XmlWay {
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn refs(&self) -> impl Iterator<Item = i64> + '_ {
+        self.refs.iter().copied()
+    }
+
+    fn tags(&self) -> impl Iterator<Item = (&str, &str)> + Clone + '_ {
+        self.tags.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl OsmRelation for XmlRelation {
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn tags(&self) -> impl Iterator<Item = (&str, &str)> + Clone + '_ {
+        self.tags.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    fn members(&self) -> impl Iterator<Item = Member<'_>> + '_ {
+        self.members
+            .iter()
+            .map(|(member_type, member_id, role)| Member {
+                member_type: *member_type,
+                member_id: *member_id,
+                role,
+            })
+    }
+}
+
+/// Streams the nodes, ways and relations of an OSM XML file, e.g. a JOSM export
+/// or an Overpass API response.
+///
+/// Elements JOSM marks with `action="delete"` and ones with `visible="false"`
+/// are skipped, as are nodes without a location.
+pub struct XmlReader<R> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    /// The osmChange block being read, if any.
+    action: Option<ChangeAction>,
+}
+
+impl XmlReader<Box<dyn BufRead + Send>> {
+    /// Opens an `*.osm`, `*.osm.gz` or `*.osm.bz2` file, or an osmChange file
+    /// compressed the same way.
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let name = path.to_string_lossy().to_ascii_lowercase();
+        let format = if name.ends_with(".gz") {
+            InputFormat::XmlGzip
+        } else if name.ends_with(".bz2") {
+            InputFormat::XmlBzip2
+        } else {
+            InputFormat::Xml
+        };
+        Ok(XmlReader::decompressed(File::open(path)?, format))
+    }
+
+    /// Reads XML from `reader`, decompressing it first for the gzip and bzip2 formats.
+    pub fn decompressed(reader: impl Read + Send + 'static, format: InputFormat) -> Self {
+        let reader: Box<dyn BufRead + Send> = match format {
+            InputFormat::XmlGzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+            InputFormat::XmlBzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+            InputFormat::Xml | InputFormat::Pbf => Box::new(BufReader::new(reader)),
+        };
+        XmlReader::new(reader)
+    }
+}
+
+impl<R: BufRead> XmlReader<R> {
+    pub fn new(reader: R) -> XmlReader<R> {
+        let mut reader = Reader::from_reader(reader);
+        reader.config_mut().trim_text(true);
+        XmlReader {
+            reader,
+            buf: Vec::new(),
+            action: None,
+        }
+    }
+
+    /// Reads an osmChange file instead, yielding every element with the block it
+    /// is in.
+    pub fn changes(self) -> ChangeReader<R> {
+        ChangeReader { xml: self }
+    }
+
+    /// The next element, and whether it is marked deleted, invisible or has no
+    /// location.
+    fn next_element(&mut self) -> io::Result<Option<(XmlElement, bool)>> {
+        // The element being read, and whether it is to be dropped at its end.
+        let mut current: Option<(XmlElement, bool)> = None;
+        loop {
+            self.buf.clear();
+            let (start, empty) = match self.reader.read_event_into(&mut self.buf) {
+                Ok(Event::Start(e)) => (e, false),
+                Ok(Event::Empty(e)) => (e, true),
+                Ok(Event::End(e)) => {
+                    match e.name().as_ref() {
+                        "node" | "way" | "relation" => {
+                            if let Some(current) = current.take() {
+                                return Ok(Some(current));
+                            }
+                        }
+                        "create" | "modify" | "delete" => self.action = None,
+                        _ => (),
+                    }
+                    continue;
+                }
+                Ok(Event::Eof) => return Ok(None),
+                Ok(_) => continue,
+                Err(e) => return Err(invalid(e)),
+            };
+            match (start.name().as_ref(), current.as_mut()) {
+                ("node" | "way" | "relation", _) => {
+                    let element = open_element(&start)?;
+                    if !empty {
+                        current = Some(element);
+                    } else {
+                        return Ok(Some(element));
+                    }
+                }
+                ("create", None) if !empty => self.action = Some(ChangeAction::Create),
+                ("modify", None) if !empty => self.action = Some(ChangeAction::Modify),
+                ("delete", None) if !empty => self.action = Some(ChangeAction::Delete),
+                ("tag", Some((element, _))) => {
+                    let tag = (attribute(&start, "k")?, attribute(&start, "v")?);
+                    if let (Some(k), Some(v)) = tag {
+                        match element {
+                            XmlElement::Node(n) => n.tags.push((k, v)),
+                            XmlElement::Way(w) => w.tags.push((k, v)),
+                            XmlElement::Relation(r) => r.tags.push((k, v)),
+                        }
+                    }
+                }
+                ("nd", Some((XmlElement::Way(way), _))) => {
+                    way.refs.push(required(&start, "ref")?);
+                }
+                ("member", Some((XmlElement::Relation(relation), _))) => {
+                    let member_type = match attribute(&start, "type")?.as_deref() {
+                        Some("node") => MemberType::Node,
+                        Some("way") => MemberType::Way,
+                        Some("relation") => MemberType::Relation,
+                        other => return Err(invalid(format!("unknown member type {other:?}"))),
+                    };
+                    let role = attribute(&start, "role")?.unwrap_or_default();
+                    relation
+                        .members
+                        .push((member_type, required(&start, "ref")?, role));
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for XmlReader<R> {
+    type Item = io::Result<XmlElement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_element() {
+                Ok(Some((_, true))) => continue,
+                Ok(Some((element, false))) => return Some(Ok(element)),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// What an osmChange file does with an element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeAction {
+    Create,
+    Modify,
+    Delete,
+}
+
+/// Streams the elements of an osmChange (`*.osc`) file with their action.
+///
+/// Deleted elements are yielded as they are written, usually with no tags; other
+/// nodes without a location are skipped.
+pub struct ChangeReader<R> {
+    xml: XmlReader<R>,
+}
+
+impl<R: BufRead> Iterator for ChangeReader<R> {
+    type Item = io::Result<(ChangeAction, XmlElement)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (element, skip) = match self.xml.next_element() {
+                Ok(Some(next)) => next,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            match self.xml.action {
+                Some(ChangeAction::Delete) => return Some(Ok((ChangeAction::Delete, element))),
+                Some(action) if !skip => return Some(Ok((action, element))),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// The element a `<node>`, `<way>` or `<relation>` tag starts, and whether it is
+/// to be skipped.
+fn open_element(start: &BytesStart) -> io::Result<(XmlElement, bool)> {
+    let id = required(start, "id")?;
+    let skip = attribute(start, "action")?.as_deref() == Some("delete")
+        || attribute(start, "visible")?.as_deref() == Some("false");
+    let element = match start.name().as_ref() {
+        "node" => {
+            let (Some(lon), Some(lat)) = (attribute(start, "lon")?, attribute(start, "lat")?)
+            else {
+                return Ok((node(id, 0, 0), true));
+            };
+            node(id, decimicro(&lon)?, decimicro(&lat)?)
+        }
+        "way" => XmlElement::Way(XmlWay {
+            id,
+            refs: Vec::new(),
+            tags: Vec::new(),
+        }),
+        _ => XmlElement::Relation(XmlRelation {
+            id,
+            members: Vec::new(),
+            tags: Vec::new(),
+        }),
+    };
+    Ok((element, skip))
+}
+
+fn node(id: i64, decimicro_lon: i32, decimicro_lat: i32) -> XmlElement {
+    XmlElement::Node(XmlNode {
+        id,
+        decimicro_lon,
+        decimicro_lat,
+        tags: Vec::new(),
+    })
+}
+
+/// A coordinate in degrees as the 100 nanodegree units PBF files use.
+fn decimicro(degrees: &str) -> io::Result<i32> {
+    let degrees: f64 = degrees
+        .parse()
+        .map_err(|_| invalid(format!("bad coordinate {degrees:?}")))?;
+    Ok((degrees * 1e7).round() as i32)
+}
+
+fn attribute(start: &BytesStart, name: &str) -> io::Result<Option<String>> {
+    match start.try_get_attribute(name).map_err(invalid)? {
+        Some(a) => Ok(Some(
+            a.normalized_value(XmlVersion::Implicit1_0)
+                .map_err(invalid)?
+                .into_owned(),
+        )),
+        None => Ok(None),
+    }
+}
+
+fn required(start: &BytesStart, name: &str) -> io::Result<i64> {
+    let value = attribute(start, name)?
+        .ok_or_else(|| invalid(format!("<{}> without {}", start.name().as_ref(), name)))?;
+    value
+        .parse()
+        .map_err(|_| invalid(format!("bad id {value:?}")))
+}
+
+fn invalid(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, error)
+}
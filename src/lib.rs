@@ -0,0 +1,29 @@
+pub mod airway;
+pub mod area;
+pub mod columns;
+pub mod dataset;
+pub mod error;
+pub mod extract;
+pub mod fgb;
+pub mod geojson;
+pub mod geoparquet;
+pub mod gpkg;
+pub mod graph;
+pub mod index;
+pub mod input;
+pub mod mapping;
+pub mod orbit;
+pub mod postgis;
+pub mod railway;
+pub mod relation;
+pub mod resolve;
+pub mod routing;
+pub mod stats;
+pub mod store;
+pub mod tagfilter;
+pub mod tiles;
+pub mod types;
+pub mod update;
+pub mod validate;
+pub mod waterway;
+pub mod wkb;
@@ -1,549 +1,704 @@
-pub mod types;
+mod cli;
 
 use std::{
-    borrow::Borrow, fmt::Debug, fs::File, io::{BufWriter, Write}, ops::Deref, sync::{Arc, Mutex}, time::SystemTime, vec
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use osmpbf::{Element, ElementReader, IndexedReader};
-use rayon::iter::{
-    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelExtend,
-    ParallelIterator,
+use clap::Parser;
+use cli::{exit_code, Cli, Command, Filters, Format, OutputOptions, RouteAlgorithm, RouteMetric};
+use osm_kovachs::{
+    area::{Area, AreaMode},
+    dataset,
+    extract::{count_elements, MediumExtractor},
+    fgb::write_flatgeobuf,
+    geoparquet::{write_geoparquet, ParquetOptions},
+    gpkg::write_geopackage,
+    graph::RoadGraph,
+    index::{index_path_for, MediumIndex},
+    input::InputFormat,
+    mapping::TagMapping,
+    orbit::{self, TimeWindow},
+    postgis::{write_postgis, PostgisOptions},
+    routing::{
+        ch::{hierarchy_path_for, ChQuery, ContractionHierarchy},
+        Algorithm, Metric, RouteOptions, Router,
+    },
+    stats::DatasetStats,
+    tagfilter::TagFilter,
+    tiles::{write_tiles, TileOptions, TileWriter},
+    types::medium::{Medium, Position},
+    update::{apply_changes, ChangeSet},
+    validate::validate,
 };
-use types::medium::{Medium, MediumType, OsmNode, Position, StreetCategory};
 
-fn main() {
-    println!("Reading command line args");
-    let arg1 = std::env::args_os()
-        .nth(1)
-        .expect("need a *.osm.pbf file as argument");
-    let arg2 = std::env::args_os()
-        .nth(2)
-        .expect("Need a *.json file as an argument");
-    let path: &std::path::Path = std::path::Path::new(&arg1);
-    let out_file = std::path::Path::new(&arg2);
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Some(threads) = cli.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            eprintln!("{e}");
+            return ExitCode::from(exit_code::FAILURE);
+        }
+    }
+    match cli.command {
+        Command::Count { input, filter } => run_count(&input, filter.as_ref()),
+        Command::Extract {
+            input,
+            output,
+            format,
+            output_options,
+            filters,
+            node_store,
+            no_index,
+        } => {
+            let work_dir = output
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            let area = match read_area(&filters) {
+                Ok(area) => area,
+                Err(code) => return code,
+            };
+            let mapping = match read_mapping(&filters) {
+                Ok(mapping) => mapping,
+                Err(code) => return code,
+            };
+            let tag_filter = filters.filter.clone();
+            let mut extractor = MediumExtractor::from_path(&input)
+                .work_dir(work_dir)
+                .filter(move |m| filters.keep(&m.medium_type))
+                .verbose(true);
+            if let Some(tag_filter) = tag_filter {
+                extractor = extractor.tag_filter(tag_filter);
+            }
+            if let Some(mapping) = mapping {
+                extractor = extractor.mapping(mapping);
+            }
+            if let Some((area, mode)) = area {
+                extractor = extractor.area(area, mode);
+            }
+            if let Some(node_store) = node_store {
+                extractor = extractor.store_kind(node_store.into());
+            }
+            let extracted = extractor.extract();
+            let mediums = match extracted {
+                Ok(mediums) => mediums,
+                Err(e) => {
+                    eprintln!("{}: {e}", input.display());
+                    return ExitCode::from(exit_code::FAILURE);
+                }
+            };
+            if let Err(code) = write_output(&output, format, &output_options, &mediums) {
+                return code;
+            }
+            if !no_index {
+                if let Err(code) = write_medium_index(&output, format, &mediums) {
+                    return code;
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Command::Update {
+            input,
+            changes,
+            output,
+            format,
+            output_options,
+            filters,
+            no_index,
+        } => {
+            let mediums = match run_update(&input, &changes, &filters) {
+                Ok(mediums) => mediums,
+                Err(code) => return code,
+            };
+            if let Err(code) = write_output(&output, format, &output_options, &mediums) {
+                return code;
+            }
+            if !no_index {
+                if let Err(code) = write_medium_index(&output, format, &mediums) {
+                    return code;
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Command::Orbit {
+            input,
+            output,
+            format,
+            output_options,
+            start,
+            minutes,
+            step,
+            no_index,
+        } => {
+            let start = start.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Clock may have gone backwards")
+                    .as_secs_f64()
+            });
+            let window = TimeWindow::new(start, start + minutes * 60.0, step);
+            let mediums = match run_orbit(&input, &window) {
+                Ok(mediums) => mediums,
+                Err(code) => return code,
+            };
+            if let Err(code) = write_output(&output, format, &output_options, &mediums) {
+                return code;
+            }
+            if !no_index {
+                if let Err(code) = write_medium_index(&output, format, &mediums) {
+                    return code;
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Command::Stats { input, filters } => {
+            let mut mediums = match read_dataset(&input, &filters) {
+                Ok(mediums) => mediums,
+                Err(code) => return code,
+            };
+            if let Err(code) = apply_filters(&filters, &mut mediums) {
+                return code;
+            }
+            println!("{}", DatasetStats::collect(&mediums));
+            ExitCode::SUCCESS
+        }
+        Command::Route {
+            input,
+            from,
+            to,
+            algorithm,
+            metric,
+            output,
+        } => run_route(
+            &input,
+            &from,
+            &to,
+            algorithm,
+            metric.into(),
+            output.as_deref(),
+        ),
+        Command::Contract { input, metric } => run_contract(&input, &metric),
+        Command::Distances {
+            input,
+            from,
+            to,
+            metric,
+            output,
+        } => run_distances(&input, &from, &to, metric.into(), output.as_deref()),
+        Command::Export {
+            input,
+            output,
+            format,
+            output_options,
+            filters,
+        } => {
+            let mut mediums = match read_dataset(&input, &filters) {
+                Ok(mediums) => mediums,
+                Err(code) => return code,
+            };
+            if let Err(code) = apply_filters(&filters, &mut mediums) {
+                return code;
+            }
+            match write_output(&output, format, &output_options, &mediums) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(code) => code,
+            }
+        }
+        Command::Tile {
+            input,
+            output,
+            min_zoom,
+            max_zoom,
+            filters,
+        } => {
+            let mut mediums = match read_dataset(&input, &filters) {
+                Ok(mediums) => mediums,
+                Err(code) => return code,
+            };
+            if let Err(code) = apply_filters(&filters, &mut mediums) {
+                return code;
+            }
+            let options = TileOptions {
+                min_zoom,
+                max_zoom: max_zoom.max(min_zoom),
+                ..TileOptions::default()
+            };
+            run_tile(&output, &mediums, &options)
+        }
+        Command::Validate { input } => run_validate(&input),
+    }
+}
 
-    println!("Reading OSM PBF File: {:#?}", path);
-    let path_str: &str = "/hdd/Data/osm/kenya-latest.osm.pbf";
-    // count_ways_kenya(path_str)
-    // count_everything(path);
-    // parse_all_to_medium(path);
-    // par_vec_count_everything(path);
-    let mediums_w_refs = par_parse_to_medium(path, out_file);
-    par_parse_to_medium_w_pos(path, out_file, mediums_w_refs)
+/// Drops the Mediums the filters reject, and restricts the rest to the area.
+fn apply_filters(filters: &Filters, mediums: &mut Vec<Medium>) -> Result<(), ExitCode> {
+    mediums.retain(|m| filters.keep(&m.medium_type));
+    if let Some((area, mode)) = read_area(filters)? {
+        area.restrict(mediums, mode);
+    }
+    Ok(())
 }
 
-fn count_ways_kenya(path_str: &str) {
-    let reader = ElementReader::from_path(path_str).unwrap();
-    let mut ways = 0_u64;
+fn read_mapping(filters: &Filters) -> Result<Option<TagMapping>, ExitCode> {
+    filters.mapping().map_err(|e| {
+        let path = filters.mapping.as_deref().unwrap_or(Path::new("--mapping"));
+        eprintln!("{}: {e}", path.display());
+        ExitCode::from(exit_code::FAILURE)
+    })
+}
 
-    // Increment the counter by one for each way.
-    reader
-        .for_each(|element| {
-            if let Element::Way(w) = element {
-                eprintln!("{}", format!("Counting way: {}", w.id()));
-                ways += 1;
-            }
-        })
-        .unwrap();
+fn read_area(filters: &Filters) -> Result<Option<(Area, AreaMode)>, ExitCode> {
+    filters.area().map_err(|e| {
+        let path = filters.polygon.as_deref().unwrap_or(Path::new("--bbox"));
+        eprintln!("{}: {e}", path.display());
+        ExitCode::from(exit_code::FAILURE)
+    })
+}
 
-    println!("{ways}: ways in file: {path_str}");
+/// A Medium dataset written by `extract`, or an OSM file extracted in memory
+/// with the tag filter and mapping from `filters`.
+fn read_dataset(input: &Path, filters: &Filters) -> Result<Vec<Medium>, ExitCode> {
+    let read = if InputFormat::from_path(input).is_some() {
+        let mut extractor = MediumExtractor::from_path(input);
+        if let Some(tag_filter) = filters.filter.as_ref() {
+            extractor = extractor.tag_filter(tag_filter.clone());
+        }
+        if let Some(mapping) = read_mapping(filters)? {
+            extractor = extractor.mapping(mapping);
+        }
+        extractor.extract().map_err(|e| e.to_string())
+    } else if filters.filter.is_some() {
+        Err(String::from(
+            "--filter needs an OSM file, not a Medium dataset",
+        ))
+    } else if filters.mapping.is_some() {
+        Err(String::from(
+            "--mapping needs an OSM file, not a Medium dataset",
+        ))
+    } else if !filters.keep_tags.is_empty() {
+        Err(String::from(
+            "--keep-tags needs an OSM file, not a Medium dataset",
+        ))
+    } else {
+        dataset::read(input).map_err(|e| e.to_string())
+    };
+    read.map_err(|e| {
+        eprintln!("{}: {e}", input.display());
+        ExitCode::from(exit_code::FAILURE)
+    })
 }
 
-fn count_everything(path: &std::path::Path) {
+fn run_tile(out: &Path, mediums: &[Medium], options: &TileOptions) -> ExitCode {
     let start_time = SystemTime::now();
-    let reader = ElementReader::from_path(path).unwrap();
-    println!("Counting...");
-    match reader.par_map_reduce(
-        |element| match element {
-            Element::Node(_) | Element::DenseNode(_) => (1, 0, 0),
-            Element::Way(w) => {
-                if w.node_locations().len() < 2 {}
-                let mut keys = Vec::new();
-                let mut values = Vec::new();
-                let ways_iter = w.tags();
-                for (key, value) in ways_iter {
-                    if key.eq("highway") {
-                        keys.push(key);
-                        values.push(value);
-                    } else if key.eq("surface") {
-                        keys.push(key);
-                        values.push(value);
-                    };
-                }
-                let way_id = w.id();
-                // println!("Way: {way_id} has tags of keys: {:#?} and values: {:#?}.", keys, values);
-                (0, 1, 0)
-            }
-            Element::Relation(_) => (0, 0, 1),
-        }, // map_op,
-        || (0u64, 0u64, 0u64),                    // identity,
-        |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2), //reduce_op
-    ) {
-        Ok((nodes, ways, relations)) => {
-            let end_time = SystemTime::now();
-            let duration = end_time
-                .duration_since(start_time)
-                .expect("Clock may have gone backwards");
-            println!("Finished counting in: {:#?}", duration);
-            println!("Nodes: {nodes}");
-            println!("Ways: {ways}");
-            println!("Relations: {relations}");
+    let written = TileWriter::create(out).and_then(|w| write_tiles(mediums, options, w));
+    let summary = match written {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("{}: {e}", out.display());
+            return ExitCode::from(exit_code::FAILURE);
+        }
+    };
+    for (z, tiles) in summary.tiles_per_zoom {
+        println!("z{z}: {tiles} tiles");
+    }
+    let duration = SystemTime::now()
+        .duration_since(start_time)
+        .expect("Clock may have gone backwards");
+    println!("Finished tiling in: {:?}", duration);
+    ExitCode::SUCCESS
+}
+
+/// Reads the dataset and applies the change files to it in order.
+fn run_update(
+    input: &Path,
+    changes: &[PathBuf],
+    filters: &Filters,
+) -> Result<Vec<Medium>, ExitCode> {
+    let start_time = SystemTime::now();
+    let mut mediums = match dataset::read(input) {
+        Ok(mediums) => mediums,
+        Err(e) => {
+            eprintln!("{}: {e}", input.display());
+            return Err(ExitCode::from(exit_code::FAILURE));
         }
+    };
+    let mut change_set = ChangeSet::default();
+    for path in changes {
+        if let Err(e) = change_set.read(path) {
+            eprintln!("{}: {e}", path.display());
+            return Err(ExitCode::from(exit_code::FAILURE));
+        }
+    }
+    if let Some(tag_filter) = filters.filter.as_ref() {
+        change_set.retain_matching(tag_filter);
+    }
+    println!(
+        "Read {} node, {} way and {} relation changes",
+        change_set.nodes.len(),
+        change_set.ways.len(),
+        change_set.relations.len()
+    );
+    let area = read_area(filters)?;
+    let mapping = read_mapping(filters)?.unwrap_or_default();
+    let summary = match apply_changes(&mut mediums, &change_set, &mapping, |m| {
+        filters.keep(&m.medium_type)
+    }) {
+        Ok(summary) => summary,
         Err(e) => {
-            println!("{e}");
-            std::process::exit(1);
+            eprintln!("{}: {e}", input.display());
+            return Err(ExitCode::from(exit_code::FAILURE));
         }
+    };
+    if let Some((area, mode)) = area {
+        area.restrict(&mut mediums, mode);
+    }
+    println!(
+        "Created {}, modified {} and deleted {} Mediums; {} more have changed nodes",
+        summary.created, summary.modified, summary.deleted, summary.moved
+    );
+    println!("{} mediums have missing node refs", summary.missing.len());
+    for m in summary.missing.iter().take(10) {
+        println!("Medium {:?} is missing nodes {:?}", m.osm_id, m.node_refs);
     }
+    let duration = SystemTime::now()
+        .duration_since(start_time)
+        .expect("Clock may have gone backwards");
+    println!("Finished updating in: {:#?}", duration);
+    Ok(mediums)
 }
 
-fn par_vec_count_everything(path: &std::path::Path) {
+/// Propagates every element set in a TLE file over `window`.
+fn run_orbit(input: &Path, window: &TimeWindow) -> Result<Vec<Medium>, ExitCode> {
+    let start_time = SystemTime::now();
+    let mediums = match orbit::trajectories(input, window) {
+        Ok(mediums) => mediums,
+        Err(e) => {
+            eprintln!("{}: {e}", input.display());
+            return Err(ExitCode::from(exit_code::FAILURE));
+        }
+    };
+    let duration = SystemTime::now()
+        .duration_since(start_time)
+        .expect("Clock may have gone backwards");
+    println!("Finished propagating in: {:#?}", duration);
+    println!("Created {} SpaceTrajectory Mediums", mediums.len());
+    Ok(mediums)
+}
+
+fn run_count(input: &Path, filter: Option<&TagFilter>) -> ExitCode {
     let start_time = SystemTime::now();
-    let reader = ElementReader::from_path(path).unwrap();
     println!("Counting...");
-    match reader.par_map_reduce(
-        |element| match element {
-            Element::Node(_) | Element::DenseNode(_) => (vec![1], Vec::new(), Vec::new()),
-            Element::Way(w) => {
-                if w.node_locations().len() < 2 {}
-                let mut keys = Vec::new();
-                let mut values = Vec::new();
-                let ways_iter = w.tags();
-                for (key, value) in ways_iter {
-                    if key.eq("highway") {
-                        keys.push(key);
-                        values.push(value);
-                    } else if key.eq("surface") {
-                        keys.push(key);
-                        values.push(value);
-                    };
-                }
-                let way_id = w.id();
-                // println!("Way: {way_id} has tags of keys: {:#?} and values: {:#?}.", keys, values);
-                (Vec::new(), vec![1], Vec::new())
-            }
-            Element::Relation(_) => (Vec::new(), Vec::new(), vec![1]),
-        }, // map_op,
-        || (Vec::new(), Vec::new(), Vec::new()), // identity,
-        |mut a: (Vec<i32>, Vec<i32>, Vec<i32>), b: (Vec<i32>, Vec<i32>, Vec<i32>)| {
-            a.0.extend(b.0);
-            a.1.extend(b.1);
-            a.2.extend(b.2);
-            (a.0, a.1, a.2)
-        }, //reduce_op
-    ) {
-        Ok((nodes, ways, relations)) => {
-            let end_time = SystemTime::now();
-            let duration = end_time
+    match count_elements(input, filter) {
+        Ok(counts) => {
+            let duration = SystemTime::now()
                 .duration_since(start_time)
                 .expect("Clock may have gone backwards");
             println!("Finished counting in: {:#?}", duration);
-            let start_sum_time = SystemTime::now();
-            let nodes_sum = nodes.iter().fold(0, |acc, n| acc + n);
-            let ways_sum = ways.iter().fold(0, |acc, w| acc + w);
-            let relations_sum = relations.iter().fold(0, |acc, r| acc + r);
-            let end_sum_time = SystemTime::now();
-            let sum_duration = end_sum_time
-                .duration_since(start_sum_time)
-                .expect("Clock bad!");
-            println!("Finished summing in: {:?}", sum_duration);
-            println!("Nodes: {:?}", nodes_sum);
-            println!("Ways: {:?}", ways_sum);
-            println!("Relations: {:?}", relations_sum);
+            println!("Nodes: {}", counts.nodes);
+            println!("Ways: {}", counts.ways);
+            println!("Relations: {}", counts.relations);
+            ExitCode::SUCCESS
         }
         Err(e) => {
-            println!("{e}");
-            std::process::exit(1);
+            eprintln!("{}: {e}", input.display());
+            ExitCode::from(exit_code::FAILURE)
         }
     }
 }
-fn par_parse_to_medium_w_pos(path: &std::path::Path, out_file: &std::path::Path, mediums: Vec<Medium>) {
-    let start_time = SystemTime::now();
-    let reader = ElementReader::from_path(path).unwrap();
-    let med_arc = Arc::new(Mutex::new(mediums));
-    println!("Populating Mediums... at{:?}", start_time);
-    match reader.par_map_reduce(|element|match element {
-                Element::Way(_w) => {
-                    (0, 0, 0)
-                }
-                Element::Relation(_r) => {
-                    (0, 0, 0)
-                }
-                Element::Node(_n) => {
-                    (0, 0, 0)
-                }
-                Element::DenseNode(n) => {
-                    let node_id = n.id;
-                    match med_arc.lock() {
-                        Err(e)    => {
-                            println!("{e}")
-                        }
-                        Ok(mut meds) => {
-                            let _ = meds.iter_mut().enumerate().for_each(|(_i, m)| {
-                                match m.osm_node_refs.par_iter().find_any(|nr| **nr == node_id) {
-                                    None => {}
-                                    Some(_nrr) => {
-                                        let pos = Position::from_osm_node(&OsmNode::from_dense_node(n.clone()));
-                                        m.medium_positions.push(pos);
-                                    }
-                                }
-                            });       
-                        }
-                    }
-                    (0, 0, 1)
-                }
-            },
-                ||(0u64, 0u64, 0u64),
-                |a,b| {
-                    (a.0, a.1, a.2 + b.2)
-                }
-            ) {
-                Ok((_,_,ns)) => {
-                    match med_arc.lock() {
-                        Err(e) => {
-                            println!("Poisoned mutex {e}")
-                        }
-                        Ok(medss) => {
-                            let end_time = SystemTime::now();
-                            let duration = end_time
-                                .duration_since(start_time)
-                                .expect("Clock may have gone backwards");
-                            println!("Random medium type: {:#?}", medss.get(0..10).unwrap());
-                            println!("Processed {ns} dense nodes to medium positions");
-                            println!("Finished populating mediums in: {:#?}", duration);
-                            let start_writing_to_file = SystemTime::now();
-                            // let file = File::create("/hdd/Data/osm/osm-kovachs-medium-w-node-refs.json").unwrap();
-                            let file = File::create(out_file).unwrap(); // Unwrap!!!
-                            let mut writer = BufWriter::new(file);
-                            serde_json::to_writer(&mut writer, medss.deref()).unwrap();
-                            writer.flush().unwrap();
-                            let end_writing_to_file = SystemTime::now();
-                            let duration_writing_to_file = end_writing_to_file
-                                .duration_since(start_writing_to_file)
-                                .expect("Bad time!");
-                            println!(
-                                "Finished writing to file in: {:#?}",
-                                duration_writing_to_file
+
+fn run_route(
+    input: &Path,
+    from: &Position,
+    to: &Position,
+    algorithm: RouteAlgorithm,
+    metric: Metric,
+    output: Option<&Path>,
+) -> ExitCode {
+    let mediums = match dataset::read(input) {
+        Ok(mediums) => mediums,
+        Err(e) => {
+            eprintln!("{}: {e}", input.display());
+            return ExitCode::from(exit_code::FAILURE);
+        }
+    };
+    let graph = RoadGraph::from_mediums(&mediums);
+    println!(
+        "Road graph has {} vertices and {} edges",
+        graph.vertices.len(),
+        graph.edges.len()
+    );
+    let index = match MediumIndex::load(&index_path_for(input)) {
+        Ok(index) if index.medium_count() == mediums.len() => index,
+        _ => MediumIndex::build(&mediums),
+    };
+    let router = Router::new(&mediums, &graph, &index);
+    let options = RouteOptions {
+        algorithm: match algorithm {
+            RouteAlgorithm::Bidirectional => Algorithm::BidirectionalDijkstra,
+            RouteAlgorithm::AStar | RouteAlgorithm::Ch => Algorithm::AStar,
+        },
+        metric,
+        ..RouteOptions::default()
+    };
+    let route = if algorithm == RouteAlgorithm::Ch {
+        let ch = load_hierarchy(input, &graph, metric);
+        let Some(query) = ChQuery::new(&router, &ch) else {
+            eprintln!(
+                "{}: the hierarchy does not match the road graph",
+                input.display()
             );
- 
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!("{e}");
-                    std::process::exit(1);
-                }
+            return ExitCode::from(exit_code::FAILURE);
+        };
+        query.route(from, to, options.snap_candidates)
+    } else {
+        router.route(from, to, &options)
+    };
+    let Some(route) = route else {
+        eprintln!("No route between {:?} and {:?}", from, to);
+        return ExitCode::from(exit_code::NO_ROUTE);
+    };
+    match output {
+        Some(out) => {
+            let written = File::create(out).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                serde_json::to_writer(&mut writer, &route)?;
+                writer.flush()
+            });
+            if let Err(e) = written {
+                eprintln!("{}: {e}", out.display());
+                return ExitCode::from(exit_code::FAILURE);
             }
+        }
+        None => {
+            println!("Distance: {:.1} m", route.distance);
+            println!("Duration: {:.0} s", route.duration);
+            println!("Mediums: {:?}", route.medium_ids);
+        }
+    }
+    ExitCode::SUCCESS
 }
 
-fn par_parse_to_medium(path: &std::path::Path, out_file: &std::path::Path) -> Vec<Medium> {
-    let start_time = SystemTime::now();
-    let reader = ElementReader::from_path(path).unwrap();
-    println!("Parsing to Medium... at{:?}", start_time);
-    match reader.par_map_reduce(
-        |element| match element {
-            Element::Way(way) => {
-                // For each way we create a medium
-                // and populate it with nodes
-                let mut way_medium = Medium::new();
-                let mut way_one_way = false;
-                let mut med_positions = Vec::new();
-                let mut node_refs: Vec<i64> = Vec::new();
-                // let _ = way.node_locations().for_each(|n| {
-                //     let position = Position::from_way_node_location(n);
-                //     med_positions.push(position);
-                // });
-                let _ = way.refs().for_each(|r| {
-                    node_refs.push(r);
-                });
-                way_medium.osm_node_refs = node_refs;
-                way_medium.medium_positions = med_positions;
-                let mut street_category = Vec::new();
-                let _ = way.tags().for_each(|(k, v)| {
-                    if k == "highway" {
-                        match v {
-                            "residential" => street_category.push(StreetCategory::Residential),
-                            "service" => street_category.push(StreetCategory::Service),
-                            "track" => street_category.push(StreetCategory::Track),
-                            "footway" => street_category.push(StreetCategory::Footway),
-                            "unclassified" => street_category.push(StreetCategory::Unclassified),
-                            "path" => street_category.push(StreetCategory::Path),
-                            "crossing" => street_category.push(StreetCategory::Crossing),
-                            "tertiary" => street_category.push(StreetCategory::Tertiary),
-                            "secondary" => street_category.push(StreetCategory::Secondary),
-                            "primary" => street_category.push(StreetCategory::Primary),
-                            "living_street" => street_category.push(StreetCategory::LivingStreet),
-                            "cycleway" => street_category.push(StreetCategory::Cycleway),
-                            "trunk" => street_category.push(StreetCategory::Trunk),
-                            "motorway" => street_category.push(StreetCategory::Motorway),
-                            "motorway_link" => street_category.push(StreetCategory::MotorwayLink),
-                            "pedestrian" => street_category.push(StreetCategory::Pedestrian),
-                            "trunk_link" => street_category.push(StreetCategory::TrunkLink),
-                            "primary_link" => street_category.push(StreetCategory::PrimaryLink),
-                            "secondary_link" => street_category.push(StreetCategory::SecondaryLink),
-                            "tertiary_link" => street_category.push(StreetCategory::TertiaryLink),
-                            "road" => street_category.push(StreetCategory::Road),
-                            _ => (),
-                        }
-                    } else if k == "oneway" {
-                        match v {
-                            "yes" => way_one_way = true,
-                            "no" => way_one_way = false,
-                            _ => (),
-                        }
-                    } else if k == "name" {
-                        match v {
-                            str => way_medium.medium_osm_name = Some(String::from(str)),
-                        }
-                    }
-                });
-                way_medium.medium_type = MediumType::Highway(street_category);
-                way_medium.osm_id = Some(way.id());
-                way_medium.is_one_way = way_one_way;
-                // mediums.push(way_medium);
-                (vec![way_medium], 0, vec![], vec![])
-            }
-            Element::Relation(_r) => (vec![], 1, vec![], vec![]),
-            Element::Node(n) => {
-                let osm_node = OsmNode::from_node(n);
-                (vec![], 0, vec![osm_node], vec![])
-            }
-            Element::DenseNode(n) => {
-                let osm_node = OsmNode::from_dense_node(n);
-                (vec![], 0, vec![], vec![osm_node])
-            }
-        }, // map_op,
-        || (vec![], 0u64, vec![], vec![]), // identity,
-        |mut a, b| {
-            a.0.extend(b.0);
-            a.2.extend(b.2);
-            a.3.extend(b.3);
-            (a.0, a.1 + b.1, a.2, a.3)
-        }, //reduce_op
-    ) {
-        Ok((mut mediums, relations, mut nodes, node_densities)) => {
-            let end_time = SystemTime::now();
-            let duration = end_time
-                .duration_since(start_time)
-                .expect("Clock may have gone backwards");
-            let start_populating_med_pos = SystemTime::now();
-            let medium_size = mediums.len();
-            let node_densities_total = node_densities.len();
-            nodes.par_extend(node_densities);
-            // let mut nodes_clone = nodes.clone();
-            let mut mediums_count = Arc::new(Mutex::new(0));
-            let medium_count_down = Arc::new(Mutex::new(medium_size));
-            // nodes.par_iter().for_each(|n|{
-            //     let _ = mediums.iter_mut().for_each(| m|{
-            //         m.osm_node_refs.iter().for_each(|re|{
-            //             if n.osm_id.eq(re) {
-            //                 let pos = Position::from_osm_node(n);
-            //                 m.medium_positions.push(pos);
-            //             }
-            //         })
-            //     });
-            // });
-            // let _ = mediums.par_iter_mut().enumerate().for_each(|(i, m)| {
-            //     let mut positions = Vec::new();
-            //     if m.medium_osm_name.is_some() {
-            //         m.osm_node_refs.iter().for_each(|re| {
-            //             // nodes.iter().for_each(|n|{
-            //             //     if n.osm_id.eq(re) {
-            //             //         let pos = Position::from_osm_node(n);
-
-            //             //         println!("Created position {:#?}", pos);
-            //             //         // m.medium_positions.push(pos);
-            //             //         positions.push(pos);
-            //             //     }
-            //             // });
-            //             match nodes.par_iter().find_any(|n| n.osm_id.eq(re)) {
-            //                 None => (),
-            //                 Some(nn) => {
-            //                     let pos = Position::from_osm_node(nn);
-            //                     // println!("Created position {:#?}", pos);
-            //                     positions.push(pos);
-            //                     // m.medium_positions.push(pos);
-            //                 }
-            //             }
-            //         });
-            //         match mediums_count.lock() {
-            //             Err(_) => (),
-            //             Ok(mut mc) => {
-            //                 *mc += 1;
-            //             }
-            //         };
-            //         // println!("Added positions {:#?} to medium {:#?}", positions, m);
-            //         m.medium_positions = positions;
-            //     }
-            //     match medium_count_down.lock() {
-            //         Err(_) => (),
-            //         Ok(mut mcd) => {
-            //             *mcd -= 1;
-            //         }
-            //     }
-            //     if i % 1000 == 0 {
-            //         eprintln!("Medium with pos count: {:#?}", mediums_count);
-            //         eprintln!(
-            //             "Mediums left: {:#?} after {:#?}",
-            //             medium_count_down,
-            //             SystemTime::now()
-            //                 .duration_since(start_populating_med_pos)
-            //                 .expect("Bad time!")
-            //         );
-            //     }
-            // });
-            let end_populating_med_pos = SystemTime::now();
-            let duration_populating_med_pos = end_populating_med_pos
-                .duration_since(start_populating_med_pos)
-                .expect("Bad time!");
-            println!("Finished creating mediums in: {:#?}", duration);
+/// The hierarchy saved by `contract`, or a new one when there is none for this
+/// graph.
+fn load_hierarchy(input: &Path, graph: &RoadGraph, metric: Metric) -> ContractionHierarchy {
+    match ContractionHierarchy::load(&hierarchy_path_for(input, metric)) {
+        Ok(ch) if ch.matches(graph) => ch,
+        _ => {
             println!(
-                "Finished populating med positions in: {:?}",
-                duration_populating_med_pos
+                "Building a contraction hierarchy for {:?}; run contract to keep it",
+                metric
             );
-            println!("Created {:#?} Mediums", mediums.iter().len());
-            println!("The nodes total: {:?}", nodes.len());
-            println!("The node density total: {:?}", node_densities_total);
-            println!("The relations total: {:?}", relations);
-            println!("Random medium type: {:#?}", mediums.get(0..10).unwrap());
-            // println!("Writing medium results to json file");
-            // let start_writing_to_file = SystemTime::now();
-            // // let file = File::create("/hdd/Data/osm/osm-kovachs-medium-w-node-refs.json").unwrap();
-            // let file = File::create(out_file).unwrap(); // Unwrap!!!
-            // let mut writer = BufWriter::new(file);
-            // serde_json::to_writer(&mut writer, &mediums).unwrap();
-            // writer.flush().unwrap();
-            // let end_writing_to_file = SystemTime::now();
-            // let duration_writing_to_file = end_writing_to_file
-            //     .duration_since(start_writing_to_file)
-            //     .expect("Bad time!");
-            // println!(
-            //     "Finished writing to file in: {:#?}",
-            //     duration_writing_to_file
-            // );
-            mediums
+            ContractionHierarchy::build(graph, metric)
         }
+    }
+}
+
+fn run_contract(input: &Path, metrics: &[RouteMetric]) -> ExitCode {
+    let mediums = match dataset::read(input) {
+        Ok(mediums) => mediums,
         Err(e) => {
-            println!("{e}");
-            std::process::exit(1);
+            eprintln!("{}: {e}", input.display());
+            return ExitCode::from(exit_code::FAILURE);
+        }
+    };
+    let graph = RoadGraph::from_mediums(&mediums);
+    println!(
+        "Road graph has {} vertices and {} edges",
+        graph.vertices.len(),
+        graph.edges.len()
+    );
+    for &metric in metrics {
+        let metric = Metric::from(metric);
+        let start_time = SystemTime::now();
+        let ch = ContractionHierarchy::build(&graph, metric);
+        let path = hierarchy_path_for(input, metric);
+        if let Err(e) = ch.save(&path) {
+            eprintln!("{}: {e}", path.display());
+            return ExitCode::from(exit_code::FAILURE);
         }
+        let duration = SystemTime::now()
+            .duration_since(start_time)
+            .expect("Clock may have gone backwards");
+        println!(
+            "Wrote {} with {} shortcuts in: {:#?}",
+            path.display(),
+            ch.shortcut_count(),
+            duration
+        );
     }
+    ExitCode::SUCCESS
 }
 
-pub fn parse_all_to_medium(path: &std::path::Path) {
-    let _reader = ElementReader::from_path(path).unwrap();
-    let mut indexed_reader = IndexedReader::from_path(path).unwrap();
-    println!("Parsing!");
-    let start_time = SystemTime::now();
-    println!("Started at {:#?}", start_time);
-
-    let mut mediums: Vec<Medium> = Vec::new();
-    // let mut nodes_vec = Vec::new();
+fn run_distances(
+    input: &Path,
+    from: &Position,
+    to: &[Position],
+    metric: Metric,
+    output: Option<&Path>,
+) -> ExitCode {
+    let mediums = match dataset::read(input) {
+        Ok(mediums) => mediums,
+        Err(e) => {
+            eprintln!("{}: {e}", input.display());
+            return ExitCode::from(exit_code::FAILURE);
+        }
+    };
+    let graph = RoadGraph::from_mediums(&mediums);
+    let index = match MediumIndex::load(&index_path_for(input)) {
+        Ok(index) if index.medium_count() == mediums.len() => index,
+        _ => MediumIndex::build(&mediums),
+    };
+    let router = Router::new(&mediums, &graph, &index);
+    let ch = load_hierarchy(input, &graph, metric);
+    let Some(query) = ChQuery::new(&router, &ch) else {
+        eprintln!(
+            "{}: the hierarchy does not match the road graph",
+            input.display()
+        );
+        return ExitCode::from(exit_code::FAILURE);
+    };
+    let costs = query.one_to_many(from, to, RouteOptions::default().snap_candidates);
+    match output {
+        Some(out) => {
+            let written = File::create(out).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                serde_json::to_writer(&mut writer, &costs)?;
+                writer.flush()
+            });
+            if let Err(e) = written {
+                eprintln!("{}: {e}", out.display());
+                return ExitCode::from(exit_code::FAILURE);
+            }
+        }
+        None => {
+            for (position, cost) in to.iter().zip(costs.iter()) {
+                match cost {
+                    Some(cost) => {
+                        println!("{},{}\t{:.1}", position.longitude, position.latitude, cost)
+                    }
+                    None => println!("{},{}\tno route", position.longitude, position.latitude),
+                }
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
 
-    let mut ways = 0;
-    let mut nodes = 0;
+fn run_validate(input: &Path) -> ExitCode {
+    let mediums = match dataset::read(input) {
+        Ok(mediums) => mediums,
+        Err(e) => {
+            eprintln!("{}: {e}", input.display());
+            return ExitCode::from(exit_code::FAILURE);
+        }
+    };
+    let index_file = index_path_for(input);
+    let index = if index_file.exists() {
+        match MediumIndex::load(&index_file) {
+            Ok(index) => Some(index),
+            Err(e) => {
+                eprintln!("{}: {e}", index_file.display());
+                return ExitCode::from(exit_code::FAILURE);
+            }
+        }
+    } else {
+        None
+    };
+    let problems = validate(&mediums, index.as_ref());
+    for p in problems.iter() {
+        println!("{p}");
+    }
+    println!(
+        "{} mediums checked, {} problems",
+        mediums.len(),
+        problems.len()
+    );
+    if problems.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(exit_code::INVALID)
+    }
+}
 
-    indexed_reader
-        .read_ways_and_deps(
-            |way| {
-                // Filter ways. Return true if tags contain "highway" : "<V>"
-                way.tags().any(|key_value| key_value.0 == "highway")
-                // way.tags().any(|key_value| key_value == (("building", "yes)))
-            },
-            |element| {
-                // Increment counter for ways and nodes
-                match element {
-                    Element::Way(way) => {
-                        ways += 1;
-                        // For each way we create a medium
-                        // and populate it with nodes
-                        let mut way_medium = Medium::new();
-                        let mut way_one_way = false;
-                        let mut med_positions = Vec::new();
-                        let _ = way.node_locations().for_each(|n| {
-                            let position = Position::from_way_node_location(n);
-                            med_positions.push(position);
-                        });
-                        med_positions.push(Position {
-                            longitude: 0.0,
-                            latitude: 0.0,
-                        });
-                        way_medium.medium_positions = med_positions;
-                        let mut street_category = Vec::new();
-                        let _ = way.tags().for_each(|(k, v)| {
-                            if k == "highway" {
-                                match v {
-                                    "residential" => {
-                                        street_category.push(StreetCategory::Residential)
-                                    }
-                                    "service" => street_category.push(StreetCategory::Service),
-                                    "track" => street_category.push(StreetCategory::Track),
-                                    "footway" => street_category.push(StreetCategory::Footway),
-                                    "unclassified" => {
-                                        street_category.push(StreetCategory::Unclassified)
-                                    }
-                                    "path" => street_category.push(StreetCategory::Path),
-                                    "crossing" => street_category.push(StreetCategory::Crossing),
-                                    "tertiary" => street_category.push(StreetCategory::Tertiary),
-                                    "secondary" => street_category.push(StreetCategory::Secondary),
-                                    "primary" => street_category.push(StreetCategory::Primary),
-                                    "living_street" => {
-                                        street_category.push(StreetCategory::LivingStreet)
-                                    }
-                                    "cycleway" => street_category.push(StreetCategory::Cycleway),
-                                    "trunk" => street_category.push(StreetCategory::Trunk),
-                                    "motorway" => street_category.push(StreetCategory::Motorway),
-                                    "motorway_link" => {
-                                        street_category.push(StreetCategory::MotorwayLink)
-                                    }
-                                    "pedestrian" => {
-                                        street_category.push(StreetCategory::Pedestrian)
-                                    }
-                                    "trunk_link" => street_category.push(StreetCategory::TrunkLink),
-                                    "primary_link" => {
-                                        street_category.push(StreetCategory::PrimaryLink)
-                                    }
-                                    "secondary_link" => {
-                                        street_category.push(StreetCategory::SecondaryLink)
-                                    }
-                                    "tertiary_link" => {
-                                        street_category.push(StreetCategory::TertiaryLink)
-                                    }
-                                    "road" => street_category.push(StreetCategory::Road),
-                                    _ => (),
-                                }
-                            } else if k == "oneway" {
-                                match v {
-                                    "yes" => way_one_way = true,
-                                    "no" => way_one_way = false,
-                                    _ => (),
-                                }
-                            } else if k == "name" {
-                                match v {
-                                    str => way_medium.medium_osm_name = Some(String::from(str)),
-                                }
-                            }
-                        });
-                        way_medium.medium_type = MediumType::Highway(street_category);
-                        way_medium.osm_id = Some(way.id());
-                        way_medium.is_one_way = way_one_way;
-                        mediums.push(way_medium);
-                    }
-                    Element::Node(_node) => nodes += 1,
-                    Element::DenseNode(_dense_node) => nodes += 1,
-                    Element::Relation(_) => {} // should not occur
+fn write_output(
+    out_file: &Path,
+    format: Format,
+    options: &OutputOptions,
+    mediums: &[Medium],
+) -> Result<(), ExitCode> {
+    let start_time = SystemTime::now();
+    let written = match format {
+        Format::Json => dataset::write_json(out_file, mediums),
+        Format::GeoJson => dataset::write_geojson(out_file, mediums),
+        Format::GeoParquet => {
+            let parquet_options = ParquetOptions {
+                row_group_size: options.row_group_size,
+                ..ParquetOptions::default()
+            };
+            write_geoparquet(out_file, mediums, parquet_options)
+        }
+        Format::FlatGeobuf => {
+            let columns: Vec<&str> = options.columns.iter().map(String::as_str).collect();
+            write_flatgeobuf(out_file, mediums, &columns).map(|skipped| {
+                if skipped > 0 {
+                    eprintln!("Skipped {skipped} Mediums without positions");
                 }
-            },
-        )
-        .unwrap();
+            })
+        }
+        Format::GeoPackage => write_geopackage(out_file, mediums).map(|summary| {
+            println!(
+                "Wrote {} Mediums and {} nodes",
+                summary.mediums, summary.nodes
+            );
+            if summary.skipped > 0 {
+                eprintln!(
+                    "Skipped {} Mediums with fewer than two positions",
+                    summary.skipped
+                );
+            }
+        }),
+        Format::Postgis => {
+            let postgis_options = PostgisOptions {
+                table_prefix: options.table_prefix.clone(),
+                srid: options.srid,
+                tags: options.tags.into(),
+            };
+            write_postgis(out_file, mediums, &postgis_options)
+        }
+    };
+    if let Err(e) = written {
+        eprintln!("{}: {e}", out_file.display());
+        return Err(ExitCode::from(exit_code::FAILURE));
+    }
+    let duration = SystemTime::now()
+        .duration_since(start_time)
+        .expect("Bad time!");
+    println!("Finished writing to file in: {:#?}", duration);
+    Ok(())
+}
 
-    // Timing
-    let end_time = SystemTime::now();
-    let duration = end_time
+/// Builds the spatial index and saves it next to the output, or inside it for
+/// the PostGIS directory.
+fn write_medium_index(out_file: &Path, format: Format, mediums: &[Medium]) -> Result<(), ExitCode> {
+    let start_time = SystemTime::now();
+    let index = MediumIndex::build(mediums);
+    let index_file = match format {
+        Format::Postgis => out_file.join("mediums.index.json"),
+        _ => index_path_for(out_file),
+    };
+    if let Err(e) = index.save(&index_file) {
+        eprintln!("{}: {e}", index_file.display());
+        return Err(ExitCode::from(exit_code::FAILURE));
+    }
+    let duration = SystemTime::now()
         .duration_since(start_time)
-        .expect("Clock may have gone backwards");
-    println!("Finished counting in: {:#?}", duration);
-    // Print result
-    println!("ways:  {ways}\nnodes: {nodes}");
-    println!("Created mediums: {:#?}", mediums.len());
-    println!("Random medium type: {:#?}", mediums.get(0..50).unwrap());
+        .expect("Bad time!");
+    println!("Wrote medium index {:?} in: {:#?}", index_file, duration);
+    Ok(())
 }
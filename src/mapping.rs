@@ -0,0 +1,344 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::{
+    airway::parse_length,
+    error::KovachsError,
+    railway::parse_gauge,
+    tagfilter::TagFilter,
+    types::medium::MediumType,
+    waterway::{parse_duration, parse_width},
+};
+
+/// The mapping [`TagMapping::default`] uses, which is also a template for
+/// writing new ones.
+pub const DEFAULT_MAPPING: &str = include_str!("mapping/default.toml");
+
+/// Which ways and relations become which Mediums, read from a TOML file such as
+/// [`DEFAULT_MAPPING`].
+///
+/// The rules are tried in order. A rule takes a way when the way has its key with
+/// a value listed in its categories, and matches its `match` expression if it
+/// has one. The rule then decides the medium type and category, copies tags into
+/// the type's attributes and fills in defaults for the attributes left empty.
+/// The tags named in the top-level `tags` list are kept on every Medium as they
+/// are.
+///
+/// Relations are classified by the same rules. Those that get the Ferry waterway
+/// category are joined from all their member ways, and those that get the
+/// Aerodrome airway category from their outer ways; other relations are skipped.
+///
+/// ```
+/// use osm_kovachs::{mapping::TagMapping, types::medium::MediumType};
+///
+/// let mapping = TagMapping::parse(
+///     r#"
+///     [[rule]]
+///     medium_type = "railway"
+///     key = "railway"
+///     categories = { monorail = "LightRail" }
+///     defaults = { electrified = "rail" }
+///     "#,
+/// )?;
+/// let classified = mapping.classify(&[("railway", "monorail")]).unwrap();
+/// assert!(matches!(classified.medium_type, MediumType::Railway(r) if r.electrified.as_deref() == Some("rail")));
+/// # Ok::<(), osm_kovachs::error::KovachsError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TagMapping {
+    rules: Vec<Rule>,
+    tags: Vec<String>,
+}
+
+/// What a rule made of a way.
+#[derive(Debug, Clone)]
+pub struct Classified {
+    pub medium_type: MediumType,
+    /// `is_one_way` for ways without oneway=yes or oneway=no.
+    pub oneway: Option<bool>,
+}
+
+impl TagMapping {
+    pub fn read(path: &Path) -> Result<TagMapping, KovachsError> {
+        TagMapping::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<TagMapping, KovachsError> {
+        let file: MappingFile = toml::from_str(text)?;
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| Rule::new(rule).map_err(|e| invalid(format!("rule {}: {e}", i + 1))))
+            .collect::<Result<_, _>>()?;
+        Ok(TagMapping {
+            rules,
+            tags: file.tags,
+        })
+    }
+
+    /// Also keeps these tags on every Medium.
+    pub fn keep_tags(mut self, keys: impl IntoIterator<Item = String>) -> TagMapping {
+        for key in keys {
+            if !self.tags.contains(&key) {
+                self.tags.push(key);
+            }
+        }
+        self
+    }
+
+    /// The tags to keep on the Medium of an element with these tags.
+    pub fn kept_tags<'a>(
+        &self,
+        tags: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> BTreeMap<String, String> {
+        if self.tags.is_empty() {
+            return BTreeMap::new();
+        }
+        tags.into_iter()
+            .filter(|(k, _)| self.tags.iter().any(|t| t == k))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// The medium type of a way or relation with these tags, or `None` when no rule takes it.
+    pub fn classify(&self, tags: &[(&str, &str)]) -> Option<Classified> {
+        self.rules.iter().find_map(|rule| rule.classify(tags))
+    }
+}
+
+impl Default for TagMapping {
+    fn default() -> TagMapping {
+        TagMapping::parse(DEFAULT_MAPPING).expect("the built-in mapping is valid")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingFile {
+    #[serde(rename = "rule", default)]
+    rules: Vec<RuleConfig>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    medium_type: Kind,
+    key: String,
+    #[serde(rename = "match", default, deserialize_with = "tag_filter")]
+    filter: Option<TagFilter>,
+    categories: HashMap<String, String>,
+    #[serde(default)]
+    attributes: HashMap<String, Keys>,
+    #[serde(default)]
+    defaults: Map<String, Value>,
+    oneway: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    Highway,
+    Railway,
+    Waterway,
+    Airway,
+}
+
+/// The tag, or tags tried in order, an attribute is copied from.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn tag_filter<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<TagFilter>, D::Error> {
+    let expression = String::deserialize(deserializer)?;
+    expression
+        .parse()
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    key: String,
+    filter: Option<TagFilter>,
+    /// The medium type for each tag value, with the defaults filled in.
+    categories: HashMap<String, MediumType>,
+    attributes: Vec<(String, Vec<String>)>,
+    oneway: Option<bool>,
+}
+
+impl Rule {
+    /// Checks the categories, attributes and defaults against the medium type.
+    fn new(config: RuleConfig) -> Result<Rule, String> {
+        let categories = config
+            .categories
+            .into_iter()
+            .map(|(value, category)| {
+                let medium_type = medium_type(config.medium_type, &category, &config.defaults)?;
+                Ok((value, medium_type))
+            })
+            .collect::<Result<HashMap<_, _>, String>>()?;
+        let attributes: Vec<(String, Vec<String>)> = config
+            .attributes
+            .into_iter()
+            .map(|(attribute, keys)| match keys {
+                Keys::One(key) => (attribute, vec![key]),
+                Keys::Many(keys) => (attribute, keys),
+            })
+            .collect();
+        if let Some(sample) = categories.values().next() {
+            for (attribute, _) in attributes.iter() {
+                // Every tag value is converted to the kind of value "1" is.
+                let value = attribute_value(attribute, "1").unwrap_or_default();
+                let copied = Map::from_iter([(attribute.clone(), value)]);
+                if with_attributes(sample, copied).is_none() {
+                    return Err(format!(
+                        "attribute {attribute:?} cannot be copied from a tag"
+                    ));
+                }
+            }
+        }
+        Ok(Rule {
+            key: config.key,
+            filter: config.filter,
+            categories,
+            attributes,
+            oneway: config.oneway,
+        })
+    }
+
+    fn classify(&self, tags: &[(&str, &str)]) -> Option<Classified> {
+        let value = tag(tags, &self.key)?;
+        let template = self.categories.get(value)?;
+        if let Some(filter) = &self.filter {
+            if !filter.matches(tags.iter().copied()) {
+                return None;
+            }
+        }
+        let copied: Map<String, Value> = self
+            .attributes
+            .iter()
+            .filter_map(|(attribute, keys)| {
+                let value = keys
+                    .iter()
+                    .filter_map(|k| tag(tags, k))
+                    .find_map(|v| attribute_value(attribute, v))?;
+                Some((attribute.clone(), value))
+            })
+            .collect();
+        let medium_type = if copied.is_empty() {
+            template.clone()
+        } else {
+            // Cannot fail, as `Rule::new` tried every attribute.
+            with_attributes(template, copied).unwrap_or_else(|| template.clone())
+        };
+        Some(Classified {
+            medium_type,
+            oneway: self.oneway,
+        })
+    }
+}
+
+fn tag<'a>(tags: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+    tags.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// The medium type for a category by its variant name, e.g. "LightRail".
+fn medium_type(
+    kind: Kind,
+    category: &str,
+    defaults: &Map<String, Value>,
+) -> Result<MediumType, String> {
+    let mut attributes = defaults.clone();
+    attributes.insert(String::from("category"), Value::from(category));
+    let value = Value::Object(attributes);
+    let medium_type = match kind {
+        Kind::Highway if !defaults.is_empty() => {
+            return Err(String::from("highways have no attributes"))
+        }
+        Kind::Highway => {
+            serde_json::from_value(Value::from(category)).map(|c| MediumType::Highway(vec![c]))
+        }
+        Kind::Railway => {
+            let mut value = value;
+            // Stations are linked after the ways are read.
+            value["stations"] = Value::Array(Vec::new());
+            serde_json::from_value(value).map(MediumType::Railway)
+        }
+        Kind::Waterway => serde_json::from_value(value).map(MediumType::Waterway),
+        Kind::Airway => serde_json::from_value(value).map(MediumType::Airway),
+    }
+    .map_err(|e| format!("{category:?}: {e}"))?;
+    let known = attribute_names(&medium_type);
+    match defaults.keys().find(|k| !known.contains(k)) {
+        Some(k) => Err(format!(
+            "{k:?} is not an attribute of {}",
+            medium_type.name()
+        )),
+        None => Ok(medium_type),
+    }
+}
+
+/// Names of the attributes a medium type has.
+fn attribute_names(medium_type: &MediumType) -> Vec<String> {
+    let value = match medium_type {
+        MediumType::Railway(a) => serde_json::to_value(a),
+        MediumType::Waterway(a) => serde_json::to_value(a),
+        MediumType::Airway(a) => serde_json::to_value(a),
+        _ => return Vec::new(),
+    };
+    match value {
+        Ok(Value::Object(map)) => map.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `template` with `copied` replacing some of its attributes, or `None` when
+/// they are not attributes of its type or of the wrong kind.
+fn with_attributes(template: &MediumType, copied: Map<String, Value>) -> Option<MediumType> {
+    fn merge<T: serde::Serialize + serde::de::DeserializeOwned>(
+        attributes: &T,
+        copied: Map<String, Value>,
+    ) -> Option<T> {
+        let Value::Object(mut map) = serde_json::to_value(attributes).ok()? else {
+            return None;
+        };
+        for (attribute, value) in copied {
+            *map.get_mut(&attribute)? = value;
+        }
+        serde_json::from_value(Value::Object(map)).ok()
+    }
+    match template {
+        MediumType::Railway(a) => merge(a, copied).map(MediumType::Railway),
+        MediumType::Waterway(a) => merge(a, copied).map(MediumType::Waterway),
+        MediumType::Airway(a) => merge(a, copied).map(MediumType::Airway),
+        _ => None,
+    }
+}
+
+/// A tag value as the attribute holds it; numbers are parsed the way the tags
+/// are usually written, and `None` when they do not parse.
+fn attribute_value(attribute: &str, v: &str) -> Option<Value> {
+    match attribute {
+        "gauge" => parse_gauge(v).map(Value::from),
+        "width" => parse_width(v).map(Value::from),
+        "duration" => parse_duration(v).map(Value::from),
+        "length" => parse_length(v).map(Value::from),
+        _ => Some(Value::from(v)),
+    }
+}
+
+fn invalid(message: impl Into<String>) -> KovachsError {
+    KovachsError::Mapping(message.into())
+}
@@ -0,0 +1,128 @@
+use std::{fs, path::Path};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use sgp4::{Constants, Elements, MinutesSinceEpoch};
+
+use crate::{
+    error::Result,
+    types::medium::{Medium, MediumType, Position, TrajectoryAttributes},
+};
+
+/// WGS 84 equatorial radius in kilometers.
+const WGS84_A_KM: f64 = 6378.137;
+/// WGS 84 flattening.
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// Julian years per minute, to move the element set epoch along with the propagation.
+const YEARS_PER_MINUTE: f64 = 1.0 / (365.25 * 1440.0);
+
+/// Times at which to sample each orbit, in seconds since the Unix epoch.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeWindow {
+    pub start: f64,
+    pub end: f64,
+    /// Seconds between two samples.
+    pub step: f64,
+}
+
+impl TimeWindow {
+    pub fn new(start: f64, end: f64, step: f64) -> TimeWindow {
+        TimeWindow { start, end, step }
+    }
+
+    fn times(&self) -> impl Iterator<Item = f64> + '_ {
+        let count = if self.step > 0.0 && self.end >= self.start {
+            ((self.end - self.start) / self.step).floor() as usize + 1
+        } else {
+            0
+        };
+        (0..count).map(move |i| self.start + i as f64 * self.step)
+    }
+}
+
+/// Reads a file of element sets, with or without a name line before each pair.
+pub fn read_tles(path: &Path) -> Result<Vec<Elements>> {
+    let text = fs::read_to_string(path)?;
+    let named = text
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .is_some_and(|l| !l.starts_with("1 "));
+    let elements = if named {
+        sgp4::parse_3les(&text)
+    } else {
+        sgp4::parse_2les(&text)
+    };
+    Ok(elements?)
+}
+
+/// Propagates one element set over `window` into a SpaceTrajectory Medium whose
+/// positions are the sub-satellite points.
+///
+/// Sampling stops at the first time SGP4 fails (e.g. once the orbit has decayed),
+/// so the track may be shorter than the window.
+pub fn ground_track(elements: &Elements, window: &TimeWindow) -> Result<Medium> {
+    let constants = Constants::from_elements(elements)?;
+    let epoch = elements.datetime.and_utc().timestamp_millis() as f64 / 1000.0;
+    let mut attributes = TrajectoryAttributes {
+        norad_id: elements.norad_id,
+        international_designator: elements.international_designator.clone(),
+        epoch,
+        altitudes: Vec::new(),
+        timestamps: Vec::new(),
+    };
+    let mut positions = Vec::new();
+    for time in window.times() {
+        let minutes = (time - epoch) / 60.0;
+        let prediction = match constants.propagate(MinutesSinceEpoch(minutes)) {
+            Ok(prediction) => prediction,
+            Err(_) => break,
+        };
+        let gmst = sgp4::iau_epoch_to_sidereal_time(elements.epoch() + minutes * YEARS_PER_MINUTE);
+        let (position, altitude) = teme_to_geodetic(prediction.position, gmst);
+        positions.push(position);
+        attributes.altitudes.push(altitude);
+        attributes.timestamps.push(time);
+    }
+
+    let mut medium = Medium::new();
+    medium.medium_osm_name = elements.object_name.clone();
+    medium.medium_positions = positions;
+    medium.medium_type = MediumType::SpaceTrajectory(attributes);
+    Ok(medium)
+}
+
+/// Ground tracks of every element set in a TLE file, in file order.
+pub fn trajectories(path: &Path, window: &TimeWindow) -> Result<Vec<Medium>> {
+    read_tles(path)?
+        .par_iter()
+        .map(|elements| ground_track(elements, window))
+        .collect()
+}
+
+/// Converts a TEME position in kilometers to a sub-satellite point and the height
+/// above the ellipsoid, rotating by the sidereal time and ignoring polar motion.
+fn teme_to_geodetic(teme: [f64; 3], gmst: f64) -> (Position, f64) {
+    let (sin, cos) = gmst.sin_cos();
+    let x = cos * teme[0] + sin * teme[1];
+    let y = -sin * teme[0] + cos * teme[1];
+    let z = teme[2];
+
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let p = (x * x + y * y).sqrt();
+    let longitude = y.atan2(x);
+    let mut latitude = z.atan2(p * (1.0 - e2));
+    let mut altitude = 0.0;
+    // Converges to well under a millimetre within a few rounds for orbital heights.
+    for _ in 0..5 {
+        let sin_lat = latitude.sin();
+        let n = WGS84_A_KM / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+        altitude = p / latitude.cos() - n;
+        latitude = z.atan2(p * (1.0 - e2 * n / (n + altitude)));
+    }
+    (
+        Position {
+            longitude: longitude.to_degrees(),
+            latitude: latitude.to_degrees(),
+        },
+        altitude,
+    )
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::Path,
+};
+
+use crate::{columns::MediumRow, error::Result, types::medium::Medium, wkb};
+
+/// Positions are WGS 84 longitude/latitude; other SRIDs are reached with
+/// `ST_Transform` while loading.
+const SOURCE_SRID: u32 = 4326;
+
+/// How the kept OSM tags of a Medium are stored. The type attributes always go
+/// in a jsonb column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagsType {
+    Hstore,
+    Jsonb,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostgisOptions {
+    /// Prepended to the table and index names, e.g. `osm_` for `osm_mediums`.
+    pub table_prefix: String,
+    /// SRID of the geometry column.
+    pub srid: u32,
+    pub tags: TagsType,
+}
+
+impl Default for PostgisOptions {
+    fn default() -> Self {
+        PostgisOptions {
+            table_prefix: String::new(),
+            srid: SOURCE_SRID,
+            tags: TagsType::Jsonb,
+        }
+    }
+}
+
+/// Writes a PostGIS dump of `mediums` into the directory `dir`, creating it if
+/// needed: `schema.sql` with the table definition, `mediums.copy` with the rows
+/// in COPY text format and EWKB geometries, and `load.sql`, which runs both and
+/// adds the spatial index. Load it from inside `dir` with
+/// `psql -d <database> -f load.sql`.
+pub fn write_postgis(dir: &Path, mediums: &[Medium], options: &PostgisOptions) -> Result<()> {
+    let prefix = &options.table_prefix;
+    if !prefix
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        || prefix.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("table prefix {prefix:?} is not a lowercase SQL identifier"),
+        )
+        .into());
+    }
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("schema.sql"), schema(options))?;
+    fs::write(dir.join("load.sql"), load_script(options))?;
+
+    let mut copy = BufWriter::new(File::create(dir.join("mediums.copy"))?);
+    let mut line = String::new();
+    for (fid, m) in mediums.iter().enumerate() {
+        line.clear();
+        copy_row(&mut line, fid, m, options.tags)?;
+        copy.write_all(line.as_bytes())?;
+    }
+    copy.flush()?;
+    Ok(())
+}
+
+fn schema(options: &PostgisOptions) -> String {
+    let table = format!("{}mediums", options.table_prefix);
+    let (extension, tags) = match options.tags {
+        TagsType::Hstore => ("CREATE EXTENSION IF NOT EXISTS hstore;\n", "hstore"),
+        TagsType::Jsonb => ("", "jsonb"),
+    };
+    format!(
+        "CREATE EXTENSION IF NOT EXISTS postgis;\n\
+         {extension}\
+         \n\
+         CREATE TABLE {table} (\n    \
+             fid bigint PRIMARY KEY,\n    \
+             osm_id bigint,\n    \
+             osm_type text,\n    \
+             name text,\n    \
+             medium_type text NOT NULL,\n    \
+             category text,\n    \
+             is_one_way boolean NOT NULL,\n    \
+             length double precision NOT NULL,\n    \
+             node_refs bigint[] NOT NULL,\n    \
+             tags {tags},\n    \
+             attributes jsonb,\n    \
+             geom geometry(Geometry, {SOURCE_SRID})\n\
+         );\n"
+    )
+}
+
+fn load_script(options: &PostgisOptions) -> String {
+    let table = format!("{}mediums", options.table_prefix);
+    let srid = options.srid;
+    let mut script = format!(
+        "\\set ON_ERROR_STOP on\n\
+         BEGIN;\n\
+         \\ir schema.sql\n\
+         \\copy {table} FROM 'mediums.copy'\n"
+    );
+    if srid != SOURCE_SRID {
+        let _ = writeln!(
+            script,
+            "ALTER TABLE {table} ALTER COLUMN geom TYPE geometry(Geometry, {srid}) \
+             USING ST_Transform(geom, {srid});"
+        );
+    }
+    let _ = write!(
+        script,
+        "CREATE INDEX {table}_geom_idx ON {table} USING GIST (geom);\n\
+         CREATE INDEX {table}_osm_id_idx ON {table} (osm_type, osm_id);\n\
+         COMMIT;\n\
+         ANALYZE {table};\n"
+    );
+    script
+}
+
+/// Appends one tab-separated line of COPY text format for `medium`.
+fn copy_row(line: &mut String, fid: usize, medium: &Medium, tags: TagsType) -> Result<()> {
+    let row = MediumRow::from_medium(medium)?;
+    let refs: Vec<String> = row.node_refs.iter().map(i64::to_string).collect();
+    let tags = match tags {
+        _ if medium.osm_tags.is_empty() => None,
+        TagsType::Jsonb => row.tags.clone(),
+        TagsType::Hstore => Some(hstore(&medium.osm_tags)),
+    };
+    let geometry = wkb::encode_with_srid(&medium.medium_positions, Some(SOURCE_SRID)).map(|ewkb| {
+        ewkb.iter()
+            .fold(String::with_capacity(2 * ewkb.len()), |mut hex, b| {
+                let _ = write!(hex, "{b:02X}");
+                hex
+            })
+    });
+
+    let fields = [
+        Some(fid.to_string()),
+        row.osm_id.map(|id| id.to_string()),
+        row.osm_type.map(|t| t.name().to_string()),
+        row.name,
+        Some(row.medium_type.to_string()),
+        row.category,
+        Some(if row.is_one_way { "t" } else { "f" }.to_string()),
+        Some(row.length.to_string()),
+        Some(format!("{{{}}}", refs.join(","))),
+        tags,
+        row.attributes,
+        geometry,
+    ];
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            line.push('\t');
+        }
+        match field {
+            Some(value) => escape_copy(line, value),
+            None => line.push_str("\\N"),
+        }
+    }
+    line.push('\n');
+    Ok(())
+}
+
+/// An hstore literal of the tags.
+fn hstore(tags: &BTreeMap<String, String>) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    tags.iter()
+        .map(|(k, v)| format!("{}=>{}", quote(k), quote(v)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Escapes a field for COPY text format.
+fn escape_copy(line: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '\\' => line.push_str("\\\\"),
+            '\t' => line.push_str("\\t"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            c => line.push(c),
+        }
+    }
+}
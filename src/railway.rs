@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use crate::{
+    index::MediumIndex,
+    types::medium::{Medium, MediumType, Position, RailStation, StationKind},
+};
+
+/// Stations further than this from every railway Medium stay unlinked.
+const STATION_LINK_METERS: f64 = 100.0;
+
+/// Track gauge in millimetres; dual gauge track is tagged as "1000;1435", of
+/// which the first is taken.
+pub(crate) fn parse_gauge(v: &str) -> Option<u32> {
+    v.split(';').next().and_then(|g| g.trim().parse().ok())
+}
+
+/// A railway=station or railway=halt node.
+pub fn rail_station<'a>(
+    osm_id: i64,
+    position: Position,
+    tags: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Option<RailStation> {
+    let mut kind = None;
+    let mut name = None;
+    for (k, v) in tags {
+        match (k, v) {
+            ("railway", "station") => kind = Some(StationKind::Station),
+            ("railway", "halt") => kind = Some(StationKind::Halt),
+            ("name", _) => name = Some(String::from(v)),
+            _ => (),
+        }
+    }
+    Some(RailStation {
+        osm_id,
+        name,
+        kind: kind?,
+        position,
+    })
+}
+
+/// Adds each station to the railway Mediums it sits on.
+///
+/// A station node that is part of a line is linked to every Medium containing
+/// it; one mapped beside the tracks goes to the closest line within
+/// [`STATION_LINK_METERS`]. Returns the number of stations left unlinked.
+pub fn link_stations(mediums: &mut [Medium], stations: Vec<RailStation>) -> usize {
+    let by_node: HashMap<i64, usize> = stations
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.osm_id, i))
+        .collect();
+    let mut linked = vec![false; stations.len()];
+    let railways: Vec<usize> = mediums
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| matches!(m.medium_type, MediumType::Railway(_)))
+        .map(|(i, _)| i)
+        .collect();
+    for &i in railways.iter() {
+        let m = &mut mediums[i];
+        let on_line: Vec<usize> = m
+            .osm_node_refs
+            .iter()
+            .filter_map(|r| by_node.get(r).copied())
+            .collect();
+        if let MediumType::Railway(rail) = &mut m.medium_type {
+            for s in on_line {
+                rail.stations.push(stations[s].clone());
+                linked[s] = true;
+            }
+        }
+    }
+
+    if linked.iter().all(|l| *l) {
+        return 0;
+    }
+    let lines: Vec<Medium> = railways.iter().map(|&i| mediums[i].clone()).collect();
+    let index = MediumIndex::build(&lines);
+    let mut unlinked = 0;
+    for (s, station) in stations.into_iter().enumerate() {
+        if linked[s] {
+            continue;
+        }
+        match index.nearest(&station.position, 1).first() {
+            Some(hit) if hit.distance <= STATION_LINK_METERS => {
+                if let MediumType::Railway(rail) = &mut mediums[railways[hit.medium]].medium_type {
+                    rail.stations.push(station);
+                }
+            }
+            _ => unlinked += 1,
+        }
+    }
+    unlinked
+}
@@ -0,0 +1,95 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::types::medium::{Medium, MediumType, OsmType, Position};
+
+/// A relation that becomes a single Medium, kept until its member ways have been read.
+#[derive(Debug, Clone)]
+pub struct RelationMedium {
+    pub osm_id: i64,
+    pub name: Option<String>,
+    pub medium_type: MediumType,
+    /// Member way ids in relation order.
+    pub ways: Vec<i64>,
+    /// Tags of the relation kept by the tag mapping.
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Appends one Medium per relation, joining its member ways end to end.
+///
+/// Members are flipped where needed so the refs run continuously. Positions are
+/// carried over when every member already has them, otherwise they are left for
+/// the position pass. The Medium's `osm_id` is the relation id. Mediums made from
+/// other relations are never taken as members. Returns the number of relations
+/// dropped because none of their ways were found.
+pub fn assemble_relations(mediums: &mut Vec<Medium>, relations: Vec<RelationMedium>) -> usize {
+    let by_way: HashMap<i64, usize> = mediums
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.osm_type != Some(OsmType::Relation))
+        .filter_map(|(i, m)| m.osm_id.map(|id| (id, i)))
+        .collect();
+    let mut dropped = 0;
+    for relation in relations {
+        let members: Vec<&Medium> = relation
+            .ways
+            .iter()
+            .filter_map(|w| by_way.get(w).map(|&i| &mediums[i]))
+            .filter(|m| !m.osm_node_refs.is_empty())
+            .collect();
+        if members.is_empty() {
+            dropped += 1;
+            continue;
+        }
+        let (osm_node_refs, medium_positions) = join_ways(&members);
+        let mut medium = Medium::new();
+        medium.osm_id = Some(relation.osm_id);
+        medium.osm_type = Some(OsmType::Relation);
+        medium.medium_osm_name = relation.name;
+        medium.osm_tags = relation.tags;
+        medium.osm_node_refs = osm_node_refs;
+        medium.medium_positions = medium_positions;
+        medium.medium_type = relation.medium_type;
+        mediums.push(medium);
+    }
+    dropped
+}
+
+/// Joins ways into one run of refs, and of positions when every way has them.
+/// Ways that do not touch the run so far are appended as they are.
+fn join_ways(ways: &[&Medium]) -> (Vec<i64>, Vec<Position>) {
+    let with_positions = ways
+        .iter()
+        .all(|m| m.medium_positions.len() == m.osm_node_refs.len());
+    let mut chain: Vec<(i64, Option<&Position>)> = Vec::new();
+    for m in ways {
+        let mut nodes: Vec<(i64, Option<&Position>)> = m
+            .osm_node_refs
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (*r, m.medium_positions.get(i)))
+            .collect();
+        if let (Some(&(first, _)), Some(&(last, _))) = (chain.first(), chain.last()) {
+            let (start, end) = (nodes[0].0, nodes[nodes.len() - 1].0);
+            if last != start && last == end {
+                nodes.reverse();
+            } else if last != start && last != end && (first == start || first == end) {
+                // The chain so far may be running backwards.
+                chain.reverse();
+                if chain.last().map(|n| n.0) == Some(end) {
+                    nodes.reverse();
+                }
+            }
+            if chain.last().map(|n| n.0) == Some(nodes[0].0) {
+                nodes.remove(0);
+            }
+        }
+        chain.extend(nodes);
+    }
+    let refs = chain.iter().map(|n| n.0).collect();
+    let positions = if with_positions {
+        chain.iter().filter_map(|n| n.1.cloned()).collect()
+    } else {
+        Vec::new()
+    };
+    (refs, positions)
+}
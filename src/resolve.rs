@@ -0,0 +1,42 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{store::NodeLocationStore, types::medium::Medium};
+
+/// The node refs of one Medium that had no location in the input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MissingRefs {
+    pub osm_id: Option<i64>,
+    pub node_refs: Vec<i64>,
+}
+
+/// Fills `medium_positions` in `osm_node_refs` order.
+///
+/// Refs without a location are skipped and returned, grouped per Medium.
+pub fn fill_positions(
+    mediums: &mut [Medium],
+    locations: &dyn NodeLocationStore,
+) -> Vec<MissingRefs> {
+    mediums
+        .par_iter_mut()
+        .filter_map(|m| {
+            let mut positions = Vec::with_capacity(m.osm_node_refs.len());
+            let mut missing = Vec::new();
+            for r in m.osm_node_refs.iter() {
+                match locations.get(*r) {
+                    Some(pos) => positions.push(pos),
+                    None => missing.push(*r),
+                }
+            }
+            m.medium_positions = positions;
+            if missing.is_empty() {
+                None
+            } else {
+                Some(MissingRefs {
+                    osm_id: m.osm_id,
+                    node_refs: missing,
+                })
+            }
+        })
+        .collect()
+}
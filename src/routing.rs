@@ -0,0 +1,527 @@
+pub mod ch;
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph::{Edge, RoadGraph},
+    index::MediumIndex,
+    types::medium::{Medium, Position, StreetCategory},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Forward search guided by the straight-line distance to the destination.
+    AStar,
+    /// Searches from both ends at once and stops when they meet.
+    BidirectionalDijkstra,
+}
+
+/// What a route minimizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+    Distance,
+    Time,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RouteOptions {
+    pub algorithm: Algorithm,
+    pub metric: Metric,
+    /// How many nearby Mediums to try when snapping an endpoint to the network.
+    pub snap_candidates: usize,
+}
+
+impl Default for RouteOptions {
+    fn default() -> Self {
+        RouteOptions {
+            algorithm: Algorithm::AStar,
+            metric: Metric::Distance,
+            snap_candidates: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    /// osm_id of every Medium travelled along, in order.
+    pub medium_ids: Vec<i64>,
+    /// The route's geometry, from the snapped start to the snapped destination.
+    pub positions: Vec<Position>,
+    /// Meters.
+    pub distance: f64,
+    /// Seconds, from the typical speed of each street category.
+    pub duration: f64,
+}
+
+/// Typical travel speed in km/h used to estimate durations.
+pub fn speed_kmh(category: StreetCategory) -> f64 {
+    match category {
+        StreetCategory::Motorway => 100.0,
+        StreetCategory::MotorwayLink => 60.0,
+        StreetCategory::Trunk => 80.0,
+        StreetCategory::TrunkLink => 50.0,
+        StreetCategory::Primary => 65.0,
+        StreetCategory::PrimaryLink => 40.0,
+        StreetCategory::Secondary => 55.0,
+        StreetCategory::SecondaryLink => 35.0,
+        StreetCategory::Tertiary => 45.0,
+        StreetCategory::TertiaryLink => 30.0,
+        StreetCategory::Unclassified => 35.0,
+        StreetCategory::Residential => 25.0,
+        StreetCategory::LivingStreet => 10.0,
+        StreetCategory::Service => 15.0,
+        StreetCategory::Track => 15.0,
+        StreetCategory::Road => 30.0,
+        StreetCategory::Cycleway => 15.0,
+        StreetCategory::Pedestrian
+        | StreetCategory::Path
+        | StreetCategory::Footway
+        | StreetCategory::Crossing => 5.0,
+        StreetCategory::Default => 20.0,
+    }
+}
+
+/// The highest value of [`speed_kmh`], which keeps the A* time heuristic admissible.
+const MAX_SPEED_KMH: f64 = 100.0;
+
+/// Seconds needed to cover `meters` of an edge.
+pub fn travel_time(edge: &Edge, meters: f64) -> f64 {
+    meters / (speed_kmh(edge.category) / 3.6)
+}
+
+/// Weight of `meters` of an edge under `metric`.
+fn edge_cost(edge: &Edge, meters: f64, metric: Metric) -> f64 {
+    match metric {
+        Metric::Distance => meters,
+        Metric::Time => travel_time(edge, meters),
+    }
+}
+
+/// Where a query point lands on one edge.
+#[derive(Debug, Clone)]
+pub struct SnapEdge {
+    pub edge: usize,
+    /// Index of the edge segment, counted in travel order.
+    pub segment: usize,
+    /// Meters from the edge's start to the snapped point.
+    pub offset: f64,
+}
+
+/// A query point moved onto the network. Two-way Mediums give one
+/// [`SnapEdge`] per direction.
+#[derive(Debug, Clone)]
+pub struct Snap {
+    pub point: Position,
+    pub edges: Vec<SnapEdge>,
+}
+
+enum Pred {
+    Edge(usize),
+    Snap(usize),
+}
+
+/// The edges making up a route between two snapped points.
+enum Legs {
+    /// Both points lie on the same edge, the start before the destination.
+    Direct { from: usize, to: usize },
+    Through {
+        from: usize,
+        middle: Vec<usize>,
+        to: usize,
+    },
+}
+
+#[derive(PartialEq)]
+struct Queued {
+    priority: f64,
+    cost: f64,
+    vertex: usize,
+}
+
+impl Eq for Queued {}
+
+impl Ord for Queued {
+    // Reversed so that BinaryHeap pops the lowest priority first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.total_cmp(&self.priority)
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn top(heap: &BinaryHeap<Queued>) -> f64 {
+    heap.peek().map_or(f64::INFINITY, |q| q.priority)
+}
+
+/// Point-to-point routing over a [`RoadGraph`] and the Mediums it was built from.
+pub struct Router<'a> {
+    mediums: &'a [Medium],
+    graph: &'a RoadGraph,
+    index: &'a MediumIndex,
+    edges_by_medium: HashMap<usize, Vec<usize>>,
+}
+
+impl<'a> Router<'a> {
+    /// `graph` and `index` must both have been built from `mediums`.
+    pub fn new(mediums: &'a [Medium], graph: &'a RoadGraph, index: &'a MediumIndex) -> Router<'a> {
+        let mut edges_by_medium: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (e, edge) in graph.edges.iter().enumerate() {
+            edges_by_medium.entry(edge.medium).or_default().push(e);
+        }
+        Router {
+            mediums,
+            graph,
+            index,
+            edges_by_medium,
+        }
+    }
+
+    pub fn graph(&self) -> &RoadGraph {
+        self.graph
+    }
+
+    /// Moves `pos` onto the closest Medium that is part of the graph.
+    pub fn snap(&self, pos: &Position, candidates: usize) -> Option<Snap> {
+        for hit in self.index.nearest(pos, candidates) {
+            let Some(edges) = self.edges_by_medium.get(&hit.medium) else {
+                continue;
+            };
+            let s = hit.segment;
+            let mut snap_edges = Vec::new();
+            for &e in edges {
+                let edge = &self.graph.edges[e];
+                if s < edge.first.min(edge.last) || s >= edge.first.max(edge.last) {
+                    continue;
+                }
+                let travel = edge.positions(self.mediums);
+                let (segment, fraction) = if edge.first <= edge.last {
+                    (s - edge.first, hit.fraction)
+                } else {
+                    (edge.first - s - 1, 1.0 - hit.fraction)
+                };
+                let offset = travel[..=segment]
+                    .windows(2)
+                    .map(|w| w[0].haversine_distance(w[1]))
+                    .sum::<f64>()
+                    + fraction * travel[segment].haversine_distance(travel[segment + 1]);
+                snap_edges.push(SnapEdge {
+                    edge: e,
+                    segment,
+                    offset,
+                });
+            }
+            if !snap_edges.is_empty() {
+                return Some(Snap {
+                    point: hit.closest,
+                    edges: snap_edges,
+                });
+            }
+        }
+        None
+    }
+
+    /// Routes from `from` to `to`, or `None` when either end cannot be snapped or
+    /// the destination is unreachable.
+    pub fn route(&self, from: &Position, to: &Position, options: &RouteOptions) -> Option<Route> {
+        let source = self.snap(from, options.snap_candidates)?;
+        let target = self.snap(to, options.snap_candidates)?;
+        self.route_between(&source, &target, options)
+    }
+
+    /// Routes between two already snapped points.
+    pub fn route_between(
+        &self,
+        source: &Snap,
+        target: &Snap,
+        options: &RouteOptions,
+    ) -> Option<Route> {
+        let legs = match options.algorithm {
+            Algorithm::AStar => self.a_star(source, target, options.metric),
+            Algorithm::BidirectionalDijkstra => {
+                self.bidirectional_dijkstra(source, target, options.metric)
+            }
+        }?;
+        Some(self.assemble(source, target, legs))
+    }
+
+    /// Cheapest way of staying on one edge, if the destination lies ahead of the start on it.
+    fn direct(&self, source: &Snap, target: &Snap, metric: Metric) -> (f64, Option<Legs>) {
+        let mut best = (f64::INFINITY, None);
+        for (i, a) in source.edges.iter().enumerate() {
+            for (j, b) in target.edges.iter().enumerate() {
+                if a.edge == b.edge && a.offset <= b.offset {
+                    let cost = edge_cost(&self.graph.edges[a.edge], b.offset - a.offset, metric);
+                    if cost < best.0 {
+                        best = (cost, Some(Legs::Direct { from: i, to: j }));
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Cost from the source snap to the end of each of its edges.
+    fn source_seeds(&self, source: &Snap, metric: Metric) -> HashMap<usize, (f64, usize)> {
+        let mut seeds: HashMap<usize, (f64, usize)> = HashMap::new();
+        for (i, s) in source.edges.iter().enumerate() {
+            let edge = &self.graph.edges[s.edge];
+            let cost = edge_cost(edge, edge.length - s.offset, metric);
+            if seeds.get(&edge.to).is_none_or(|(c, _)| cost < *c) {
+                seeds.insert(edge.to, (cost, i));
+            }
+        }
+        seeds
+    }
+
+    /// Cost from the start of each of the target snap's edges to the target.
+    fn target_seeds(&self, target: &Snap, metric: Metric) -> HashMap<usize, (f64, usize)> {
+        let mut seeds: HashMap<usize, (f64, usize)> = HashMap::new();
+        for (j, t) in target.edges.iter().enumerate() {
+            let edge = &self.graph.edges[t.edge];
+            let cost = edge_cost(edge, t.offset, metric);
+            if seeds.get(&edge.from).is_none_or(|(c, _)| cost < *c) {
+                seeds.insert(edge.from, (cost, j));
+            }
+        }
+        seeds
+    }
+
+    fn a_star(&self, source: &Snap, target: &Snap, metric: Metric) -> Option<Legs> {
+        let (mut best, mut legs) = self.direct(source, target, metric);
+        let heuristic = |v: usize| {
+            let meters = self.graph.vertices[v]
+                .position
+                .haversine_distance(&target.point);
+            match metric {
+                Metric::Distance => meters,
+                Metric::Time => meters / (MAX_SPEED_KMH / 3.6),
+            }
+        };
+        let residual = self.target_seeds(target, metric);
+        let mut cost: HashMap<usize, f64> = HashMap::new();
+        let mut pred: HashMap<usize, Pred> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        for (v, (c, i)) in self.source_seeds(source, metric) {
+            cost.insert(v, c);
+            pred.insert(v, Pred::Snap(i));
+            heap.push(Queued {
+                priority: c + heuristic(v),
+                cost: c,
+                vertex: v,
+            });
+        }
+        let mut meet = None;
+        while let Some(Queued {
+            priority,
+            cost: c,
+            vertex: v,
+        }) = heap.pop()
+        {
+            if priority >= best {
+                break;
+            }
+            if c > cost[&v] {
+                continue;
+            }
+            if let Some((r, j)) = residual.get(&v) {
+                if c + r < best {
+                    best = c + r;
+                    meet = Some((v, *j));
+                }
+            }
+            for &e in self.graph.outgoing(v) {
+                let edge = &self.graph.edges[e];
+                let next = c + edge_cost(edge, edge.length, metric);
+                if cost.get(&edge.to).is_none_or(|old| next < *old) {
+                    cost.insert(edge.to, next);
+                    pred.insert(edge.to, Pred::Edge(e));
+                    heap.push(Queued {
+                        priority: next + heuristic(edge.to),
+                        cost: next,
+                        vertex: edge.to,
+                    });
+                }
+            }
+        }
+        if let Some((v, to)) = meet {
+            let (from, middle) = self.unwind_forward(&pred, v);
+            legs = Some(Legs::Through { from, middle, to });
+        }
+        legs
+    }
+
+    fn bidirectional_dijkstra(&self, source: &Snap, target: &Snap, metric: Metric) -> Option<Legs> {
+        let (mut best, mut legs) = self.direct(source, target, metric);
+        let mut cost_f: HashMap<usize, f64> = HashMap::new();
+        let mut cost_b: HashMap<usize, f64> = HashMap::new();
+        let mut pred_f: HashMap<usize, Pred> = HashMap::new();
+        let mut pred_b: HashMap<usize, Pred> = HashMap::new();
+        let mut heap_f = BinaryHeap::new();
+        let mut heap_b = BinaryHeap::new();
+        let mut meet = None;
+        for (v, (c, i)) in self.source_seeds(source, metric) {
+            cost_f.insert(v, c);
+            pred_f.insert(v, Pred::Snap(i));
+            heap_f.push(Queued {
+                priority: c,
+                cost: c,
+                vertex: v,
+            });
+        }
+        for (v, (c, j)) in self.target_seeds(target, metric) {
+            cost_b.insert(v, c);
+            pred_b.insert(v, Pred::Snap(j));
+            heap_b.push(Queued {
+                priority: c,
+                cost: c,
+                vertex: v,
+            });
+            if let Some(f) = cost_f.get(&v) {
+                if f + c < best {
+                    best = f + c;
+                    meet = Some(v);
+                }
+            }
+        }
+        while top(&heap_f) + top(&heap_b) < best {
+            let forward = top(&heap_f) <= top(&heap_b);
+            let (heap, cost, pred, other) = if forward {
+                (&mut heap_f, &mut cost_f, &mut pred_f, &cost_b)
+            } else {
+                (&mut heap_b, &mut cost_b, &mut pred_b, &cost_f)
+            };
+            let Some(Queued {
+                cost: c, vertex: v, ..
+            }) = heap.pop()
+            else {
+                break;
+            };
+            if c > cost[&v] {
+                continue;
+            }
+            let edges = if forward {
+                self.graph.outgoing(v)
+            } else {
+                self.graph.incoming(v)
+            };
+            for &e in edges {
+                let edge = &self.graph.edges[e];
+                let u = if forward { edge.to } else { edge.from };
+                let next = c + edge_cost(edge, edge.length, metric);
+                if cost.get(&u).is_none_or(|old| next < *old) {
+                    cost.insert(u, next);
+                    pred.insert(u, Pred::Edge(e));
+                    heap.push(Queued {
+                        priority: next,
+                        cost: next,
+                        vertex: u,
+                    });
+                    if let Some(o) = other.get(&u) {
+                        if next + o < best {
+                            best = next + o;
+                            meet = Some(u);
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(v) = meet {
+            let (from, middle) = self.unwind_forward(&pred_f, v);
+            let (mut rest, to) = self.unwind_backward(&pred_b, v);
+            let mut middle = middle;
+            middle.append(&mut rest);
+            legs = Some(Legs::Through { from, middle, to });
+        }
+        legs
+    }
+
+    /// Edges from the source snap to `v`, and which source edge the path starts on.
+    fn unwind_forward(&self, pred: &HashMap<usize, Pred>, mut v: usize) -> (usize, Vec<usize>) {
+        let mut edges = Vec::new();
+        loop {
+            match pred[&v] {
+                Pred::Edge(e) => {
+                    edges.push(e);
+                    v = self.graph.edges[e].from;
+                }
+                Pred::Snap(i) => {
+                    edges.reverse();
+                    return (i, edges);
+                }
+            }
+        }
+    }
+
+    /// Edges from `v` to the target snap, and which target edge the path ends on.
+    fn unwind_backward(&self, pred: &HashMap<usize, Pred>, mut v: usize) -> (Vec<usize>, usize) {
+        let mut edges = Vec::new();
+        loop {
+            match pred[&v] {
+                Pred::Edge(e) => {
+                    edges.push(e);
+                    v = self.graph.edges[e].to;
+                }
+                Pred::Snap(j) => return (edges, j),
+            }
+        }
+    }
+
+    fn assemble(&self, source: &Snap, target: &Snap, legs: Legs) -> Route {
+        let mut route = Route {
+            medium_ids: Vec::new(),
+            positions: vec![source.point.clone()],
+            distance: 0.0,
+            duration: 0.0,
+        };
+        let mut travel = |edge: &Edge, meters: f64, positions: &[&Position]| {
+            if let Some(id) = edge.medium_osm_id {
+                if route.medium_ids.last() != Some(&id) {
+                    route.medium_ids.push(id);
+                }
+            }
+            route
+                .positions
+                .extend(positions.iter().map(|p| (*p).clone()));
+            route.distance += meters;
+            route.duration += travel_time(edge, meters);
+        };
+        match legs {
+            Legs::Direct { from, to } => {
+                let (a, b) = (&source.edges[from], &target.edges[to]);
+                let edge = &self.graph.edges[a.edge];
+                let positions = edge.positions(self.mediums);
+                travel(
+                    edge,
+                    b.offset - a.offset,
+                    &positions[a.segment + 1..=b.segment],
+                );
+            }
+            Legs::Through { from, middle, to } => {
+                let a = &source.edges[from];
+                let edge = &self.graph.edges[a.edge];
+                let positions = edge.positions(self.mediums);
+                travel(edge, edge.length - a.offset, &positions[a.segment + 1..]);
+                for e in middle {
+                    let edge = &self.graph.edges[e];
+                    let positions = edge.positions(self.mediums);
+                    travel(edge, edge.length, &positions[1..]);
+                }
+                let b = &target.edges[to];
+                let edge = &self.graph.edges[b.edge];
+                let positions = edge.positions(self.mediums);
+                travel(edge, b.offset, &positions[1..=b.segment]);
+            }
+        }
+        route.positions.push(target.point.clone());
+        route
+    }
+}
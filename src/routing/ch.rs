@@ -0,0 +1,704 @@
+use std::{
+    collections::{BinaryHeap, HashMap},
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{top, Legs, Metric, Pred, Queued, Route, Router};
+use crate::{error::Result, graph::RoadGraph, types::medium::Position};
+
+/// Nodes settled by a witness search before it gives up and a shortcut is added anyway.
+const WITNESS_SETTLE_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ChEdgeKind {
+    /// An edge of the [`RoadGraph`].
+    Original(usize),
+    /// Stands for two consecutive hierarchy edges through a contracted vertex.
+    Shortcut(usize, usize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChEdge {
+    from: usize,
+    to: usize,
+    weight: f64,
+    kind: ChEdgeKind,
+}
+
+/// A contraction hierarchy over a [`RoadGraph`] for one [`Metric`].
+///
+/// Built once offline with [`ContractionHierarchy::build`] and saved to disk;
+/// queries then only search upwards in the hierarchy from both ends, which
+/// touches a few hundred vertices even on a country-sized network.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractionHierarchy {
+    pub metric: Metric,
+    /// Size of the graph the hierarchy was built from, checked on use.
+    graph_vertices: usize,
+    graph_edges: usize,
+    /// [`graph_checksum`] of that graph, which changes with any edge or weight.
+    graph_checksum: u64,
+    rank: Vec<usize>,
+    edges: Vec<ChEdge>,
+    /// Edges to higher ranked vertices, by source: `up_edges[up_index[v]..up_index[v + 1]]`.
+    up_index: Vec<usize>,
+    up_edges: Vec<usize>,
+    /// Edges from higher ranked vertices, by target, laid out like the upward ones.
+    down_index: Vec<usize>,
+    down_edges: Vec<usize>,
+}
+
+/// Where the hierarchy for a JSON output is kept: `kenya.json` -> `kenya.ch-time.bin`.
+pub fn hierarchy_path_for(out_file: &Path, metric: Metric) -> PathBuf {
+    match metric {
+        Metric::Distance => out_file.with_extension("ch-distance.bin"),
+        Metric::Time => out_file.with_extension("ch-time.bin"),
+    }
+}
+
+/// Adjacency used while contracting: (neighbour, weight, hierarchy edge id).
+type Adjacency = Vec<Vec<(usize, f64, usize)>>;
+
+struct Contraction {
+    edges: Vec<ChEdge>,
+    outgoing: Adjacency,
+    incoming: Adjacency,
+    contracted: Vec<bool>,
+    contracted_neighbours: Vec<usize>,
+}
+
+impl Contraction {
+    fn add_edge(&mut self, edge: ChEdge) {
+        let id = self.edges.len();
+        self.outgoing[edge.from].push((edge.to, edge.weight, id));
+        self.incoming[edge.to].push((edge.from, edge.weight, id));
+        self.edges.push(edge);
+    }
+
+    /// Shortest distance from `source` to each of `targets` without passing
+    /// through `skip`, as far as a bounded search gets.
+    fn witness_distances(
+        &self,
+        source: usize,
+        skip: usize,
+        targets: &[usize],
+        limit: f64,
+    ) -> HashMap<usize, f64> {
+        let mut cost: HashMap<usize, f64> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        cost.insert(source, 0.0);
+        heap.push(Queued {
+            priority: 0.0,
+            cost: 0.0,
+            vertex: source,
+        });
+        let mut settled = 0;
+        let mut remaining = targets.len();
+        while let Some(Queued {
+            cost: c, vertex: v, ..
+        }) = heap.pop()
+        {
+            if c > cost[&v] {
+                continue;
+            }
+            if c > limit || settled >= WITNESS_SETTLE_LIMIT {
+                break;
+            }
+            settled += 1;
+            if targets.contains(&v) {
+                remaining -= 1;
+                if remaining == 0 {
+                    break;
+                }
+            }
+            for &(u, w, _) in self.outgoing[v].iter() {
+                if u == skip || self.contracted[u] {
+                    continue;
+                }
+                let next = c + w;
+                if cost.get(&u).is_none_or(|old| next < *old) {
+                    cost.insert(u, next);
+                    heap.push(Queued {
+                        priority: next,
+                        cost: next,
+                        vertex: u,
+                    });
+                }
+            }
+        }
+        cost
+    }
+
+    /// Shortcuts needed to contract `v`: (from, to, weight, in edge, out edge).
+    fn shortcuts(&self, v: usize) -> Vec<(usize, usize, f64, usize, usize)> {
+        let incoming = live_neighbours(&self.incoming[v], &self.contracted, v);
+        let outgoing = live_neighbours(&self.outgoing[v], &self.contracted, v);
+        let mut shortcuts = Vec::new();
+        if incoming.is_empty() || outgoing.is_empty() {
+            return shortcuts;
+        }
+        let max_out = outgoing.iter().map(|o| o.1).fold(0.0, f64::max);
+        for &(u, w_in, e_in) in incoming.iter() {
+            let targets: Vec<usize> = outgoing.iter().map(|o| o.0).filter(|x| *x != u).collect();
+            if targets.is_empty() {
+                continue;
+            }
+            let witness = self.witness_distances(u, v, &targets, w_in + max_out);
+            for &(x, w_out, e_out) in outgoing.iter() {
+                if x == u {
+                    continue;
+                }
+                let via = w_in + w_out;
+                if witness.get(&x).is_none_or(|w| *w > via) {
+                    shortcuts.push((u, x, via, e_in, e_out));
+                }
+            }
+        }
+        shortcuts
+    }
+
+    fn priority(&self, v: usize) -> i64 {
+        let removed = live_neighbours(&self.incoming[v], &self.contracted, v).len()
+            + live_neighbours(&self.outgoing[v], &self.contracted, v).len();
+        self.shortcuts(v).len() as i64 - removed as i64 + self.contracted_neighbours[v] as i64
+    }
+}
+
+/// Cheapest live edge to each neighbour, leaving out self loops.
+/// FNV-1a over the endpoints and `metric` weight of every edge of `graph`.
+fn graph_checksum(graph: &RoadGraph, metric: Metric) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for edge in graph.edges.iter() {
+        let weight = super::edge_cost(edge, edge.length, metric);
+        let words = [edge.from as u64, edge.to as u64, weight.to_bits()];
+        for byte in words.iter().flat_map(|w| w.to_le_bytes()) {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+fn live_neighbours(
+    adjacency: &[(usize, f64, usize)],
+    contracted: &[bool],
+    v: usize,
+) -> Vec<(usize, f64, usize)> {
+    let mut best: HashMap<usize, (f64, usize)> = HashMap::new();
+    for &(u, w, e) in adjacency {
+        if u == v || contracted[u] {
+            continue;
+        }
+        if best.get(&u).is_none_or(|(old, _)| w < *old) {
+            best.insert(u, (w, e));
+        }
+    }
+    best.into_iter().map(|(u, (w, e))| (u, w, e)).collect()
+}
+
+fn group(
+    vertices: usize,
+    edges: &[ChEdge],
+    key: impl Fn(&ChEdge) -> Option<usize>,
+) -> (Vec<usize>, Vec<usize>) {
+    let mut index = vec![0; vertices + 1];
+    for e in edges.iter() {
+        if let Some(k) = key(e) {
+            index[k + 1] += 1;
+        }
+    }
+    for v in 0..vertices {
+        index[v + 1] += index[v];
+    }
+    let mut next = index.clone();
+    let mut grouped = vec![0; index[vertices]];
+    for (id, e) in edges.iter().enumerate() {
+        if let Some(k) = key(e) {
+            grouped[next[k]] = id;
+            next[k] += 1;
+        }
+    }
+    (index, grouped)
+}
+
+impl ContractionHierarchy {
+    /// Contracts every vertex of `graph`, cheapest first by edge difference.
+    pub fn build(graph: &RoadGraph, metric: Metric) -> ContractionHierarchy {
+        let n = graph.vertices.len();
+        let mut contraction = Contraction {
+            edges: Vec::new(),
+            outgoing: vec![Vec::new(); n],
+            incoming: vec![Vec::new(); n],
+            contracted: vec![false; n],
+            contracted_neighbours: vec![0; n],
+        };
+        for (id, edge) in graph.edges.iter().enumerate() {
+            if edge.from == edge.to {
+                continue;
+            }
+            contraction.add_edge(ChEdge {
+                from: edge.from,
+                to: edge.to,
+                weight: super::edge_cost(edge, edge.length, metric),
+                kind: ChEdgeKind::Original(id),
+            });
+        }
+
+        let mut queue: BinaryHeap<std::cmp::Reverse<(i64, usize)>> = (0..n)
+            .map(|v| std::cmp::Reverse((contraction.priority(v), v)))
+            .collect();
+        let mut rank = vec![0; n];
+        let mut next_rank = 0;
+        while let Some(std::cmp::Reverse((_, v))) = queue.pop() {
+            if contraction.contracted[v] {
+                continue;
+            }
+            // Lazy update: only contract if v is still the cheapest.
+            let priority = contraction.priority(v);
+            if let Some(std::cmp::Reverse((next, _))) = queue.peek() {
+                if priority > *next {
+                    queue.push(std::cmp::Reverse((priority, v)));
+                    continue;
+                }
+            }
+            for (from, to, weight, e_in, e_out) in contraction.shortcuts(v) {
+                contraction.add_edge(ChEdge {
+                    from,
+                    to,
+                    weight,
+                    kind: ChEdgeKind::Shortcut(e_in, e_out),
+                });
+            }
+            contraction.contracted[v] = true;
+            rank[v] = next_rank;
+            next_rank += 1;
+            for &(u, _, _) in contraction.incoming[v]
+                .iter()
+                .chain(contraction.outgoing[v].iter())
+            {
+                contraction.contracted_neighbours[u] += 1;
+            }
+        }
+
+        let edges = contraction.edges;
+        let (up_index, up_edges) =
+            group(n, &edges, |e| (rank[e.to] > rank[e.from]).then_some(e.from));
+        let (down_index, down_edges) =
+            group(n, &edges, |e| (rank[e.from] > rank[e.to]).then_some(e.to));
+        ContractionHierarchy {
+            metric,
+            graph_vertices: n,
+            graph_edges: graph.edges.len(),
+            graph_checksum: graph_checksum(graph, metric),
+            rank,
+            edges,
+            up_index,
+            up_edges,
+            down_index,
+            down_edges,
+        }
+    }
+
+    /// Whether the hierarchy was built from this graph: the same edges between the
+    /// same vertices, with the same weights.
+    pub fn matches(&self, graph: &RoadGraph) -> bool {
+        self.graph_vertices == graph.vertices.len()
+            && self.graph_edges == graph.edges.len()
+            && self.graph_checksum == graph_checksum(graph, self.metric)
+    }
+
+    pub fn shortcut_count(&self) -> usize {
+        self.edges
+            .iter()
+            .filter(|e| matches!(e.kind, ChEdgeKind::Shortcut(..)))
+            .count()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<ContractionHierarchy> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(bincode::deserialize_from(reader)?)
+    }
+
+    fn upward(&self, v: usize) -> &[usize] {
+        &self.up_edges[self.up_index[v]..self.up_index[v + 1]]
+    }
+
+    fn downward(&self, v: usize) -> &[usize] {
+        &self.down_edges[self.down_index[v]..self.down_index[v + 1]]
+    }
+
+    /// Appends the graph edges a hierarchy edge stands for, in travel order.
+    fn unpack(&self, edge: usize, out: &mut Vec<usize>) {
+        let mut stack = vec![edge];
+        while let Some(e) = stack.pop() {
+            match self.edges[e].kind {
+                ChEdgeKind::Original(id) => out.push(id),
+                ChEdgeKind::Shortcut(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+            }
+        }
+    }
+
+    /// Complete upward search from `seeds`, following upward edges when
+    /// `forward` and downward edges backwards otherwise.
+    fn search(
+        &self,
+        seeds: &HashMap<usize, (f64, usize)>,
+        forward: bool,
+    ) -> (HashMap<usize, f64>, HashMap<usize, Pred>) {
+        let mut cost: HashMap<usize, f64> = HashMap::new();
+        let mut pred: HashMap<usize, Pred> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        for (&v, &(c, i)) in seeds.iter() {
+            cost.insert(v, c);
+            pred.insert(v, Pred::Snap(i));
+            heap.push(Queued {
+                priority: c,
+                cost: c,
+                vertex: v,
+            });
+        }
+        while let Some(Queued {
+            cost: c, vertex: v, ..
+        }) = heap.pop()
+        {
+            if c > cost[&v] {
+                continue;
+            }
+            let edges = if forward {
+                self.upward(v)
+            } else {
+                self.downward(v)
+            };
+            for &e in edges {
+                let edge = &self.edges[e];
+                let u = if forward { edge.to } else { edge.from };
+                let next = c + edge.weight;
+                if cost.get(&u).is_none_or(|old| next < *old) {
+                    cost.insert(u, next);
+                    pred.insert(u, Pred::Edge(e));
+                    heap.push(Queued {
+                        priority: next,
+                        cost: next,
+                        vertex: u,
+                    });
+                }
+            }
+        }
+        (cost, pred)
+    }
+}
+
+/// Answers queries with a [`ContractionHierarchy`], snapping and assembling
+/// routes through the [`Router`] of the graph it was built from.
+pub struct ChQuery<'a> {
+    router: &'a Router<'a>,
+    ch: &'a ContractionHierarchy,
+}
+
+impl<'a> ChQuery<'a> {
+    /// Returns `None` when the hierarchy was not built from the router's graph.
+    pub fn new(router: &'a Router<'a>, ch: &'a ContractionHierarchy) -> Option<ChQuery<'a>> {
+        if !ch.matches(router.graph()) {
+            return None;
+        }
+        Some(ChQuery { router, ch })
+    }
+
+    pub fn route(&self, from: &Position, to: &Position, snap_candidates: usize) -> Option<Route> {
+        let source = self.router.snap(from, snap_candidates)?;
+        let target = self.router.snap(to, snap_candidates)?;
+        let metric = self.ch.metric;
+        let (mut best, mut legs) = self.router.direct(&source, &target, metric);
+        let mut cost_f: HashMap<usize, f64> = HashMap::new();
+        let mut cost_b: HashMap<usize, f64> = HashMap::new();
+        let mut pred_f: HashMap<usize, Pred> = HashMap::new();
+        let mut pred_b: HashMap<usize, Pred> = HashMap::new();
+        let mut heap_f = BinaryHeap::new();
+        let mut heap_b = BinaryHeap::new();
+        let mut meet = None;
+        for (v, (c, i)) in self.router.source_seeds(&source, metric) {
+            cost_f.insert(v, c);
+            pred_f.insert(v, Pred::Snap(i));
+            heap_f.push(Queued {
+                priority: c,
+                cost: c,
+                vertex: v,
+            });
+        }
+        for (v, (c, j)) in self.router.target_seeds(&target, metric) {
+            cost_b.insert(v, c);
+            pred_b.insert(v, Pred::Snap(j));
+            heap_b.push(Queued {
+                priority: c,
+                cost: c,
+                vertex: v,
+            });
+            if let Some(f) = cost_f.get(&v) {
+                if f + c < best {
+                    best = f + c;
+                    meet = Some(v);
+                }
+            }
+        }
+        // Unlike plain bidirectional Dijkstra the two upward searches may only
+        // stop once both of them are past the best meeting point.
+        while top(&heap_f).min(top(&heap_b)) < best {
+            let forward = top(&heap_f) <= top(&heap_b);
+            let (heap, cost, pred, other) = if forward {
+                (&mut heap_f, &mut cost_f, &mut pred_f, &cost_b)
+            } else {
+                (&mut heap_b, &mut cost_b, &mut pred_b, &cost_f)
+            };
+            let Some(Queued {
+                cost: c, vertex: v, ..
+            }) = heap.pop()
+            else {
+                break;
+            };
+            if c > cost[&v] {
+                continue;
+            }
+            let edges = if forward {
+                self.ch.upward(v)
+            } else {
+                self.ch.downward(v)
+            };
+            for &e in edges {
+                let edge = &self.ch.edges[e];
+                let u = if forward { edge.to } else { edge.from };
+                let next = c + edge.weight;
+                if cost.get(&u).is_none_or(|old| next < *old) {
+                    cost.insert(u, next);
+                    pred.insert(u, Pred::Edge(e));
+                    heap.push(Queued {
+                        priority: next,
+                        cost: next,
+                        vertex: u,
+                    });
+                    if let Some(o) = other.get(&u) {
+                        if next + o < best {
+                            best = next + o;
+                            meet = Some(u);
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(v) = meet {
+            let (from, mut middle) = self.unwind(&pred_f, v, true);
+            let (to, rest) = self.unwind(&pred_b, v, false);
+            middle.extend(rest);
+            legs = Some(Legs::Through { from, middle, to });
+        }
+        Some(self.router.assemble(&source, &target, legs?))
+    }
+
+    /// Cost in the hierarchy's metric (meters or seconds) from `from` to each of
+    /// `targets`; `None` for targets that cannot be snapped or reached.
+    pub fn one_to_many(
+        &self,
+        from: &Position,
+        targets: &[Position],
+        snap_candidates: usize,
+    ) -> Vec<Option<f64>> {
+        let metric = self.ch.metric;
+        let Some(source) = self.router.snap(from, snap_candidates) else {
+            return vec![None; targets.len()];
+        };
+        let mut best = vec![f64::INFINITY; targets.len()];
+        // Each target leaves the cost of its backward search in a bucket at
+        // every vertex it reaches; one forward search then collects them.
+        let mut buckets: HashMap<usize, Vec<(usize, f64)>> = HashMap::new();
+        for (j, to) in targets.iter().enumerate() {
+            let Some(target) = self.router.snap(to, snap_candidates) else {
+                continue;
+            };
+            best[j] = self.router.direct(&source, &target, metric).0;
+            let (cost, _) = self
+                .ch
+                .search(&self.router.target_seeds(&target, metric), false);
+            for (v, c) in cost {
+                buckets.entry(v).or_default().push((j, c));
+            }
+        }
+        let (cost, _) = self
+            .ch
+            .search(&self.router.source_seeds(&source, metric), true);
+        for (v, c) in cost {
+            if let Some(bucket) = buckets.get(&v) {
+                for &(j, b) in bucket {
+                    best[j] = best[j].min(c + b);
+                }
+            }
+        }
+        best.into_iter()
+            .map(|c| c.is_finite().then_some(c))
+            .collect()
+    }
+
+    /// Graph edges from the snap to `v` (forward) or from `v` to the snap
+    /// (backward), with the index of the snap edge the path starts or ends on.
+    fn unwind(
+        &self,
+        pred: &HashMap<usize, Pred>,
+        mut v: usize,
+        forward: bool,
+    ) -> (usize, Vec<usize>) {
+        let mut ch_edges = Vec::new();
+        let snap = loop {
+            match pred[&v] {
+                Pred::Edge(e) => {
+                    ch_edges.push(e);
+                    v = if forward {
+                        self.ch.edges[e].from
+                    } else {
+                        self.ch.edges[e].to
+                    };
+                }
+                Pred::Snap(i) => break i,
+            }
+        };
+        if forward {
+            ch_edges.reverse();
+        }
+        let mut edges = Vec::new();
+        for e in ch_edges {
+            self.ch.unpack(e, &mut edges);
+        }
+        (snap, edges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        index::MediumIndex,
+        routing::{Algorithm, RouteOptions},
+        types::medium::{Medium, MediumType, StreetCategory},
+    };
+
+    const SIZE: i64 = 6;
+    const CATEGORIES: [StreetCategory; 3] = [
+        StreetCategory::Primary,
+        StreetCategory::Residential,
+        StreetCategory::Tertiary,
+    ];
+
+    /// A slightly uneven grid of streets, with every third row one-way.
+    fn grid() -> Vec<Medium> {
+        let node = |x: i64, y: i64| x * SIZE + y;
+        let position = |id: i64| Position {
+            longitude: 36.8 + 0.01 * (id / SIZE) as f64 + 0.0007 * ((id * 7) % 5) as f64,
+            latitude: -1.3 + 0.01 * (id % SIZE) as f64 + 0.0005 * ((id * 3) % 4) as f64,
+        };
+        let street = |osm_id: i64, refs: Vec<i64>, one_way: bool| {
+            let mut medium = Medium::new();
+            medium.osm_id = Some(osm_id);
+            medium.medium_type = MediumType::Highway(vec![CATEGORIES[osm_id as usize % 3]]);
+            medium.is_one_way = one_way;
+            medium.medium_positions = refs.iter().map(|&r| position(r)).collect();
+            medium.osm_node_refs = refs;
+            medium
+        };
+        let mut mediums = Vec::new();
+        for y in 0..SIZE {
+            let refs = (0..SIZE).map(|x| node(x, y)).collect();
+            mediums.push(street(y, refs, y % 3 == 1));
+        }
+        for x in 0..SIZE {
+            let refs = (0..SIZE).map(|y| node(x, y)).collect();
+            mediums.push(street(SIZE + x, refs, false));
+        }
+        mediums
+    }
+
+    /// Points between the grid's intersections, so routes start and end mid-edge.
+    fn points() -> Vec<Position> {
+        (0..SIZE - 1)
+            .flat_map(|x| {
+                (0..SIZE).map(move |y| Position {
+                    longitude: 36.8045 + 0.01 * x as f64,
+                    latitude: -1.3 + 0.01 * y as f64 + 0.0003,
+                })
+            })
+            .collect()
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6 * a.max(1.0), "{a} != {b}");
+    }
+
+    fn check(metric: Metric) {
+        let mediums = grid();
+        let graph = RoadGraph::from_mediums(&mediums);
+        let index = MediumIndex::build(&mediums);
+        let router = Router::new(&mediums, &graph, &index);
+        let ch = ContractionHierarchy::build(&graph, metric);
+        let query = ChQuery::new(&router, &ch).unwrap();
+        let options = RouteOptions {
+            algorithm: Algorithm::BidirectionalDijkstra,
+            metric,
+            snap_candidates: 8,
+        };
+        let cost = |route: &Route| match metric {
+            Metric::Distance => route.distance,
+            Metric::Time => route.duration,
+        };
+        let points = points();
+        for from in points.iter() {
+            let many = query.one_to_many(from, &points, options.snap_candidates);
+            for (to, many) in points.iter().zip(many) {
+                let dijkstra = router.route(from, to, &options).unwrap();
+                let ch_route = query.route(from, to, options.snap_candidates).unwrap();
+                assert_close(cost(&ch_route), cost(&dijkstra));
+                assert_close(many.unwrap(), cost(&dijkstra));
+            }
+        }
+    }
+
+    #[test]
+    fn distances_match_dijkstra() {
+        check(Metric::Distance);
+    }
+
+    #[test]
+    fn durations_match_dijkstra() {
+        check(Metric::Time);
+    }
+
+    #[test]
+    fn rejects_other_graphs() {
+        let mediums = grid();
+        let graph = RoadGraph::from_mediums(&mediums);
+        let ch = ContractionHierarchy::build(&graph, Metric::Distance);
+        assert!(ch.matches(&graph));
+        let smaller = RoadGraph::from_mediums(&mediums[1..]);
+        assert!(!ch.matches(&smaller));
+
+        // Same shape, but a node moved, as after `update`.
+        let mut moved = mediums.clone();
+        moved[0].medium_positions[0].latitude -= 0.001;
+        assert!(!ch.matches(&RoadGraph::from_mediums(&moved)));
+
+        // Same shape and lengths, but slower, which only the time hierarchy sees.
+        let mut slower = mediums.clone();
+        slower[0].medium_type = MediumType::Highway(vec![StreetCategory::Track]);
+        let slower = RoadGraph::from_mediums(&slower);
+        assert!(ch.matches(&slower));
+        assert!(!ContractionHierarchy::build(&graph, Metric::Time).matches(&slower));
+    }
+}
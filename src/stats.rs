@@ -0,0 +1,94 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    graph::street_category,
+    types::medium::{BoundingBox, Medium},
+};
+
+/// Totals for one kind of Medium.
+#[derive(Debug, Clone, Default)]
+pub struct KindStats {
+    pub mediums: usize,
+    pub positions: usize,
+    /// Summed length in meters.
+    pub length: f64,
+}
+
+/// Summary of a Medium dataset.
+#[derive(Debug, Clone, Default)]
+pub struct DatasetStats {
+    pub by_type: BTreeMap<&'static str, KindStats>,
+    /// Highway Mediums by the category their edges would get in a [`RoadGraph`](crate::graph::RoadGraph).
+    pub street_categories: BTreeMap<String, usize>,
+    /// Mediums with fewer positions than node refs.
+    pub incomplete: usize,
+    pub bbox: Option<BoundingBox>,
+}
+
+impl DatasetStats {
+    pub fn collect(mediums: &[Medium]) -> DatasetStats {
+        let mut stats = DatasetStats::default();
+        for m in mediums {
+            let kind = stats.by_type.entry(m.medium_type.name()).or_default();
+            kind.mediums += 1;
+            kind.positions += m.medium_positions.len();
+            kind.length += m.length();
+            if let Some(category) = street_category(m) {
+                *stats
+                    .street_categories
+                    .entry(format!("{:?}", category))
+                    .or_default() += 1;
+            }
+            if m.medium_positions.len() < m.osm_node_refs.len() {
+                stats.incomplete += 1;
+            }
+            for p in m.medium_positions.iter() {
+                stats.bbox = Some(match stats.bbox {
+                    None => BoundingBox::new(p.longitude, p.latitude, p.longitude, p.latitude),
+                    Some(b) => BoundingBox::new(
+                        b.min_lon.min(p.longitude),
+                        b.min_lat.min(p.latitude),
+                        b.max_lon.max(p.longitude),
+                        b.max_lat.max(p.latitude),
+                    ),
+                });
+            }
+        }
+        stats
+    }
+}
+
+impl fmt::Display for DatasetStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<18}{:>12}{:>14}{:>14}",
+            "type", "mediums", "positions", "km"
+        )?;
+        for (name, kind) in self.by_type.iter() {
+            writeln!(
+                f,
+                "{:<18}{:>12}{:>14}{:>14.1}",
+                name,
+                kind.mediums,
+                kind.positions,
+                kind.length / 1000.0
+            )?;
+        }
+        if !self.street_categories.is_empty() {
+            writeln!(f, "\nhighway categories")?;
+            for (category, count) in self.street_categories.iter() {
+                writeln!(f, "  {:<16}{:>12}", category, count)?;
+            }
+        }
+        writeln!(f, "\nmediums missing positions: {}", self.incomplete)?;
+        match self.bbox {
+            Some(b) => write!(
+                f,
+                "bounding box: {:.7},{:.7},{:.7},{:.7}",
+                b.min_lon, b.min_lat, b.max_lon, b.max_lat
+            ),
+            None => write!(f, "bounding box: empty"),
+        }
+    }
+}
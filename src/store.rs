@@ -0,0 +1,84 @@
+pub mod dense;
+pub mod sorted;
+pub mod sparse;
+
+use std::path::{Path, PathBuf};
+
+use crate::{error::Result, input::InputFormat, types::medium::Position};
+
+/// A node location as written by the first pass: (id, lon, lat) in decimicrodegrees (10⁻⁷).
+pub type NodeLocation = (i64, i32, i32);
+
+/// Where the Medium pipeline keeps node locations between the pass that reads
+/// them from the file and the pass that turns node refs into positions.
+pub trait NodeLocationStore: Send + Sync {
+    /// Adds a batch of locations. Batches may arrive in any order.
+    fn insert_batch(&mut self, batch: &[NodeLocation]) -> Result<()>;
+
+    /// Called once every node has been written; the store is read-only afterwards.
+    fn finish(&mut self) -> Result<()>;
+
+    fn get(&self, id: i64) -> Option<Position>;
+
+    /// Number of locations written so far.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreKind {
+    /// Sorted id/location vectors held in memory. Fine for country extracts.
+    Sparse,
+    /// Sorted runs spilled to disk and merged into one file, read through mmap.
+    SortedFile,
+    /// A memory-mapped array indexed by node id, 8 bytes per id up to the largest id.
+    Dense,
+}
+
+/// Inputs up to this size keep their node locations in memory.
+const SPARSE_MAX_INPUT: u64 = 2 << 30;
+/// Inputs up to this size (a continent) use the sorted file; anything larger uses the dense array.
+const SORTED_MAX_INPUT: u64 = 16 << 30;
+/// An OSM XML file is roughly this many times larger than the same data as PBF.
+const XML_SIZE_RATIO: u64 = 10;
+
+impl StoreKind {
+    /// Picks a store from the size of the *.osm.pbf file.
+    pub fn for_input_size(bytes: u64) -> StoreKind {
+        if bytes <= SPARSE_MAX_INPUT {
+            StoreKind::Sparse
+        } else if bytes <= SORTED_MAX_INPUT {
+            StoreKind::SortedFile
+        } else {
+            StoreKind::Dense
+        }
+    }
+
+    /// Picks a store from the size of an input file, counting an uncompressed XML
+    /// file as the PBF it would make.
+    pub fn for_input(path: &Path, format: InputFormat) -> Result<StoreKind> {
+        let mut size = std::fs::metadata(path)?.len();
+        if format == InputFormat::Xml {
+            size /= XML_SIZE_RATIO;
+        }
+        Ok(StoreKind::for_input_size(size))
+    }
+}
+
+/// Creates an empty store. Disk-backed stores put their files in `work_dir`
+/// and remove them when dropped.
+pub fn create_store(kind: StoreKind, work_dir: &Path) -> Result<Box<dyn NodeLocationStore>> {
+    Ok(match kind {
+        StoreKind::Sparse => Box::new(sparse::SparseStore::new()),
+        StoreKind::SortedFile => Box::new(sorted::SortedFileStore::create(work_dir)?),
+        StoreKind::Dense => Box::new(dense::DenseStore::create(work_dir)?),
+    })
+}
+
+/// A file name in `work_dir` that does not clash with other runs.
+fn work_file(work_dir: &Path, name: &str) -> PathBuf {
+    work_dir.join(format!("osm-kovachs-{}-{}", std::process::id(), name))
+}
@@ -0,0 +1,146 @@
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::PathBuf,
+};
+
+use memmap2::MmapMut;
+
+use super::{work_file, NodeLocation, NodeLocationStore};
+use crate::{error::Result, types::medium::Position};
+
+/// Bytes per slot: lon and lat as biased u32.
+const SLOT: usize = 8;
+/// The backing file grows in steps of this many ids.
+const GROW_IDS: u64 = 64 << 20;
+
+/// Memory-mapped array with one slot per node id.
+///
+/// The file is sparse, so only pages holding real nodes take up disk space, and
+/// lookups are a single read. This is what planet-sized inputs need: node ids
+/// there are dense enough that sorting them would cost more than the holes.
+pub struct DenseStore {
+    path: PathBuf,
+    file: File,
+    map: MmapMut,
+    capacity: u64,
+    count: usize,
+}
+
+impl DenseStore {
+    pub fn create(work_dir: &std::path::Path) -> Result<DenseStore> {
+        let path = work_file(work_dir, "dense.bin");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        file.set_len(GROW_IDS * SLOT as u64)?;
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(DenseStore {
+            path,
+            file,
+            map,
+            capacity: GROW_IDS,
+            count: 0,
+        })
+    }
+
+    fn grow_to(&mut self, id: u64) -> io::Result<()> {
+        let capacity = (id / GROW_IDS + 1) * GROW_IDS;
+        self.map.flush_async()?;
+        self.file.set_len(capacity * SLOT as u64)?;
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+        self.capacity = capacity;
+        Ok(())
+    }
+}
+
+/// Coordinates are stored shifted by 2^31 so that an all-zero slot, which is
+/// what the holes of the sparse file read as, can never be a real location.
+fn encode(v: i32) -> u32 {
+    (v as i64 - i32::MIN as i64) as u32
+}
+
+fn decode(v: u32) -> i32 {
+    (v as i64 + i32::MIN as i64) as i32
+}
+
+impl NodeLocationStore for DenseStore {
+    fn insert_batch(&mut self, batch: &[NodeLocation]) -> Result<()> {
+        for &(id, lon, lat) in batch {
+            if id < 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("dense node store cannot hold negative node id {id}"),
+                )
+                .into());
+            }
+            if id as u64 >= self.capacity {
+                self.grow_to(id as u64)?;
+            }
+            let offset = id as usize * SLOT;
+            if self.map[offset..offset + SLOT].iter().all(|&b| b == 0) {
+                self.count += 1;
+            }
+            self.map[offset..offset + 4].copy_from_slice(&encode(lon).to_le_bytes());
+            self.map[offset + 4..offset + 8].copy_from_slice(&encode(lat).to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.map.flush()?;
+        Ok(())
+    }
+
+    fn get(&self, id: i64) -> Option<Position> {
+        if id < 0 || id as u64 >= self.capacity {
+            return None;
+        }
+        let offset = id as usize * SLOT;
+        let slot = &self.map[offset..offset + SLOT];
+        let lon = u32::from_le_bytes(slot[0..4].try_into().unwrap());
+        let lat = u32::from_le_bytes(slot[4..8].try_into().unwrap());
+        if lon == 0 && lat == 0 {
+            return None;
+        }
+        Some(Position::from_decimicro(decode(lon), decode(lat)))
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+}
+
+impl Drop for DenseStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_each_node_once() {
+        let dir = std::env::temp_dir().join(format!("kovachs-dense-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut store = DenseStore::create(&dir).unwrap();
+        store
+            .insert_batch(&[(3, 10, 20), (5, -10, -20), (3, 11, 21)])
+            .unwrap();
+        store.insert_batch(&[(5, 12, 22)]).unwrap();
+        assert_eq!(store.len(), 2);
+        let position = store.get(3).unwrap();
+        let expected = Position::from_decimicro(11, 21);
+        assert_eq!(
+            (position.longitude, position.latitude),
+            (expected.longitude, expected.latitude)
+        );
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+use rayon::slice::ParallelSliceMut;
+
+use super::{work_file, NodeLocation, NodeLocationStore};
+use crate::{error::Result, types::medium::Position};
+
+/// Bytes per record: id as i64, lon and lat as i32, little endian.
+const RECORD: usize = 16;
+/// Locations buffered in memory before they are sorted and spilled as a run (256 MiB).
+const RUN_LEN: usize = 16 << 20;
+
+/// Node locations sorted by id in one flat file.
+///
+/// Batches are buffered and spilled as sorted runs, `finish` merges the runs,
+/// and lookups binary search the memory-mapped result. Memory use is bounded by
+/// one run no matter how many nodes the input has.
+pub struct SortedFileStore {
+    work_dir: PathBuf,
+    buffer: Vec<NodeLocation>,
+    runs: Vec<PathBuf>,
+    path: PathBuf,
+    map: Option<Mmap>,
+    count: usize,
+}
+
+impl SortedFileStore {
+    pub fn create(work_dir: &Path) -> Result<SortedFileStore> {
+        std::fs::create_dir_all(work_dir)?;
+        Ok(SortedFileStore {
+            work_dir: work_dir.to_path_buf(),
+            buffer: Vec::new(),
+            runs: Vec::new(),
+            path: work_file(work_dir, "sorted.bin"),
+            map: None,
+            count: 0,
+        })
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.buffer.par_sort_unstable_by_key(|(id, _, _)| *id);
+        let path = work_file(&self.work_dir, &format!("run-{}.bin", self.runs.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for location in self.buffer.drain(..) {
+            write_record(&mut writer, location)?;
+        }
+        writer.flush()?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// K-way merge of the sorted runs into the final file, dropping duplicate ids.
+    fn merge_runs(&mut self) -> io::Result<()> {
+        let mut readers = self
+            .runs
+            .iter()
+            .map(|p| File::open(p).map(BufReader::new))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut heap = BinaryHeap::new();
+        for (i, reader) in readers.iter_mut().enumerate() {
+            if let Some(location) = read_record(reader)? {
+                heap.push(Reverse((location, i)));
+            }
+        }
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        let mut last_id = None;
+        self.count = 0;
+        while let Some(Reverse((location, i))) = heap.pop() {
+            if last_id != Some(location.0) {
+                write_record(&mut writer, location)?;
+                last_id = Some(location.0);
+                self.count += 1;
+            }
+            if let Some(next) = read_record(&mut readers[i])? {
+                heap.push(Reverse((next, i)));
+            }
+        }
+        writer.flush()?;
+        for run in self.runs.drain(..) {
+            std::fs::remove_file(run)?;
+        }
+        Ok(())
+    }
+
+    fn record(map: &Mmap, i: usize) -> NodeLocation {
+        let r = &map[i * RECORD..(i + 1) * RECORD];
+        (
+            i64::from_le_bytes(r[0..8].try_into().unwrap()),
+            i32::from_le_bytes(r[8..12].try_into().unwrap()),
+            i32::from_le_bytes(r[12..16].try_into().unwrap()),
+        )
+    }
+}
+
+fn write_record(writer: &mut impl Write, (id, lon, lat): NodeLocation) -> io::Result<()> {
+    writer.write_all(&id.to_le_bytes())?;
+    writer.write_all(&lon.to_le_bytes())?;
+    writer.write_all(&lat.to_le_bytes())
+}
+
+fn read_record(reader: &mut impl Read) -> io::Result<Option<NodeLocation>> {
+    let mut r = [0u8; RECORD];
+    match reader.read_exact(&mut r) {
+        Ok(()) => Ok(Some((
+            i64::from_le_bytes(r[0..8].try_into().unwrap()),
+            i32::from_le_bytes(r[8..12].try_into().unwrap()),
+            i32::from_le_bytes(r[12..16].try_into().unwrap()),
+        ))),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl NodeLocationStore for SortedFileStore {
+    fn insert_batch(&mut self, batch: &[NodeLocation]) -> Result<()> {
+        self.buffer.extend_from_slice(batch);
+        self.count += batch.len();
+        if self.buffer.len() >= RUN_LEN {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.spill()?;
+        self.merge_runs()?;
+        let file = File::open(&self.path)?;
+        // An empty file cannot be mapped.
+        if file.metadata()?.len() > 0 {
+            self.map = Some(unsafe { Mmap::map(&file)? });
+        }
+        Ok(())
+    }
+
+    fn get(&self, id: i64) -> Option<Position> {
+        let map = self.map.as_ref()?;
+        let (mut lo, mut hi) = (0, map.len() / RECORD);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let (mid_id, lon, lat) = SortedFileStore::record(map, mid);
+            match mid_id.cmp(&id) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(Position::from_decimicro(lon, lat)),
+            }
+        }
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+}
+
+impl Drop for SortedFileStore {
+    fn drop(&mut self) {
+        self.map = None;
+        for run in self.runs.iter() {
+            let _ = std::fs::remove_file(run);
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,72 @@
+use rayon::slice::ParallelSliceMut;
+
+use super::{NodeLocation, NodeLocationStore};
+use crate::{error::Result, types::medium::Position};
+
+/// Compact id -> (lon, lat) map held in memory.
+///
+/// Ids are kept sorted next to their coordinates, 16 bytes per node, and looked
+/// up with a binary search.
+#[derive(Debug, Default)]
+pub struct SparseStore {
+    pending: Vec<NodeLocation>,
+    ids: Vec<i64>,
+    coords: Vec<(i32, i32)>,
+}
+
+impl SparseStore {
+    pub fn new() -> SparseStore {
+        SparseStore::default()
+    }
+
+    pub fn from_unsorted(locations: Vec<NodeLocation>) -> SparseStore {
+        let mut store = SparseStore {
+            pending: locations,
+            ..SparseStore::default()
+        };
+        store.sort_pending();
+        store
+    }
+
+    fn sort_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut locations = std::mem::take(&mut self.pending);
+        locations.extend(
+            self.ids
+                .drain(..)
+                .zip(self.coords.drain(..))
+                .map(|(id, (lon, lat))| (id, lon, lat)),
+        );
+        locations.par_sort_unstable_by_key(|(id, _, _)| *id);
+        locations.dedup_by_key(|(id, _, _)| *id);
+        (self.ids, self.coords) = locations
+            .into_iter()
+            .map(|(id, lon, lat)| (id, (lon, lat)))
+            .unzip();
+    }
+}
+
+impl NodeLocationStore for SparseStore {
+    fn insert_batch(&mut self, batch: &[NodeLocation]) -> Result<()> {
+        self.pending.extend_from_slice(batch);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.sort_pending();
+        Ok(())
+    }
+
+    fn get(&self, id: i64) -> Option<Position> {
+        self.ids
+            .binary_search(&id)
+            .ok()
+            .map(|i| Position::from_decimicro(self.coords[i].0, self.coords[i].1))
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len() + self.pending.len()
+    }
+}
@@ -0,0 +1,379 @@
+use std::{fmt, str::FromStr};
+
+/// A test on the tags of a node, way or relation, parsed from an expression such
+/// as `highway in (primary, secondary) and not access=private`.
+///
+/// The grammar, loosest binding first:
+///
+/// ```text
+/// expr  := and ("or" and)*
+/// and   := unary ("and" unary)*
+/// unary := "not" unary | "(" expr ")" | test
+/// test  := key | key "=" value | key "!=" value | key "in" "(" value ("," value)* ")"
+/// ```
+///
+/// A bare key matches when the tag is present. `key!=value` also matches when the
+/// tag is missing. Keys and values are words of letters, digits and `_:.-`, or
+/// quoted with `"` or `'`.
+///
+/// ```
+/// use osm_kovachs::tagfilter::TagFilter;
+///
+/// let filter: TagFilter = "highway in (primary, secondary) and not access=private".parse()?;
+/// assert!(filter.matches([("highway", "primary")].into_iter()));
+/// assert!(!filter.matches([("highway", "primary"), ("access", "private")].into_iter()));
+/// # Ok::<(), osm_kovachs::tagfilter::FilterError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagFilter {
+    Has(String),
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    Not(Box<TagFilter>),
+    And(Vec<TagFilter>),
+    Or(Vec<TagFilter>),
+}
+
+impl TagFilter {
+    /// Evaluates the expression against an element's tags, walking a fresh copy
+    /// of the iterator for every key looked up.
+    pub fn matches<'a, I>(&self, tags: I) -> bool
+    where
+        I: Iterator<Item = (&'a str, &'a str)> + Clone,
+    {
+        let value = |key: &str| tags.clone().find(|(k, _)| *k == key).map(|(_, v)| v);
+        match self {
+            TagFilter::Has(key) => value(key).is_some(),
+            TagFilter::Equals(key, v) => value(key) == Some(v.as_str()),
+            TagFilter::NotEquals(key, v) => value(key) != Some(v.as_str()),
+            TagFilter::In(key, values) => value(key).is_some_and(|v| values.iter().any(|x| x == v)),
+            TagFilter::Not(filter) => !filter.matches(tags.clone()),
+            TagFilter::And(filters) => filters.iter().all(|f| f.matches(tags.clone())),
+            TagFilter::Or(filters) => filters.iter().any(|f| f.matches(tags.clone())),
+        }
+    }
+}
+
+/// Why an expression could not be parsed, and the byte offset where it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+impl FromStr for TagFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<TagFilter, FilterError> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            next: 0,
+            end: s.len(),
+        };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some((offset, token)) => Err(FilterError {
+                offset,
+                message: format!("unexpected {token}"),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A bare word, which may also be a keyword.
+    Word(String),
+    Quoted(String),
+    Equals,
+    NotEquals,
+    Open,
+    Close,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "{w:?}"),
+            Token::Quoted(q) => write!(f, "{q:?}"),
+            Token::Equals => f.write_str("'='"),
+            Token::NotEquals => f.write_str("'!='"),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+            Token::Comma => f.write_str("','"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '=' => Token::Equals,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '!' if chars.next_if(|(_, c)| *c == '=').is_some() => Token::NotEquals,
+            '"' | '\'' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, q)) if q == c => break,
+                        Some((_, q)) => quoted.push(q),
+                        None => {
+                            return Err(FilterError {
+                                offset,
+                                message: String::from("unterminated quote"),
+                            })
+                        }
+                    }
+                }
+                Token::Quoted(quoted)
+            }
+            c if is_word(c) => {
+                let mut word = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| is_word(*c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+            c => {
+                return Err(FilterError {
+                    offset,
+                    message: format!("unexpected {c:?}"),
+                })
+            }
+        };
+        tokens.push((offset, token));
+    }
+    Ok(tokens)
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | ':' | '.' | '-')
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Offset reported for errors at the end of the input.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens.get(self.next).map(|(o, t)| (*o, t))
+    }
+
+    fn advance(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    /// Consumes the next token if it is the keyword `keyword`.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some((_, Token::Word(w))) if w == keyword);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), FilterError> {
+        match self.advance() {
+            Some((_, token)) if token == expected => Ok(()),
+            other => Err(self.unexpected(other, &expected.to_string())),
+        }
+    }
+
+    fn unexpected(&self, found: Option<(usize, Token)>, expected: &str) -> FilterError {
+        match found {
+            Some((offset, token)) => FilterError {
+                offset,
+                message: format!("expected {expected}, found {token}"),
+            },
+            None => FilterError {
+                offset: self.end,
+                message: format!("expected {expected}, found the end"),
+            },
+        }
+    }
+
+    fn or(&mut self) -> Result<TagFilter, FilterError> {
+        let mut filters = vec![self.and()?];
+        while self.keyword("or") {
+            filters.push(self.and()?);
+        }
+        Ok(if filters.len() == 1 {
+            filters.remove(0)
+        } else {
+            TagFilter::Or(filters)
+        })
+    }
+
+    fn and(&mut self) -> Result<TagFilter, FilterError> {
+        let mut filters = vec![self.unary()?];
+        while self.keyword("and") {
+            filters.push(self.unary()?);
+        }
+        Ok(if filters.len() == 1 {
+            filters.remove(0)
+        } else {
+            TagFilter::And(filters)
+        })
+    }
+
+    fn unary(&mut self) -> Result<TagFilter, FilterError> {
+        if self.keyword("not") {
+            return Ok(TagFilter::Not(Box::new(self.unary()?)));
+        }
+        if matches!(self.peek(), Some((_, Token::Open))) {
+            self.next += 1;
+            let filter = self.or()?;
+            self.expect(Token::Close)?;
+            return Ok(filter);
+        }
+        let key = self.text("a key")?;
+        match self.peek() {
+            Some((_, Token::Equals)) => {
+                self.next += 1;
+                Ok(TagFilter::Equals(key, self.text("a value")?))
+            }
+            Some((_, Token::NotEquals)) => {
+                self.next += 1;
+                Ok(TagFilter::NotEquals(key, self.text("a value")?))
+            }
+            Some((_, Token::Word(w))) if w == "in" => {
+                self.next += 1;
+                self.expect(Token::Open)?;
+                let mut values = vec![self.text("a value")?];
+                while matches!(self.peek(), Some((_, Token::Comma))) {
+                    self.next += 1;
+                    values.push(self.text("a value")?);
+                }
+                self.expect(Token::Close)?;
+                Ok(TagFilter::In(key, values))
+            }
+            _ => Ok(TagFilter::Has(key)),
+        }
+    }
+
+    /// A key or value: a quoted string, or a word that is not a keyword.
+    fn text(&mut self, expected: &str) -> Result<String, FilterError> {
+        match self.advance() {
+            Some((_, Token::Quoted(q))) => Ok(q),
+            Some((_, Token::Word(w))) if !matches!(w.as_str(), "and" | "or" | "not" | "in") => {
+                Ok(w)
+            }
+            other => Err(self.unexpected(other, expected)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> TagFilter {
+        s.parse().unwrap()
+    }
+
+    fn has(key: &str) -> TagFilter {
+        TagFilter::Has(key.into())
+    }
+
+    fn error(s: &str) -> FilterError {
+        s.parse::<TagFilter>().unwrap_err()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("a or b and c"),
+            TagFilter::Or(vec![has("a"), TagFilter::And(vec![has("b"), has("c")])])
+        );
+        assert_eq!(
+            parse("a and b or c"),
+            TagFilter::Or(vec![TagFilter::And(vec![has("a"), has("b")]), has("c")])
+        );
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert_eq!(
+            parse("not a and b"),
+            TagFilter::And(vec![TagFilter::Not(Box::new(has("a"))), has("b")])
+        );
+        assert_eq!(
+            parse("not (a and b)"),
+            TagFilter::Not(Box::new(TagFilter::And(vec![has("a"), has("b")])))
+        );
+    }
+
+    #[test]
+    fn parses_tests() {
+        assert_eq!(
+            parse("highway in (primary, 'secondary link')"),
+            TagFilter::In(
+                "highway".into(),
+                vec!["primary".into(), "secondary link".into()]
+            )
+        );
+        assert_eq!(
+            parse(r#"access!="no""#),
+            TagFilter::NotEquals("access".into(), "no".into())
+        );
+        assert_eq!(
+            parse("addr:street=Main_St.-1"),
+            TagFilter::Equals("addr:street".into(), "Main_St.-1".into())
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_offsets() {
+        let cases = [
+            ("highway=", 8, "expected a value, found the end"),
+            ("(highway", 8, "expected ')', found the end"),
+            ("highway primary", 8, r#"unexpected "primary""#),
+            ("name='x", 5, "unterminated quote"),
+            ("a & b", 2, "unexpected '&'"),
+            ("and", 0, r#"expected a key, found "and""#),
+            ("highway in primary", 11, r#"expected '(', found "primary""#),
+        ];
+        for (s, offset, message) in cases {
+            assert_eq!(
+                error(s),
+                FilterError {
+                    offset,
+                    message: message.into()
+                },
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn matches_tags() {
+        let tags = [("highway", "primary"), ("name", "Uhuru")];
+        let filter = parse("highway in (primary, trunk) and not access=private");
+        assert!(filter.matches(tags.into_iter()));
+        assert!(!filter.matches([("highway", "primary"), ("access", "private")].into_iter()));
+        assert!(parse("access!=private").matches(tags.into_iter()));
+        assert!(!parse("railway or name=Other").matches(tags.into_iter()));
+        assert!(!parse("highway in (trunk)").matches(std::iter::empty()));
+    }
+}
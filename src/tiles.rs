@@ -0,0 +1,494 @@
+pub mod geometry;
+pub mod mvt;
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use rayon::prelude::*;
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+use crate::{
+    columns::MediumRow,
+    error::Result,
+    types::medium::{
+        AeroCategory, Medium, MediumType, RailCategory, StreetCategory, WaterCategory,
+    },
+};
+
+use self::{
+    geometry::{clip_line, project_line, simplify},
+    mvt::{encode_tile, Layer, TileGeometry, Value},
+};
+
+/// Layer names, one per Medium type.
+const LAYERS: [&str; 6] = [
+    "default",
+    "highway",
+    "railway",
+    "waterway",
+    "airway",
+    "space_trajectory",
+];
+
+/// The zoom level from which each kind of Medium is drawn.
+#[derive(Debug, Clone)]
+pub struct ZoomRules {
+    streets: HashMap<StreetCategory, u8>,
+}
+
+impl Default for ZoomRules {
+    fn default() -> Self {
+        use StreetCategory::*;
+        let streets = [
+            (Motorway, 5),
+            (Trunk, 6),
+            (Primary, 8),
+            (Secondary, 9),
+            (Tertiary, 10),
+            (MotorwayLink, 10),
+            (TrunkLink, 11),
+            (PrimaryLink, 12),
+            (SecondaryLink, 12),
+            (TertiaryLink, 12),
+            (Unclassified, 12),
+            (Residential, 13),
+            (LivingStreet, 13),
+            (Road, 13),
+            (Service, 14),
+            (Track, 14),
+            (Cycleway, 14),
+            (Pedestrian, 14),
+            (Path, 14),
+            (Footway, 14),
+            (Crossing, 15),
+            (Default, 14),
+        ];
+        ZoomRules {
+            streets: streets.into_iter().collect(),
+        }
+    }
+}
+
+impl ZoomRules {
+    /// Draws streets of `category` from `min_zoom` on.
+    pub fn set_street(&mut self, category: StreetCategory, min_zoom: u8) {
+        self.streets.insert(category, min_zoom);
+    }
+
+    pub fn min_zoom(&self, medium_type: &MediumType) -> u8 {
+        match medium_type {
+            MediumType::Default => 14,
+            MediumType::Highway(categories) => {
+                let category = categories
+                    .first()
+                    .copied()
+                    .unwrap_or(StreetCategory::Default);
+                self.streets.get(&category).copied().unwrap_or(14)
+            }
+            MediumType::Railway(a) => match a.category {
+                RailCategory::Rail => 8,
+                RailCategory::LightRail | RailCategory::Subway => 11,
+                RailCategory::Tram | RailCategory::NarrowGauge => 12,
+                RailCategory::Abandoned | RailCategory::Disused => 14,
+            },
+            MediumType::Waterway(a) => match a.category {
+                WaterCategory::River | WaterCategory::Ferry => 8,
+                WaterCategory::Canal => 10,
+                WaterCategory::Stream => 13,
+                WaterCategory::Ditch => 14,
+            },
+            MediumType::Airway(a) => match a.category {
+                AeroCategory::Aerodrome => 9,
+                AeroCategory::Runway => 10,
+                AeroCategory::Taxiway | AeroCategory::Apron => 13,
+                AeroCategory::Taxilane | AeroCategory::ParkingPosition => 14,
+            },
+            MediumType::SpaceTrajectory(_) => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TileOptions {
+    pub min_zoom: u8,
+    /// Mediums whose rules start beyond this zoom are all drawn at it.
+    pub max_zoom: u8,
+    /// Tile coordinates run from 0 to `extent` along each side.
+    pub extent: u32,
+    /// How far past its edges, in tile coordinates, a tile's geometry reaches.
+    pub buffer: u32,
+    /// Douglas-Peucker tolerance in tile coordinates.
+    pub tolerance: f64,
+    pub rules: ZoomRules,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        TileOptions {
+            min_zoom: 0,
+            max_zoom: 14,
+            extent: 4096,
+            buffer: 64,
+            tolerance: 8.0,
+            rules: ZoomRules::default(),
+        }
+    }
+}
+
+/// Where tiles go: a `z/x/y.mvt` directory tree, or an MBTiles file when the
+/// path ends in `.mbtiles`.
+pub enum TileWriter {
+    Directory(PathBuf),
+    MbTiles(Connection),
+}
+
+impl TileWriter {
+    pub fn create(path: &Path) -> Result<TileWriter> {
+        if path.extension().and_then(|e| e.to_str()) != Some("mbtiles") {
+            fs::create_dir_all(path)?;
+            return Ok(TileWriter::Directory(path.to_path_buf()));
+        }
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+             CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+             CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+             BEGIN;",
+        )?;
+        Ok(TileWriter::MbTiles(conn))
+    }
+
+    pub fn write(&mut self, z: u8, x: u32, y: u32, tile: &[u8]) -> Result<()> {
+        match self {
+            TileWriter::Directory(root) => {
+                let dir = root.join(z.to_string()).join(x.to_string());
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join(format!("{y}.mvt")), tile)?;
+            }
+            TileWriter::MbTiles(conn) => {
+                // MBTiles holds gzipped tiles, with rows counted from the south.
+                let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+                gzip.write_all(tile)?;
+                let row = (1u32 << z) - 1 - y;
+                conn.execute(
+                    "INSERT INTO tiles VALUES (?1, ?2, ?3, ?4)",
+                    params![z, x, row, gzip.finish()?],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the MBTiles metadata: into its table, or as `metadata.json` at the
+    /// root of a directory.
+    pub fn finish(self, metadata: &[(&str, String)]) -> Result<()> {
+        match self {
+            TileWriter::Directory(root) => {
+                let object: serde_json::Map<_, _> = metadata
+                    .iter()
+                    .map(|(k, v)| {
+                        let value = serde_json::from_str(v).unwrap_or_else(|_| json!(v));
+                        (k.to_string(), value)
+                    })
+                    .collect();
+                fs::write(root.join("metadata.json"), json!(object).to_string())?;
+            }
+            TileWriter::MbTiles(conn) => {
+                for (name, value) in metadata {
+                    conn.execute("INSERT INTO metadata VALUES (?1, ?2)", params![name, value])?;
+                }
+                conn.execute_batch("COMMIT;")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tiles written per zoom level, from the lowest.
+#[derive(Debug, Clone, Default)]
+pub struct TileSummary {
+    pub tiles_per_zoom: Vec<(u8, usize)>,
+}
+
+/// A Medium ready for tiling: projected once, cut and simplified per zoom.
+struct Source {
+    layer: &'static str,
+    id: Option<u64>,
+    properties: Vec<(&'static str, Value)>,
+    min_zoom: u8,
+    /// Web Mercator in the unit square, split at the antimeridian.
+    parts: Vec<Vec<[f64; 2]>>,
+}
+
+/// The geometry of one source in one tile: the tile's x and y, and the source index.
+type Piece = ((u32, u32), usize, TileGeometry);
+
+/// Cuts `mediums` into vector tiles for every zoom level in the options, with a
+/// layer per Medium type.
+pub fn write_tiles(
+    mediums: &[Medium],
+    options: &TileOptions,
+    mut writer: TileWriter,
+) -> Result<TileSummary> {
+    let sources = mediums
+        .par_iter()
+        .filter(|m| !m.medium_positions.is_empty())
+        .map(|m| source(m, options))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut summary = TileSummary::default();
+    for z in options.min_zoom..=options.max_zoom {
+        let mut pieces: Vec<Piece> = sources
+            .par_iter()
+            .enumerate()
+            .filter(|(_, s)| s.min_zoom.min(options.max_zoom) <= z)
+            .flat_map_iter(|(i, s)| {
+                cut(s, z, options)
+                    .into_iter()
+                    .map(move |(tile, geometry)| (tile, i, geometry))
+            })
+            .collect();
+        pieces.par_sort_by_key(|(tile, i, _)| (*tile, *i));
+
+        let mut by_tile: Vec<((u32, u32), &[Piece])> = Vec::new();
+        let mut rest = &pieces[..];
+        while let Some((tile, _, _)) = rest.first() {
+            let n = rest.iter().take_while(|(t, _, _)| t == tile).count();
+            by_tile.push((*tile, &rest[..n]));
+            rest = &rest[n..];
+        }
+        let tiles: Vec<((u32, u32), Vec<u8>)> = by_tile
+            .par_iter()
+            .map(|(tile, pieces)| {
+                let mut layers: Vec<Layer> = LAYERS
+                    .iter()
+                    .map(|name| Layer::new(*name, options.extent))
+                    .collect();
+                for (_, i, geometry) in pieces.iter() {
+                    let s = &sources[*i];
+                    let layer = LAYERS.iter().position(|l| *l == s.layer).unwrap_or(0);
+                    layers[layer].add_feature(s.id, geometry, &s.properties);
+                }
+                (*tile, encode_tile(layers.iter()))
+            })
+            .filter(|(_, data)| !data.is_empty())
+            .collect();
+        for ((x, y), data) in tiles.iter() {
+            writer.write(z, *x, *y, data)?;
+        }
+        summary.tiles_per_zoom.push((z, tiles.len()));
+    }
+
+    writer.finish(&metadata(mediums, options))?;
+    Ok(summary)
+}
+
+fn source(medium: &Medium, options: &TileOptions) -> Result<Source> {
+    let row = MediumRow::from_medium(medium)?;
+    let mut properties = Vec::new();
+    if let Some(name) = row.name {
+        properties.push(("name", Value::String(name)));
+    }
+    if let Some(category) = row.category {
+        properties.push(("category", Value::String(category)));
+    }
+    properties.push(("oneway", Value::Bool(row.is_one_way)));
+    // Feature ids are OSM ids, which ways and relations share.
+    if let Some(osm_type) = row.osm_type {
+        properties.push(("osm_type", Value::String(osm_type.name().to_string())));
+    }
+    Ok(Source {
+        layer: row.medium_type,
+        id: row.osm_id.and_then(|id| u64::try_from(id).ok()),
+        properties,
+        min_zoom: options.rules.min_zoom(&medium.medium_type),
+        parts: project_line(&medium.medium_positions),
+    })
+}
+
+/// The geometry of `source` in each tile of zoom `z` it reaches.
+fn cut(source: &Source, z: u8, options: &TileOptions) -> Vec<((u32, u32), TileGeometry)> {
+    let extent = options.extent as f64;
+    let buffer = options.buffer as f64;
+    let tiles = 1i64 << z;
+    let scale = extent * tiles as f64;
+    let in_world = |x: i64, y: i64| (0..tiles).contains(&x) && (0..tiles).contains(&y);
+    let local = |p: [f64; 2], x: i64, y: i64| {
+        [
+            (p[0] - x as f64 * extent).round() as i32,
+            (p[1] - y as f64 * extent).round() as i32,
+        ]
+    };
+
+    let single_point = source.parts.len() == 1 && source.parts[0].len() == 1;
+    if single_point {
+        let p = source.parts[0][0].map(|c| c * scale);
+        let x = ((p[0] / extent) as i64).min(tiles - 1);
+        let y = ((p[1] / extent) as i64).min(tiles - 1);
+        return vec![(
+            (x as u32, y as u32),
+            TileGeometry::Points(vec![local(p, x, y)]),
+        )];
+    }
+
+    let mut lines: HashMap<(i64, i64), Vec<Vec<[i32; 2]>>> = HashMap::new();
+    for part in source.parts.iter() {
+        let scaled: Vec<[f64; 2]> = part.iter().map(|p| p.map(|c| c * scale)).collect();
+        let points = simplify(&scaled, options.tolerance);
+        if points.len() < 2 {
+            continue;
+        }
+        // Segments by the tiles they pass through or come within a buffer of.
+        let mut segments: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, w) in points.windows(2).enumerate() {
+            for (x, y) in traverse(w[0], w[1], extent) {
+                for (dx, dy) in NEIGHBOURHOOD {
+                    let (x, y) = (x + dx, y + dy);
+                    if in_world(x, y) {
+                        let list = segments.entry((x, y)).or_default();
+                        if list.last() != Some(&i) {
+                            list.push(i);
+                        }
+                    }
+                }
+            }
+        }
+        for ((x, y), mut list) in segments {
+            list.sort_unstable();
+            list.dedup();
+            let min = [x as f64 * extent - buffer, y as f64 * extent - buffer];
+            let max = [
+                min[0] + extent + 2.0 * buffer,
+                min[1] + extent + 2.0 * buffer,
+            ];
+            let mut start = 0;
+            while start < list.len() {
+                let mut end = start;
+                while end + 1 < list.len() && list[end + 1] == list[end] + 1 {
+                    end += 1;
+                }
+                let run = &points[list[start]..=list[end] + 1];
+                for piece in clip_line(run, min, max) {
+                    lines
+                        .entry((x, y))
+                        .or_default()
+                        .push(piece.into_iter().map(|p| local(p, x, y)).collect());
+                }
+                start = end + 1;
+            }
+        }
+    }
+    lines
+        .into_iter()
+        .map(|((x, y), lines)| ((x as u32, y as u32), TileGeometry::Lines(lines)))
+        .collect()
+}
+
+const NEIGHBOURHOOD: [(i64, i64); 9] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (0, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// The grid cells of size `cell` the segment from `a` to `b` passes through.
+fn traverse(a: [f64; 2], b: [f64; 2], cell: f64) -> Vec<(i64, i64)> {
+    let (x0, y0) = (a[0] / cell, a[1] / cell);
+    let (x1, y1) = (b[0] / cell, b[1] / cell);
+    let (mut x, mut y) = (x0.floor() as i64, y0.floor() as i64);
+    let (end_x, end_y) = (x1.floor() as i64, y1.floor() as i64);
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let step_x = if dx > 0.0 { 1 } else { -1 };
+    let step_y = if dy > 0.0 { 1 } else { -1 };
+    let boundary = |v: f64, d: f64| {
+        if d > 0.0 {
+            v.floor() + 1.0 - v
+        } else {
+            v - v.floor()
+        }
+    };
+    let mut t_x = if dx == 0.0 {
+        f64::INFINITY
+    } else {
+        boundary(x0, dx) / dx.abs()
+    };
+    let mut t_y = if dy == 0.0 {
+        f64::INFINITY
+    } else {
+        boundary(y0, dy) / dy.abs()
+    };
+    let (delta_x, delta_y) = (1.0 / dx.abs(), 1.0 / dy.abs());
+
+    let steps = (end_x - x).abs() + (end_y - y).abs();
+    let mut cells = Vec::with_capacity(steps as usize + 1);
+    cells.push((x, y));
+    for _ in 0..steps {
+        if t_x < t_y {
+            x += step_x;
+            t_x += delta_x;
+        } else {
+            y += step_y;
+            t_y += delta_y;
+        }
+        cells.push((x, y));
+    }
+    cells
+}
+
+fn metadata(mediums: &[Medium], options: &TileOptions) -> Vec<(&'static str, String)> {
+    let (mut west, mut south, mut east, mut north) = (180.0, 85.0511, -180.0, -85.0511);
+    for p in mediums.iter().flat_map(|m| m.medium_positions.iter()) {
+        west = p.longitude.min(west);
+        east = p.longitude.max(east);
+        south = p.latitude.max(-85.0511).min(south);
+        north = p.latitude.min(85.0511).max(north);
+    }
+    if west > east {
+        (west, south, east, north) = (-180.0, -85.0511, 180.0, 85.0511);
+    }
+    let fields = json!({"name": "String", "category": "String", "oneway": "Boolean"});
+    let vector_layers: Vec<_> = LAYERS
+        .iter()
+        .map(|id| {
+            json!({
+                "id": id,
+                "fields": fields,
+                "minzoom": options.min_zoom,
+                "maxzoom": options.max_zoom,
+            })
+        })
+        .collect();
+    vec![
+        ("name", "mediums".to_string()),
+        ("format", "pbf".to_string()),
+        ("type", "baselayer".to_string()),
+        ("minzoom", options.min_zoom.to_string()),
+        ("maxzoom", options.max_zoom.to_string()),
+        ("bounds", format!("{west},{south},{east},{north}")),
+        (
+            "center",
+            format!(
+                "{},{},{}",
+                (west + east) / 2.0,
+                (south + north) / 2.0,
+                options.min_zoom
+            ),
+        ),
+        ("json", json!({"vector_layers": vector_layers}).to_string()),
+    ]
+}
@@ -0,0 +1,154 @@
+use std::f64::consts::PI;
+
+use crate::types::medium::Position;
+
+/// Latitude where Web Mercator's square world ends.
+const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Web Mercator position scaled to the unit square, x eastwards and y
+/// southwards from the north-west corner.
+pub fn project(p: &Position) -> [f64; 2] {
+    let latitude = p.latitude.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (p.longitude + 180.0) / 360.0;
+    let y = (1.0 - (latitude.tan() + 1.0 / latitude.cos()).ln() / PI) / 2.0;
+    [x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)]
+}
+
+/// Projects a line, splitting it where it jumps across the antimeridian.
+pub fn project_line(positions: &[Position]) -> Vec<Vec<[f64; 2]>> {
+    let mut parts = vec![Vec::with_capacity(positions.len())];
+    for (i, p) in positions.iter().enumerate() {
+        if i > 0 && (p.longitude - positions[i - 1].longitude).abs() > 180.0 {
+            parts.push(Vec::new());
+        }
+        if let Some(part) = parts.last_mut() {
+            part.push(project(p));
+        }
+    }
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// Douglas-Peucker simplification, keeping every point further than
+/// `tolerance` from the simplified line.
+pub fn simplify(points: &[[f64; 2]], tolerance: f64) -> Vec<[f64; 2]> {
+    if points.len() < 3 || tolerance <= 0.0 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+    let tolerance_sq = tolerance * tolerance;
+    while let Some((first, last)) = stack.pop() {
+        let mut farthest = None;
+        let mut max_sq = tolerance_sq;
+        for i in first + 1..last {
+            let d = segment_distance_sq(points[i], points[first], points[last]);
+            if d > max_sq {
+                max_sq = d;
+                farthest = Some(i);
+            }
+        }
+        if let Some(i) = farthest {
+            keep[i] = true;
+            stack.push((first, i));
+            stack.push((i, last));
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, k)| *k)
+        .map(|(p, _)| *p)
+        .collect()
+}
+
+fn segment_distance_sq(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq == 0.0 {
+        0.0
+    } else {
+        (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / length_sq).clamp(0.0, 1.0)
+    };
+    let (x, y) = (a[0] + t * dx - p[0], a[1] + t * dy - p[1]);
+    x * x + y * y
+}
+
+/// The pieces of a line inside the box from `min` to `max`.
+pub fn clip_line(points: &[[f64; 2]], min: [f64; 2], max: [f64; 2]) -> Vec<Vec<[f64; 2]>> {
+    let mut parts = Vec::new();
+    let mut current: Vec<[f64; 2]> = Vec::new();
+    for w in points.windows(2) {
+        match clip_segment(w[0], w[1], min, max) {
+            Some((a, b)) => {
+                if current.last() != Some(&a) {
+                    if current.len() > 1 {
+                        parts.push(std::mem::take(&mut current));
+                    }
+                    current.clear();
+                    current.push(a);
+                }
+                current.push(b);
+                // The segment left the box, so the next one starts a new piece.
+                if b != w[1] {
+                    parts.push(std::mem::take(&mut current));
+                }
+            }
+            None => {
+                if current.len() > 1 {
+                    parts.push(std::mem::take(&mut current));
+                }
+                current.clear();
+            }
+        }
+    }
+    if current.len() > 1 {
+        parts.push(current);
+    }
+    parts
+}
+
+/// Liang-Barsky clipping of the segment from `a` to `b`.
+fn clip_segment(
+    a: [f64; 2],
+    b: [f64; 2],
+    min: [f64; 2],
+    max: [f64; 2],
+) -> Option<([f64; 2], [f64; 2])> {
+    let d = [b[0] - a[0], b[1] - a[1]];
+    let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+    for axis in 0..2 {
+        for (p, q) in [
+            (-d[axis], a[axis] - min[axis]),
+            (d[axis], max[axis] - a[axis]),
+        ] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else {
+                let r = q / p;
+                if p < 0.0 {
+                    t0 = t0.max(r);
+                } else {
+                    t1 = t1.min(r);
+                }
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    let at = |t: f64| {
+        if t == 0.0 {
+            a
+        } else if t == 1.0 {
+            b
+        } else {
+            [a[0] + t * d[0], a[1] + t * d[1]]
+        }
+    };
+    Some((at(t0), at(t1)))
+}
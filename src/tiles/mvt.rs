@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+
+/// Geometry in tile coordinates, already clipped to the tile and its buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TileGeometry {
+    Points(Vec<[i32; 2]>),
+    Lines(Vec<Vec<[i32; 2]>>),
+}
+
+/// A feature property value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Double(f64),
+    Int(i64),
+    Bool(bool),
+}
+
+const POINT: u32 = 1;
+const LINE_STRING: u32 = 2;
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+
+/// One named layer of a tile, with its shared key and value tables.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    name: String,
+    extent: u32,
+    keys: Vec<String>,
+    key_index: HashMap<String, u32>,
+    values: Vec<Vec<u8>>,
+    /// Keyed by the encoded value, so doubles need no Eq or Hash.
+    value_index: HashMap<Vec<u8>, u32>,
+    features: Vec<u8>,
+    feature_count: usize,
+}
+
+impl Layer {
+    pub fn new(name: impl Into<String>, extent: u32) -> Layer {
+        Layer {
+            name: name.into(),
+            extent,
+            keys: Vec::new(),
+            key_index: HashMap::new(),
+            values: Vec::new(),
+            value_index: HashMap::new(),
+            features: Vec::new(),
+            feature_count: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.feature_count == 0
+    }
+
+    /// Adds a feature. Does nothing when the geometry has no point, or no line
+    /// with two distinct points, left to draw.
+    pub fn add_feature(
+        &mut self,
+        id: Option<u64>,
+        geometry: &TileGeometry,
+        properties: &[(&str, Value)],
+    ) {
+        let Some((kind, commands)) = encode_geometry(geometry) else {
+            return;
+        };
+        let mut tags = Vec::with_capacity(2 * properties.len());
+        for (key, value) in properties {
+            tags.push(self.key(key));
+            tags.push(self.value(value));
+        }
+
+        let mut feature = Vec::new();
+        if let Some(id) = id {
+            write_key(&mut feature, 1, VARINT);
+            write_varint(&mut feature, id);
+        }
+        if !tags.is_empty() {
+            write_packed(&mut feature, 2, &tags);
+        }
+        write_key(&mut feature, 3, VARINT);
+        write_varint(&mut feature, kind as u64);
+        write_packed(&mut feature, 4, &commands);
+
+        write_bytes(&mut self.features, 2, &feature);
+        self.feature_count += 1;
+    }
+
+    fn key(&mut self, key: &str) -> u32 {
+        if let Some(i) = self.key_index.get(key) {
+            return *i;
+        }
+        let i = self.keys.len() as u32;
+        self.keys.push(key.to_string());
+        self.key_index.insert(key.to_string(), i);
+        i
+    }
+
+    fn value(&mut self, value: &Value) -> u32 {
+        let mut encoded = Vec::new();
+        match value {
+            Value::String(s) => write_bytes(&mut encoded, 1, s.as_bytes()),
+            Value::Double(d) => {
+                write_key(&mut encoded, 3, FIXED64);
+                encoded.extend_from_slice(&d.to_le_bytes());
+            }
+            Value::Int(i) => {
+                write_key(&mut encoded, 6, VARINT);
+                write_varint(&mut encoded, zigzag64(*i));
+            }
+            Value::Bool(b) => {
+                write_key(&mut encoded, 7, VARINT);
+                write_varint(&mut encoded, *b as u64);
+            }
+        }
+        if let Some(i) = self.value_index.get(&encoded) {
+            return *i;
+        }
+        let i = self.values.len() as u32;
+        self.values.push(encoded.clone());
+        self.value_index.insert(encoded, i);
+        i
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let mut layer = Vec::with_capacity(self.features.len() + 64);
+        write_key(&mut layer, 15, VARINT);
+        write_varint(&mut layer, 2);
+        write_bytes(&mut layer, 1, self.name.as_bytes());
+        layer.extend_from_slice(&self.features);
+        for key in self.keys.iter() {
+            write_bytes(&mut layer, 3, key.as_bytes());
+        }
+        for value in self.values.iter() {
+            write_bytes(&mut layer, 4, value);
+        }
+        write_key(&mut layer, 5, VARINT);
+        write_varint(&mut layer, self.extent as u64);
+        write_bytes(out, 3, &layer);
+    }
+}
+
+/// Encodes the non-empty layers into a tile.
+pub fn encode_tile<'a>(layers: impl IntoIterator<Item = &'a Layer>) -> Vec<u8> {
+    let mut tile = Vec::new();
+    for layer in layers.into_iter().filter(|l| !l.is_empty()) {
+        layer.encode(&mut tile);
+    }
+    tile
+}
+
+/// The geometry type and command integers, leaving out repeated points.
+fn encode_geometry(geometry: &TileGeometry) -> Option<(u32, Vec<u32>)> {
+    let mut commands = Vec::new();
+    let mut cursor = [0, 0];
+    let mut push_point = |commands: &mut Vec<u32>, p: [i32; 2]| {
+        commands.push(zigzag32(p[0] - cursor[0]));
+        commands.push(zigzag32(p[1] - cursor[1]));
+        cursor = p;
+    };
+    match geometry {
+        TileGeometry::Points(points) => {
+            if points.is_empty() {
+                return None;
+            }
+            commands.push(command(MOVE_TO, points.len()));
+            for p in points {
+                push_point(&mut commands, *p);
+            }
+            Some((POINT, commands))
+        }
+        TileGeometry::Lines(lines) => {
+            for line in lines {
+                let mut line = line.clone();
+                line.dedup();
+                if line.len() < 2 {
+                    continue;
+                }
+                commands.push(command(MOVE_TO, 1));
+                push_point(&mut commands, line[0]);
+                commands.push(command(LINE_TO, line.len() - 1));
+                for p in &line[1..] {
+                    push_point(&mut commands, *p);
+                }
+            }
+            (!commands.is_empty()).then_some((LINE_STRING, commands))
+        }
+    }
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    (id & 0x7) | ((count as u32) << 3)
+}
+
+fn zigzag32(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+fn zigzag64(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+
+fn write_key(out: &mut Vec<u8>, field: u32, wire_type: u8) {
+    write_varint(out, ((field << 3) | wire_type as u32) as u64);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_key(out, field, LENGTH_DELIMITED);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_packed(out: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut packed = Vec::with_capacity(values.len());
+    for v in values {
+        write_varint(&mut packed, *v as u64);
+    }
+    write_bytes(out, field, &packed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zigzag() {
+        for (n, z) in [(0, 0), (-1, 1), (1, 2), (-2, 3), (2, 4)] {
+            assert_eq!(zigzag32(n), z);
+            assert_eq!(zigzag64(n as i64), z as u64);
+        }
+        assert_eq!(zigzag32(i32::MAX), u32::MAX - 1);
+        assert_eq!(zigzag32(i32::MIN), u32::MAX);
+        assert_eq!(zigzag64(i64::MIN), u64::MAX);
+    }
+
+    #[test]
+    fn varints() {
+        let mut out = Vec::new();
+        for n in [0, 1, 127, 128, 300] {
+            write_varint(&mut out, n);
+        }
+        assert_eq!(out, [0x00, 0x01, 0x7f, 0x80, 0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(command(MOVE_TO, 1), 9);
+        assert_eq!(command(LINE_TO, 3), 26);
+        assert_eq!(command(MOVE_TO, 2), 17);
+    }
+
+    // The geometries below are the examples of the Mapbox Vector Tile spec, 4.3.5.
+    #[test]
+    fn encodes_points() {
+        assert_eq!(
+            encode_geometry(&TileGeometry::Points(vec![[25, 17]])),
+            Some((POINT, vec![9, 50, 34]))
+        );
+        assert_eq!(
+            encode_geometry(&TileGeometry::Points(vec![[5, 7], [3, 2]])),
+            Some((POINT, vec![17, 10, 14, 3, 9]))
+        );
+        assert_eq!(encode_geometry(&TileGeometry::Points(vec![])), None);
+    }
+
+    #[test]
+    fn encodes_lines() {
+        let line = vec![[2, 2], [2, 10], [10, 10]];
+        assert_eq!(
+            encode_geometry(&TileGeometry::Lines(vec![line.clone()])),
+            Some((LINE_STRING, vec![9, 4, 4, 18, 0, 16, 16, 0]))
+        );
+        assert_eq!(
+            encode_geometry(&TileGeometry::Lines(vec![line, vec![[1, 1], [3, 5]]])),
+            Some((
+                LINE_STRING,
+                vec![9, 4, 4, 18, 0, 16, 16, 0, 9, 17, 17, 10, 4, 8]
+            ))
+        );
+    }
+
+    #[test]
+    fn drops_repeated_points_and_degenerate_lines() {
+        let lines = vec![
+            vec![[4, 4], [4, 4]],
+            vec![[2, 2], [2, 2], [2, 10], [10, 10]],
+        ];
+        assert_eq!(
+            encode_geometry(&TileGeometry::Lines(lines)),
+            Some((LINE_STRING, vec![9, 4, 4, 18, 0, 16, 16, 0]))
+        );
+        assert_eq!(
+            encode_geometry(&TileGeometry::Lines(vec![vec![[1, 1]]])),
+            None
+        );
+    }
+
+    #[test]
+    fn encodes_a_tile() {
+        let mut layer = Layer::new("l", 4096);
+        layer.add_feature(
+            Some(1),
+            &TileGeometry::Points(vec![[1, 1]]),
+            &[("a", Value::Bool(true))],
+        );
+        #[rustfmt::skip]
+        let expected = [
+            0x1a, 0x1e, // layer
+            0x78, 0x02, // version
+            0x0a, 0x01, b'l', // name
+            0x12, 0x0d, // feature
+            0x08, 0x01, // id
+            0x12, 0x02, 0x00, 0x00, // tags
+            0x18, 0x01, // type
+            0x22, 0x03, 0x09, 0x02, 0x02, // geometry
+            0x1a, 0x01, b'a', // keys
+            0x22, 0x02, 0x38, 0x01, // values
+            0x28, 0x80, 0x20, // extent
+        ];
+        assert_eq!(encode_tile([&layer]), expected);
+    }
+
+    #[test]
+    fn shares_keys_and_values() {
+        let mut layer = Layer::new("l", 4096);
+        let point = TileGeometry::Points(vec![[1, 1]]);
+        for name in ["a", "b", "a"] {
+            let properties = [
+                ("name", Value::String(name.into())),
+                ("oneway", Value::Bool(false)),
+            ];
+            layer.add_feature(None, &point, &properties);
+        }
+        layer.add_feature(None, &TileGeometry::Lines(vec![]), &[("x", Value::Int(-1))]);
+        assert_eq!(layer.keys, ["name", "oneway"]);
+        assert_eq!(layer.values.len(), 3);
+        assert_eq!(layer.feature_count, 3);
+    }
+
+    #[test]
+    fn skips_empty_layers() {
+        assert!(encode_tile([&Layer::new("l", 4096)]).is_empty());
+    }
+}
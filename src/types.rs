@@ -1 +1 @@
-pub mod medium;
\ No newline at end of file
+pub mod medium;
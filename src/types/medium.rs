@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use osmpbf::{DenseNode, Node, WayNodeLocation};
 use serde::{Deserialize, Serialize};
 
@@ -15,6 +17,61 @@ impl Position {
     pub fn from_osm_node(osm_node: &OsmNode) -> Position {
         Position { longitude: osm_node.longitude, latitude: osm_node.latitude }
     }
+
+    /// Builds a position from coordinates in decimicrodegrees (10⁻⁷).
+    pub fn from_decimicro(lon: i32, lat: i32) -> Position {
+        Position { longitude: 1e-7 * lon as f64, latitude: 1e-7 * lat as f64 }
+    }
+
+    /// Great-circle distance in meters.
+    pub fn haversine_distance(&self, other: &Position) -> f64 {
+        let d_lat = (other.latitude - self.latitude).to_radians();
+        let d_lon = (other.longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2)
+            + self.latitude.to_radians().cos() * other.latitude.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+
+    /// Closest point to `self` on the segment `a`-`b`, with its distance in meters and
+    /// its fraction along the segment. Uses an equirectangular projection around `self`,
+    /// which is accurate for the segment lengths found in OSM ways.
+    pub fn project_onto_segment(&self, a: &Position, b: &Position) -> (Position, f64, f64) {
+        let scale = self.latitude.to_radians().cos();
+        let (ax, ay) = ((a.longitude - self.longitude) * scale, a.latitude - self.latitude);
+        let (bx, by) = ((b.longitude - self.longitude) * scale, b.latitude - self.latitude);
+        let (dx, dy) = (bx - ax, by - ay);
+        let len_2 = dx * dx + dy * dy;
+        let t = if len_2 == 0.0 { 0.0 } else { (-(ax * dx + ay * dy) / len_2).clamp(0.0, 1.0) };
+        let closest = Position {
+            longitude: a.longitude + t * (b.longitude - a.longitude),
+            latitude: a.latitude + t * (b.latitude - a.latitude),
+        };
+        let distance = self.haversine_distance(&closest);
+        (closest, distance, t)
+    }
+}
+
+/// Mean earth radius in meters.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// An axis-aligned box in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    pub fn new(min_lon: f64, min_lat: f64, max_lon: f64, max_lat: f64) -> BoundingBox {
+        BoundingBox { min_lon, min_lat, max_lon, max_lat }
+    }
+
+    pub fn contains(&self, pos: &Position) -> bool {
+        pos.longitude >= self.min_lon && pos.longitude <= self.max_lon
+            && pos.latitude >= self.min_lat && pos.latitude <= self.max_lat
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -38,13 +95,27 @@ impl OsmNode {
 pub enum MediumType{
    Default,
    Highway (Vec<StreetCategory>),
-   Railway,
-   Waterway,
-   Airway,
-   SpaceTrajectory 
+   Railway (RailAttributes),
+   Waterway (WaterAttributes),
+   Airway (AirAttributes),
+   SpaceTrajectory (TrajectoryAttributes)
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl MediumType {
+    /// Lower-case name of the variant, without its attributes.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediumType::Default => "default",
+            MediumType::Highway(_) => "highway",
+            MediumType::Railway(_) => "railway",
+            MediumType::Waterway(_) => "waterway",
+            MediumType::Airway(_) => "airway",
+            MediumType::SpaceTrajectory(_) => "space_trajectory",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum StreetCategory {
     /// High capacity highways designed to safely carry fast motor traffic.
     Motorway,
@@ -92,10 +163,168 @@ pub enum StreetCategory {
     Default,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum RailCategory {
+    /// Full sized passenger or freight trains in the standard gauge for the country or state.
+    Rail,
+    /// A higher-standard tram system, normally in its own right-of-way.
+    LightRail,
+    /// A city passenger rail service running mostly grade separated.
+    Subway,
+    /// One or two carriage rail vehicles, usually sharing the road with other traffic.
+    Tram,
+    /// Narrow-gauge passenger or freight trains.
+    NarrowGauge,
+    /// A former railway whose tracks have been removed.
+    Abandoned,
+    /// A railway no longer in use whose tracks are still in place.
+    Disused,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RailAttributes {
+    pub category: RailCategory,
+    /// Track gauge in millimetres; the first one for dual gauge track.
+    pub gauge: Option<u32>,
+    /// Kind of electrification (contact_line, rail, no, ...).
+    pub electrified: Option<String>,
+    /// What the line is used for (main, branch, industrial, tourism, ...).
+    pub usage: Option<String>,
+    /// Minor track kind (yard, siding, spur, crossover).
+    pub service: Option<String>,
+    /// Stations and halts along the line.
+    pub stations: Vec<RailStation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum StationKind {
+    /// railway=station
+    Station,
+    /// railway=halt, a small station without switches.
+    Halt,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RailStation {
+    pub osm_id: i64,
+    pub name: Option<String>,
+    pub kind: StationKind,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum WaterCategory {
+    /// A large natural flowing waterway.
+    River,
+    /// An artificial waterway, usually for navigation or drainage.
+    Canal,
+    /// A naturally-formed waterway that is too narrow to be a river.
+    Stream,
+    /// A small artificial channel for drainage.
+    Ditch,
+    /// A ferry crossing, from route=ferry.
+    Ferry,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WaterAttributes {
+    pub category: WaterCategory,
+    /// Whether small boats may use the waterway (boat=yes/no/...).
+    pub boat: Option<String>,
+    /// Whether larger ships may use the waterway (ship=yes/no/...).
+    pub ship: Option<String>,
+    /// Width in meters.
+    pub width: Option<f64>,
+    /// Scheduled duration of a ferry crossing in seconds.
+    pub duration: Option<u32>,
+}
+
+impl WaterAttributes {
+    /// Whether any vessel is allowed: ferries always are, other waterways only when tagged.
+    pub fn is_navigable(&self) -> bool {
+        let allowed = |v: &Option<String>| matches!(v.as_deref(), Some(v) if v != "no" && v != "private");
+        self.category == WaterCategory::Ferry || allowed(&self.boat) || allowed(&self.ship)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum AeroCategory {
+    /// A strip where aircraft take off and land.
+    Runway,
+    /// A path connecting runways with aprons and hangars.
+    Taxiway,
+    /// A path within an apron leading to parking positions.
+    Taxilane,
+    /// An area where aircraft are parked, loaded or refuelled.
+    Apron,
+    /// Where a single aircraft parks.
+    ParkingPosition,
+    /// The outline of an airport.
+    Aerodrome,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AirAttributes {
+    pub category: AeroCategory,
+    /// The ref tag, e.g. "06/24" for a runway; the ICAO code for an aerodrome without one.
+    pub reference: Option<String>,
+    pub surface: Option<String>,
+    /// Length in meters, as tagged or else measured along the geometry.
+    pub length: Option<f64>,
+    /// OSM id of the aerodrome the Medium lies in.
+    pub aerodrome: Option<i64>,
+}
+
+/// Orbit data of a ground track; `altitudes` and `timestamps` run in step with
+/// the Medium's `medium_positions`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TrajectoryAttributes {
+    pub norad_id: u64,
+    /// International designator, e.g. "1998-067A".
+    pub international_designator: Option<String>,
+    /// Epoch of the element set, in seconds since the Unix epoch.
+    pub epoch: f64,
+    /// Height above the WGS 84 ellipsoid in kilometers.
+    pub altitudes: Vec<f64>,
+    /// Seconds since the Unix epoch.
+    pub timestamps: Vec<f64>,
+}
+
+/// Kind of OSM element a Medium was made from; way and relation ids overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OsmType {
+    Way,
+    Relation,
+}
+
+impl OsmType {
+    pub fn name(self) -> &'static str {
+        match self {
+            OsmType::Way => "way",
+            OsmType::Relation => "relation",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<OsmType> {
+        match name {
+            "way" => Some(OsmType::Way),
+            "relation" => Some(OsmType::Relation),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Medium {
     pub osm_id: Option<i64>,
+    /// What `osm_id` is the id of; `None` for Mediums not made from OSM data.
+    #[serde(default)]
+    pub osm_type: Option<OsmType>,
     pub medium_osm_name: Option<String>,
+    /// OSM tags kept by the tag mapping's `tags` list.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub osm_tags: BTreeMap<String, String>,
     pub medium_type: MediumType,
     pub is_one_way: bool,
     pub osm_node_refs: Vec<i64>,
@@ -106,13 +335,20 @@ impl Medium {
     pub fn new() -> Medium {
         Medium {
             osm_id: None,
+            osm_type: None,
             medium_osm_name: None, 
+            osm_tags: BTreeMap::new(),
             medium_type: MediumType::Default,
             is_one_way: false,
             osm_node_refs: Vec::new(),
             medium_positions: Vec::new() 
         }
     }
+
+    /// Length of the Medium's geometry in meters.
+    pub fn length(&self) -> f64 {
+        self.medium_positions.windows(2).map(|w| w[0].haversine_distance(&w[1])).sum()
+    }
 }
 
 impl Default for Medium {
@@ -0,0 +1,436 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use crate::{
+    airway::link_aerodromes,
+    error::{KovachsError, Result},
+    extract::{medium_from_way, relation_medium},
+    input::{
+        ChangeAction, OsmRelation, OsmWay, XmlElement, XmlNode, XmlReader, XmlRelation, XmlWay,
+    },
+    mapping::TagMapping,
+    railway::{link_stations, rail_station},
+    relation::assemble_relations,
+    resolve::MissingRefs,
+    tagfilter::TagFilter,
+    types::medium::{Medium, MediumType, OsmType, Position, RailStation},
+};
+
+/// The latest version of every element touched by one or more osmChange files;
+/// `None` for deleted ones.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeSet {
+    pub nodes: HashMap<i64, Option<XmlNode>>,
+    pub ways: HashMap<i64, Option<XmlWay>>,
+    pub relations: HashMap<i64, Option<XmlRelation>>,
+}
+
+impl ChangeSet {
+    /// Adds the changes in an `*.osc`, `*.osc.gz` or `*.osc.bz2` file. Read the
+    /// files oldest first, as later versions replace earlier ones.
+    pub fn read(&mut self, path: &Path) -> Result<()> {
+        for change in XmlReader::from_path(path)?.changes() {
+            let (action, element) = change?;
+            let keep = action != ChangeAction::Delete;
+            match element {
+                XmlElement::Node(n) => {
+                    self.nodes.insert(n.id, keep.then_some(n));
+                }
+                XmlElement::Way(w) => {
+                    self.ways.insert(w.id, keep.then_some(w));
+                }
+                XmlElement::Relation(r) => {
+                    self.relations.insert(r.id, keep.then_some(r));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Treats ways and relations whose tags no longer match `filter` as deleted,
+    /// and nodes that do not match as untagged, so they cannot become stations.
+    pub fn retain_matching(&mut self, filter: &TagFilter) {
+        for node in self.nodes.values_mut().flatten() {
+            if !filter.matches(node.tags.iter().map(|(k, v)| (k.as_str(), v.as_str()))) {
+                node.tags.clear();
+            }
+        }
+        for way in self.ways.values_mut() {
+            if way.as_ref().is_some_and(|w| !filter.matches(w.tags())) {
+                *way = None;
+            }
+        }
+        for relation in self.relations.values_mut() {
+            if relation.as_ref().is_some_and(|r| !filter.matches(r.tags())) {
+                *relation = None;
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.ways.is_empty() && self.relations.is_empty()
+    }
+}
+
+/// What [`apply_changes`] did to the dataset.
+#[derive(Debug, Clone, Default)]
+pub struct UpdateSummary {
+    pub created: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    /// Untouched Mediums whose positions were looked up again because some of
+    /// their nodes changed.
+    pub moved: usize,
+    /// Node refs of new or moved Mediums found neither in the changes nor in the
+    /// dataset.
+    pub missing: Vec<MissingRefs>,
+}
+
+/// Applies `changes` to a dataset written by `extract`.
+///
+/// Created and modified ways are classified again by `mapping` and replace the
+/// Medium with their id; ferry and aerodrome relations are joined again from the
+/// member ways in the dataset. Mediums using a moved node get its new position,
+/// and stations and aerodromes are linked again. New Mediums are only added when
+/// `keep` returns true for them, so pass the mapping and filter the dataset was
+/// extracted with.
+///
+/// Node locations come from the changes, or else from the Mediums using the node
+/// before the update, so a new way over nodes that no Medium uses ends up with
+/// missing refs. Relations are not joined again when only their member ways change.
+///
+/// Fails, leaving `mediums` as they were, when a Medium has an OSM id but no
+/// `osm_type`, as datasets written before it was recorded do; extract those again.
+pub fn apply_changes(
+    mediums: &mut Vec<Medium>,
+    changes: &ChangeSet,
+    mapping: &TagMapping,
+    keep: impl Fn(&Medium) -> bool,
+) -> Result<UpdateSummary> {
+    if let Some(m) = mediums
+        .iter()
+        .find(|m| m.osm_id.is_some() && m.osm_type.is_none())
+    {
+        return Err(KovachsError::InvalidData(format!(
+            "Medium {:?} does not say whether it is a way or a relation; extract the dataset again",
+            m.osm_id
+        )));
+    }
+    let mut summary = UpdateSummary::default();
+    let mut stations = take_stations(mediums);
+    for (id, node) in changes.nodes.iter() {
+        stations.remove(id);
+        if let Some(n) = node {
+            let position = Position::from_decimicro(n.decimicro_lon, n.decimicro_lat);
+            let tags = n.tags.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+            if let Some(station) = rail_station(n.id, position, tags) {
+                stations.insert(n.id, station);
+            }
+        }
+    }
+
+    let mut ways: Vec<&XmlWay> = changes.ways.values().flatten().collect();
+    ways.sort_unstable_by_key(|w| w.id);
+    let new_ways: Vec<Medium> = ways
+        .into_iter()
+        .map(|w| medium_from_way(w, mapping))
+        .collect();
+    let uses_changed_node = |m: &Medium| {
+        m.osm_node_refs
+            .iter()
+            .any(|r| changes.nodes.contains_key(r))
+    };
+
+    // Locations are looked up before the changed Mediums are dropped, as an osmChange
+    // leaves out the nodes of a way whose tags alone changed.
+    let needed: HashSet<i64> = new_ways
+        .iter()
+        .chain(mediums.iter().filter(|m| uses_changed_node(m)))
+        .flat_map(|m| m.osm_node_refs.iter().copied())
+        .filter(|r| !changes.nodes.contains_key(r))
+        .collect();
+    let known = known_locations(mediums, &needed);
+
+    // Mediums of changed elements are dropped and built again from the change.
+    let mut replaced = HashSet::new();
+    mediums.retain(|m| {
+        let (Some(osm_type), Some(id)) = (m.osm_type, m.osm_id) else {
+            return true;
+        };
+        let changed = match osm_type {
+            OsmType::Way => changes.ways.contains_key(&id),
+            OsmType::Relation => changes.relations.contains_key(&id),
+        };
+        if changed {
+            replaced.insert((osm_type, id));
+        }
+        !changed
+    });
+    let moved: Vec<usize> = mediums
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| uses_changed_node(m))
+        .map(|(i, _)| i)
+        .collect();
+
+    let locate = |r: i64| match changes.nodes.get(&r) {
+        Some(node) => node
+            .as_ref()
+            .map(|n| Position::from_decimicro(n.decimicro_lon, n.decimicro_lat)),
+        None => known.get(&r).cloned(),
+    };
+    for &i in moved.iter() {
+        summary
+            .missing
+            .extend(fill_positions(&mut mediums[i], locate));
+    }
+    summary.moved = moved.len();
+    for mut m in new_ways {
+        let missing = fill_positions(&mut m, locate);
+        if keep(&m) {
+            summary.missing.extend(missing);
+            count(&mut summary, &mut replaced, &m);
+            mediums.push(m);
+        }
+    }
+
+    let mut relations: Vec<&XmlRelation> = changes.relations.values().flatten().collect();
+    relations.sort_unstable_by_key(|r| r.id);
+    let relations = relations
+        .into_iter()
+        .filter_map(|r| relation_medium(r, mapping))
+        .collect();
+    let first_relation = mediums.len();
+    assemble_relations(mediums, relations);
+    let assembled = mediums.split_off(first_relation);
+    for m in assembled.into_iter().filter(|m| keep(m)) {
+        count(&mut summary, &mut replaced, &m);
+        mediums.push(m);
+    }
+    summary.deleted = replaced.len();
+
+    link_stations(mediums, stations.into_values().collect());
+    link_aerodromes(mediums);
+    Ok(summary)
+}
+
+/// Counts a new Medium as modified when it replaces one, and as created otherwise.
+/// Whatever is left in `replaced` at the end was deleted.
+fn count(summary: &mut UpdateSummary, replaced: &mut HashSet<(OsmType, i64)>, medium: &Medium) {
+    let replaces = match (medium.osm_type, medium.osm_id) {
+        (Some(osm_type), Some(id)) => replaced.remove(&(osm_type, id)),
+        _ => false,
+    };
+    if replaces {
+        summary.modified += 1;
+    } else {
+        summary.created += 1;
+    }
+}
+
+/// Removes every station from the railways, keyed by their node id.
+fn take_stations(mediums: &mut [Medium]) -> HashMap<i64, RailStation> {
+    let mut stations = HashMap::new();
+    for m in mediums.iter_mut() {
+        if let MediumType::Railway(rail) = &mut m.medium_type {
+            for s in rail.stations.drain(..) {
+                stations.insert(s.osm_id, s);
+            }
+        }
+    }
+    stations
+}
+
+/// Locations of the `needed` nodes, taken from Mediums that have a position for
+/// every ref.
+fn known_locations(mediums: &[Medium], needed: &HashSet<i64>) -> HashMap<i64, Position> {
+    let mut known = HashMap::with_capacity(needed.len());
+    for m in mediums
+        .iter()
+        .filter(|m| m.medium_positions.len() == m.osm_node_refs.len())
+    {
+        for (r, p) in m.osm_node_refs.iter().zip(m.medium_positions.iter()) {
+            if needed.contains(r) {
+                known.insert(*r, p.clone());
+            }
+        }
+    }
+    known
+}
+
+/// Fills `medium_positions` in ref order, returning the refs without a location.
+fn fill_positions(
+    medium: &mut Medium,
+    locate: impl Fn(i64) -> Option<Position>,
+) -> Option<MissingRefs> {
+    let mut positions = Vec::with_capacity(medium.osm_node_refs.len());
+    let mut missing = Vec::new();
+    for r in medium.osm_node_refs.iter() {
+        match locate(*r) {
+            Some(p) => positions.push(p),
+            None => missing.push(*r),
+        }
+    }
+    medium.medium_positions = positions;
+    (!missing.is_empty()).then_some(MissingRefs {
+        osm_id: medium.osm_id,
+        node_refs: missing,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::medium::StreetCategory;
+
+    fn position(id: i64) -> Position {
+        Position::from_decimicro(id as i32 * 1000, -id as i32 * 1000)
+    }
+
+    fn node(id: i64) -> XmlNode {
+        XmlNode {
+            id,
+            decimicro_lon: id as i32 * 1000,
+            decimicro_lat: -id as i32 * 1000,
+            tags: Vec::new(),
+        }
+    }
+
+    fn way(id: i64, refs: &[i64], highway: &str) -> XmlWay {
+        XmlWay {
+            id,
+            refs: refs.to_vec(),
+            tags: vec![("highway".into(), highway.into())],
+        }
+    }
+
+    fn medium(osm_type: OsmType, id: i64, refs: &[i64]) -> Medium {
+        let mut medium = Medium::new();
+        medium.osm_id = Some(id);
+        medium.osm_type = Some(osm_type);
+        medium.medium_type = MediumType::Highway(vec![StreetCategory::Residential]);
+        medium.osm_node_refs = refs.to_vec();
+        medium.medium_positions = refs.iter().map(|&r| position(r)).collect();
+        medium
+    }
+
+    /// Ways the mapping gave a category to, as `extract` keeps by default.
+    fn mapped(medium: &Medium) -> bool {
+        !matches!(&medium.medium_type, MediumType::Highway(c) if c.is_empty())
+    }
+
+    fn ids(mediums: &[Medium]) -> Vec<(Option<OsmType>, Option<i64>)> {
+        mediums.iter().map(|m| (m.osm_type, m.osm_id)).collect()
+    }
+
+    #[test]
+    fn counts_created_modified_and_deleted() {
+        let mut mediums = vec![
+            medium(OsmType::Way, 1, &[1, 2]),
+            medium(OsmType::Way, 2, &[2, 3]),
+            medium(OsmType::Way, 5, &[5, 1]),
+            medium(OsmType::Way, 7, &[3, 6]),
+            medium(OsmType::Relation, 20, &[]),
+        ];
+        let mut changes = ChangeSet::default();
+        changes.nodes.insert(4, Some(node(4)));
+        changes.nodes.insert(6, Some(node(60)));
+        // Modified.
+        changes.ways.insert(1, Some(way(1, &[1, 2], "primary")));
+        // Deleted, and no longer mapped.
+        changes.ways.insert(2, None);
+        changes
+            .ways
+            .insert(5, Some(way(5, &[5, 1], "construction")));
+        // Created, one of them over a node nobody knows.
+        changes.ways.insert(3, Some(way(3, &[3, 4], "service")));
+        changes.ways.insert(8, Some(way(8, &[4, 99], "track")));
+        // A new way that shares its id with a relation.
+        changes.ways.insert(20, Some(way(20, &[1, 4], "footway")));
+
+        let summary =
+            apply_changes(&mut mediums, &changes, &TagMapping::default(), mapped).unwrap();
+        assert_eq!(
+            (
+                summary.created,
+                summary.modified,
+                summary.deleted,
+                summary.moved
+            ),
+            (3, 1, 2, 1)
+        );
+        assert_eq!(summary.missing.len(), 1);
+        assert_eq!(summary.missing[0].osm_id, Some(8));
+        assert_eq!(summary.missing[0].node_refs, [99]);
+        assert_eq!(
+            ids(&mediums),
+            [
+                (Some(OsmType::Way), Some(7)),
+                (Some(OsmType::Relation), Some(20)),
+                (Some(OsmType::Way), Some(1)),
+                (Some(OsmType::Way), Some(3)),
+                (Some(OsmType::Way), Some(8)),
+                (Some(OsmType::Way), Some(20)),
+            ]
+        );
+        // Way 7 picked up the new location of node 6.
+        assert_eq!(
+            mediums[0].medium_positions[1].longitude,
+            position(60).longitude
+        );
+        assert!(matches!(
+            &mediums[2].medium_type,
+            MediumType::Highway(c) if c == &[StreetCategory::Primary]
+        ));
+        // Way 1 kept the locations of its old Medium, way 3 took one from way 7.
+        assert_eq!(mediums[2].medium_positions.len(), 2);
+        assert_eq!(
+            mediums[3].medium_positions[0].latitude,
+            position(3).latitude
+        );
+    }
+
+    #[test]
+    fn keeps_locations_of_a_way_whose_tags_changed() {
+        let mut mediums = vec![
+            medium(OsmType::Way, 1, &[1, 2, 3]),
+            medium(OsmType::Way, 2, &[4, 5]),
+        ];
+        let mut changes = ChangeSet::default();
+        changes.ways.insert(1, Some(way(1, &[1, 2, 3], "primary")));
+
+        let summary =
+            apply_changes(&mut mediums, &changes, &TagMapping::default(), mapped).unwrap();
+        assert_eq!(
+            (summary.created, summary.modified, summary.deleted),
+            (0, 1, 0)
+        );
+        assert!(summary.missing.is_empty());
+        let latitudes: Vec<f64> = mediums[1]
+            .medium_positions
+            .iter()
+            .map(|p| p.latitude)
+            .collect();
+        assert_eq!(
+            latitudes,
+            [
+                position(1).latitude,
+                position(2).latitude,
+                position(3).latitude
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_mediums_without_osm_type() {
+        let mut old = medium(OsmType::Way, 1, &[1, 2]);
+        old.osm_type = None;
+        let mut mediums = vec![old];
+        let mut changes = ChangeSet::default();
+        changes.ways.insert(1, None);
+        assert!(apply_changes(&mut mediums, &changes, &TagMapping::default(), mapped).is_err());
+        assert_eq!(mediums.len(), 1);
+    }
+}
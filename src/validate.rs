@@ -0,0 +1,126 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    index::MediumIndex,
+    types::medium::{Medium, MediumType, OsmType},
+};
+
+/// Something wrong with a Medium dataset. Mediums are referred to by their position
+/// in the dataset and, when they have one, their OSM id.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// A Medium without any positions.
+    Empty { medium: usize, osm_id: Option<i64> },
+    /// Positions and node refs are out of step, usually because nodes were missing
+    /// from the input.
+    MissingPositions {
+        medium: usize,
+        osm_id: Option<i64>,
+        refs: usize,
+        positions: usize,
+    },
+    /// A position outside -180..180 / -90..90 or not a number.
+    BadCoordinate {
+        medium: usize,
+        osm_id: Option<i64>,
+        position: usize,
+    },
+    /// Two Mediums made from the same OSM element. `kind` is the element type,
+    /// or the medium type for Mediums that do not record it.
+    DuplicateId { osm_id: i64, kind: &'static str },
+    /// A SpaceTrajectory's altitudes or timestamps are not in step with its positions.
+    TrajectoryMismatch { medium: usize },
+    /// The saved index was built from a different number of Mediums.
+    IndexMismatch { indexed: usize, mediums: usize },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Empty { medium, osm_id } => {
+                write!(f, "medium {medium} ({osm_id:?}) has no positions")
+            }
+            Problem::MissingPositions {
+                medium,
+                osm_id,
+                refs,
+                positions,
+            } => write!(
+                f,
+                "medium {medium} ({osm_id:?}) has {positions} positions for {refs} node refs"
+            ),
+            Problem::BadCoordinate {
+                medium,
+                osm_id,
+                position,
+            } => write!(
+                f,
+                "medium {medium} ({osm_id:?}) has an invalid coordinate at position {position}"
+            ),
+            Problem::DuplicateId { osm_id, kind } => {
+                write!(f, "osm id {osm_id} is used by more than one {kind} medium")
+            }
+            Problem::TrajectoryMismatch { medium } => write!(
+                f,
+                "space trajectory {medium} has altitudes or timestamps out of step with its positions"
+            ),
+            Problem::IndexMismatch { indexed, mediums } => write!(
+                f,
+                "index was built from {indexed} mediums but the dataset has {mediums}"
+            ),
+        }
+    }
+}
+
+/// Every problem found in `mediums`, and in `index` when one is given.
+pub fn validate(mediums: &[Medium], index: Option<&MediumIndex>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut seen: HashMap<(i64, &'static str), usize> = HashMap::new();
+    for (i, m) in mediums.iter().enumerate() {
+        let osm_id = m.osm_id;
+        if m.medium_positions.is_empty() {
+            problems.push(Problem::Empty { medium: i, osm_id });
+        } else if !m.osm_node_refs.is_empty() && m.osm_node_refs.len() != m.medium_positions.len() {
+            problems.push(Problem::MissingPositions {
+                medium: i,
+                osm_id,
+                refs: m.osm_node_refs.len(),
+                positions: m.medium_positions.len(),
+            });
+        }
+        if let Some(position) = m.medium_positions.iter().position(|p| {
+            !(-180.0..=180.0).contains(&p.longitude) || !(-90.0..=90.0).contains(&p.latitude)
+        }) {
+            problems.push(Problem::BadCoordinate {
+                medium: i,
+                osm_id,
+                position,
+            });
+        }
+        if let MediumType::SpaceTrajectory(t) = &m.medium_type {
+            let n = m.medium_positions.len();
+            if t.altitudes.len() != n || t.timestamps.len() != n {
+                problems.push(Problem::TrajectoryMismatch { medium: i });
+            }
+        }
+        if let Some(id) = osm_id {
+            let kind = m
+                .osm_type
+                .map_or_else(|| m.medium_type.name(), OsmType::name);
+            let count = seen.entry((id, kind)).or_insert(0);
+            *count += 1;
+            if *count == 2 {
+                problems.push(Problem::DuplicateId { osm_id: id, kind });
+            }
+        }
+    }
+    if let Some(index) = index {
+        if index.medium_count() != mediums.len() {
+            problems.push(Problem::IndexMismatch {
+                indexed: index.medium_count(),
+                mediums: mediums.len(),
+            });
+        }
+    }
+    problems
+}
@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    input::{MemberType, OsmRelation},
+    relation::RelationMedium,
+    types::medium::{MediumType, WaterAttributes},
+};
+
+/// Width in meters from values like "12", "12.5 m" or "3 km"; other units are ignored.
+pub(crate) fn parse_width(v: &str) -> Option<f64> {
+    let v = v.trim();
+    let (number, factor) = if let Some(n) = v.strip_suffix("km") {
+        (n, 1000.0)
+    } else if let Some(n) = v.strip_suffix('m') {
+        (n, 1.0)
+    } else {
+        (v, 1.0)
+    };
+    number.trim().parse::<f64>().ok().map(|w| w * factor)
+}
+
+/// Duration in seconds from "mm", "hh:mm" or "hh:mm:ss".
+pub(crate) fn parse_duration(v: &str) -> Option<u32> {
+    let parts: Vec<u32> = v
+        .trim()
+        .split(':')
+        .map(|p| p.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [m] => Some(m * 60),
+        [h, m] => Some(h * 3600 + m * 60),
+        [h, m, s] => Some(h * 3600 + m * 60 + s),
+        _ => None,
+    }
+}
+
+/// A ferry route relation with the attributes the tag mapping gave it, to be joined
+/// from its member ways.
+pub fn ferry_route(relation: &impl OsmRelation, attributes: WaterAttributes) -> RelationMedium {
+    let name = relation
+        .tags()
+        .find(|(k, _)| *k == "name")
+        .map(|(_, v)| String::from(v));
+    let ways = relation
+        .members()
+        .filter(|m| m.member_type == MemberType::Way)
+        .map(|m| m.member_id)
+        .collect();
+    RelationMedium {
+        osm_id: relation.id(),
+        name,
+        medium_type: MediumType::Waterway(attributes),
+        ways,
+        tags: BTreeMap::new(),
+    }
+}
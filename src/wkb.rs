@@ -0,0 +1,275 @@
+use crate::{
+    error::{KovachsError, Result},
+    types::medium::Position,
+};
+
+const POINT: u32 = 1;
+const LINE_STRING: u32 = 2;
+const POLYGON: u32 = 3;
+const MULTI_LINE_STRING: u32 = 5;
+/// EWKB flag marking that an SRID follows the geometry type.
+const EWKB_SRID_FLAG: u32 = 0x2000_0000;
+
+/// Little-endian WKB for a Medium's positions: a Point for a single position, a
+/// LineString otherwise, and `None` when there are no positions.
+pub fn encode(positions: &[Position]) -> Option<Vec<u8>> {
+    encode_with_srid(positions, None)
+}
+
+/// Like [`encode`], but as PostGIS EWKB carrying `srid` when one is given.
+pub fn encode_with_srid(positions: &[Position], srid: Option<u32>) -> Option<Vec<u8>> {
+    let (kind, header) = match positions.len() {
+        0 => return None,
+        1 => (POINT, 0),
+        n => (LINE_STRING, n),
+    };
+    let mut wkb = Vec::with_capacity(13 + 16 * positions.len());
+    wkb.push(1);
+    match srid {
+        Some(srid) => {
+            wkb.extend_from_slice(&(kind | EWKB_SRID_FLAG).to_le_bytes());
+            wkb.extend_from_slice(&srid.to_le_bytes());
+        }
+        None => wkb.extend_from_slice(&kind.to_le_bytes()),
+    }
+    if kind == LINE_STRING {
+        wkb.extend_from_slice(&(header as u32).to_le_bytes());
+    }
+    for p in positions {
+        wkb.extend_from_slice(&p.longitude.to_le_bytes());
+        wkb.extend_from_slice(&p.latitude.to_le_bytes());
+    }
+    Some(wkb)
+}
+
+/// Positions of a WKB or EWKB Point, LineString or MultiLineString, or of a
+/// Polygon's outer ring. Z and M values are dropped.
+pub fn decode(wkb: &[u8]) -> Result<Vec<Position>> {
+    let mut reader = Reader { wkb, at: 0 };
+    let mut positions = Vec::new();
+    reader.geometry(&mut positions)?;
+    Ok(positions)
+}
+
+struct Reader<'a> {
+    wkb: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .wkb
+            .get(self.at..self.at + N)
+            .ok_or_else(|| invalid("truncated WKB"))?;
+        self.at += N;
+        let mut out = [0; N];
+        out.copy_from_slice(bytes);
+        Ok(out)
+    }
+
+    fn u32(&mut self, little: bool) -> Result<u32> {
+        let bytes = self.take::<4>()?;
+        Ok(if little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn f64(&mut self, little: bool) -> Result<f64> {
+        let bytes = self.take::<8>()?;
+        Ok(if little {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        })
+    }
+
+    fn geometry(&mut self, positions: &mut Vec<Position>) -> Result<()> {
+        let little = self.take::<1>()?[0] == 1;
+        let raw = self.u32(little)?;
+        if raw & EWKB_SRID_FLAG != 0 {
+            self.u32(little)?;
+        }
+        // Both the EWKB flags and the ISO 1000/2000/3000 offsets mark Z and M.
+        let (has_z, has_m) = (
+            raw & 0x8000_0000 != 0 || (raw & 0xffff) / 1000 % 2 == 1,
+            raw & 0x4000_0000 != 0 || (raw & 0xffff) / 1000 >= 2,
+        );
+        let dimensions = 2 + has_z as usize + has_m as usize;
+        match (raw & 0xffff) % 1000 {
+            POINT => self.points(1, dimensions, little, positions),
+            LINE_STRING => {
+                let n = self.u32(little)? as usize;
+                self.points(n, dimensions, little, positions)
+            }
+            POLYGON => {
+                let rings = self.u32(little)?;
+                for ring in 0..rings {
+                    let n = self.u32(little)? as usize;
+                    let mut points = Vec::new();
+                    self.points(n, dimensions, little, &mut points)?;
+                    if ring == 0 {
+                        positions.extend(points);
+                    }
+                }
+                Ok(())
+            }
+            MULTI_LINE_STRING => {
+                let lines = self.u32(little)?;
+                for _ in 0..lines {
+                    self.geometry(positions)?;
+                }
+                Ok(())
+            }
+            other => Err(invalid(format!("unsupported WKB geometry type {other}"))),
+        }
+    }
+
+    fn points(
+        &mut self,
+        n: usize,
+        dimensions: usize,
+        little: bool,
+        positions: &mut Vec<Position>,
+    ) -> Result<()> {
+        for _ in 0..n {
+            let longitude = self.f64(little)?;
+            let latitude = self.f64(little)?;
+            for _ in 2..dimensions {
+                self.f64(little)?;
+            }
+            positions.push(Position {
+                longitude,
+                latitude,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: impl Into<String>) -> KovachsError {
+    KovachsError::InvalidData(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(coordinates: &[(f64, f64)]) -> Vec<Position> {
+        coordinates
+            .iter()
+            .map(|&(longitude, latitude)| Position {
+                longitude,
+                latitude,
+            })
+            .collect()
+    }
+
+    fn coordinates(positions: &[Position]) -> Vec<(f64, f64)> {
+        positions
+            .iter()
+            .map(|p| (p.longitude, p.latitude))
+            .collect()
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn encodes_points_and_line_strings() {
+        assert_eq!(encode(&[]), None);
+        assert_eq!(
+            encode(&positions(&[(1.0, 2.0)])).unwrap(),
+            hex("0101000000000000000000f03f0000000000000040")
+        );
+        assert_eq!(
+            encode_with_srid(&positions(&[(1.0, 2.0)]), Some(4326)).unwrap(),
+            hex("0101000020e6100000000000000000f03f0000000000000040")
+        );
+        let line = encode(&positions(&[(1.0, 2.0), (3.0, 4.0)])).unwrap();
+        assert_eq!(&line[..9], &hex("010200000002000000")[..]);
+        assert_eq!(line.len(), 9 + 2 * 16);
+    }
+
+    #[test]
+    fn round_trips() {
+        let line = [(36.817, -1.286), (36.82, -1.29), (-0.5, 51.25)];
+        for coordinates_in in [&line[..1], &line[..]] {
+            let positions_in = positions(coordinates_in);
+            for srid in [None, Some(4326), Some(3857)] {
+                let wkb = encode_with_srid(&positions_in, srid).unwrap();
+                assert_eq!(coordinates(&decode(&wkb).unwrap()), coordinates_in);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_big_endian_and_z() {
+        // POINT(1 2), big-endian.
+        let point = hex("00000000013ff00000000000004000000000000000");
+        assert_eq!(coordinates(&decode(&point).unwrap()), [(1.0, 2.0)]);
+        // ISO LINESTRING Z (1 2 9, 3 4 9).
+        let mut line = hex("01ea03000002000000");
+        for value in [1.0f64, 2.0, 9.0, 3.0, 4.0, 9.0] {
+            line.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(
+            coordinates(&decode(&line).unwrap()),
+            [(1.0, 2.0), (3.0, 4.0)]
+        );
+        // EWKB POINT ZM with an SRID.
+        let mut point = hex("01010000e0e6100000");
+        for value in [5.0f64, 6.0, 7.0, 8.0] {
+            point.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(coordinates(&decode(&point).unwrap()), [(5.0, 6.0)]);
+    }
+
+    #[test]
+    fn decodes_polygons_and_multi_line_strings() {
+        let ring = |wkb: &mut Vec<u8>, coordinates: &[f64]| {
+            wkb.extend_from_slice(&(coordinates.len() as u32 / 2).to_le_bytes());
+            for value in coordinates {
+                wkb.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        let mut polygon = hex("010300000002000000");
+        ring(&mut polygon, &[0.0, 0.0, 4.0, 0.0, 0.0, 4.0, 0.0, 0.0]);
+        ring(&mut polygon, &[1.0, 1.0, 2.0, 1.0, 1.0, 2.0, 1.0, 1.0]);
+        assert_eq!(
+            coordinates(&decode(&polygon).unwrap()),
+            [(0.0, 0.0), (4.0, 0.0), (0.0, 4.0), (0.0, 0.0)]
+        );
+
+        let mut lines = hex("010500000002000000");
+        for line in [[1.0, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0]] {
+            let positions = positions(&[(line[0], line[1]), (line[2], line[3])]);
+            lines.extend(encode(&positions).unwrap());
+        }
+        assert_eq!(
+            coordinates(&decode(&lines).unwrap()),
+            [(1.0, 2.0), (3.0, 4.0), (5.0, 6.0), (7.0, 8.0)]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let line = encode(&positions(&[(1.0, 2.0), (3.0, 4.0)])).unwrap();
+        let error = decode(&line[..line.len() - 1]).unwrap_err();
+        assert!(error.to_string().contains("truncated WKB"), "{error}");
+        // GEOMETRYCOLLECTION
+        let error = decode(&hex("010700000000000000")).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("unsupported WKB geometry type 7"),
+            "{error}"
+        );
+    }
+}